@@ -1,4 +1,4 @@
-use super::{Config, ConfigContainer};
+use super::{Config, ConfigContainer, Overrides};
 use lazy_static;
 use lazy_static::lazy_static as compute_static;
 use rusqlite::{self, Connection};
@@ -7,10 +7,35 @@ use std::sync::Mutex;
 pub const TB_FAVOURATES_MUSIC: &str = "favourates_music";
 pub const TB_FAVOURATES_PLAYLIST: &str = "favourates_playlist";
 pub const TB_FAVOURATES_ARTIST: &str = "favourates_artist";
+pub const TB_HISTORY_MUSIC: &str = "history_music";
+pub const TB_HISTORY_PLAYLIST: &str = "history_playlist";
+pub const TB_HISTORY_ARTIST: &str = "history_artist";
+pub const TB_LOCAL_MUSIC: &str = "local_music";
+pub const TB_USER_PLAYLIST: &str = "user_playlist";
+pub const TB_USER_PLAYLIST_MUSIC: &str = "user_playlist_music";
+pub const TB_DOWNLOAD_HISTORY: &str = "download_history";
+pub const TB_DOWNLOADED_MUSIC: &str = "downloaded_music";
+pub const TB_DOWNLOADED_PLAYLIST: &str = "downloaded_playlist";
 
 compute_static! {
+    // Set by cli before anything else is initilized. See `Overrides`
+    pub static ref OVERRIDES: Mutex<Overrides> = Mutex::new(Overrides::default());
+
     pub static ref CONFIG: Config = {
-        match ConfigContainer::give_me_config() {
+        let overrides = OVERRIDES.lock().unwrap().clone();
+        let config_container = match &overrides.config_file {
+            // Config file given in command line must exists. Default is not offered in that case
+            Some(config_file) => match ConfigContainer::from_file(config_file) {
+                Some(config_container) => Some(config_container),
+                None => {
+                    eprintln!("A valid config is required for startup. Exiting..");
+                    std::process::exit(1);
+                }
+            },
+            None => ConfigContainer::give_me_config(),
+        };
+
+        let mut config = match config_container {
             Some(config_container) => config_container.config,
 
             None => {
@@ -27,7 +52,9 @@ compute_static! {
                     }
                 }
             }
-        }
+        };
+        overrides.apply(&mut config);
+        config
     };
 
     pub static ref STORAGE: Mutex<Connection> = {
@@ -12,6 +12,10 @@ pub const CONF_DIR_NAME: &str = "ytui_music";
 pub const CONFIG_FILE_NAME: &str = "config.json";
 pub const MPV_OPTION_FILE_NAME: &str = "mpv.conf";
 pub const SQLITE_DB_NAME: &str = "storage.db3";
+pub const LOG_FILE_NAME: &str = "ytui_music.log";
+pub const SERVERS_CACHE_FILE_NAME: &str = "discovered_servers.json";
+pub const SESSION_FILE_NAME: &str = "session.json";
+pub const SOCKET_FILE_NAME: &str = "ytui_music.sock";
 pub const AUDIO_DIR_VAR_KEY: &str = "YTUI_MUSIC_DIR";
 pub const YTUI_CONFIG_DIR_VAR_KEY: &str = "YTUI_CONFIG_DIR";
 
@@ -50,6 +54,7 @@ where
 type Color = (u8, u8, u8);
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(default)]
 pub struct ShortcutsKeys {
     pub toggle_play: char,
     pub next: char,
@@ -66,6 +71,13 @@ pub struct ShortcutsKeys {
     pub favourates_remove: char,
     pub vol_increase: char,
     pub vol_decrease: char,
+    pub playlist_edit: char,
+    pub move_up: char,
+    pub move_down: char,
+    pub play_next: char,
+    pub queue_append: char,
+    pub show_downloads: char,
+    pub sync_playlist: char,
 }
 
 impl Default for ShortcutsKeys {
@@ -134,6 +146,33 @@ impl Default for ShortcutsKeys {
 
             // Same as vol_increase but decrease the volume
             vol_decrease: '-',
+
+            // Create and edit your own playlist. Name of playlist is then typed in searchbar
+            // if pressed over musicbar, add the focused music to playlist of typed name. Playlist
+            // is created if there is none with that name
+            // if pressed over one of your playlist in playlistbar, rename that playlist
+            // if pressed over anything else in playlistbar, create new empty playlist
+            playlist_edit: 'e',
+
+            // Move the focused music up/down in your own playlist or in queue. Does nothing elsewhere
+            move_up: 'K',
+            move_down: 'J',
+
+            // Play the focused music right after current music instead of replacing what is playing
+            // if pressed over playlistbar whole content of playlist is played next
+            // if pressed over queue, move the focused music next to current music
+            play_next: 'o',
+
+            // Same as play_next but add music at the end of queue
+            queue_append: 'a',
+
+            // Show/hide the list of downloads. In this list, ENTER retry the failed download and
+            // favourates_remove key cancel the download or remove the finished one from list
+            show_downloads: 'D',
+
+            // Download only the music of focused playlist that are not downloaded yet.
+            // If pressed anywhere else than playlistbar, sync every playlist downloaded before
+            sync_playlist: 'y',
         }
     }
 }
@@ -206,6 +245,14 @@ pub struct Constants {
     // If it is intended to not use this feature then just set these string to some random characters
     // that you would probably never type in search query.
     pub search_by_type: [String; 3],
+
+    // Same as search_by_type but search the music in local library instead
+    #[serde(default = "default_search_local")]
+    pub search_local: String,
+}
+
+fn default_search_local() -> String {
+    String::from("local:")
 }
 
 impl Default for Constants {
@@ -223,13 +270,24 @@ impl Default for Constants {
                 String::from("playlist:"),
                 String::from("artist:"),
             ],
+            search_local: default_search_local(),
         }
     }
 }
 
 #[derive(Deserialize, Serialize, Debug, PartialEq)]
+#[serde(default)]
 pub struct Servers {
     pub list: Vec<String>,
+
+    // Location of instances document from which servers are discovered by
+    // `ytui_music servers refresh`. This may also be a path to local file
+    pub discovery_url: String,
+
+    // Discovered servers running invidious version older than this are ignored.
+    // Versions are compared as string so a date like "2022.08" is enough.
+    // Empty string accepts any version
+    pub min_version: String,
 }
 
 impl Default for Servers {
@@ -244,7 +302,29 @@ impl Default for Servers {
             }
         }
 
-        Servers { list }
+        Servers {
+            list,
+            discovery_url: String::from(
+                "https://api.invidious.io/instances.json?sort_by=type,health",
+            ),
+            min_version: String::new(),
+        }
+    }
+}
+
+impl Servers {
+    // Add the servers that are not already in list.
+    // Servers with and without trailing slash are considered same
+    pub fn merge(&mut self, servers: Vec<String>) {
+        for server in servers {
+            let is_new = !self
+                .list
+                .iter()
+                .any(|existing| existing.trim_end_matches('/') == server.trim_end_matches('/'));
+            if is_new {
+                self.list.push(server);
+            }
+        }
     }
 }
 
@@ -252,6 +332,166 @@ impl Default for Servers {
 pub struct Downloads {
     pub path: String,
     pub format: String,
+
+    // Directories other than `path` to look for music in local library.
+    // Download path is always part of local library
+    #[serde(default)]
+    pub library_roots: Vec<String>,
+
+    // Downloader to run. Anything that accepts same arguments as youtube-dl (eg: yt-dlp) works
+    // with default arguments. Others can be used by changing `arguments`
+    #[serde(default = "default_downloader")]
+    pub executable: String,
+
+    // Arguments passed to downloader. See DOWNLOAD_PLACEHOLDERS for what can be used in it
+    #[serde(default = "default_download_arguments")]
+    pub arguments: Vec<String>,
+
+    // Name of downloaded file relative to `path`. This becomes {output} in arguments.
+    // Besides placeholders, template of downloader itself (like %(title)s) can be used
+    #[serde(default = "default_output_template")]
+    pub output_template: String,
+
+    // Downloaded file is moved to this sub directory of `path`. See FOLDER_PLACEHOLDERS for what can
+    // be used in it. Directory of empty placeholder is skipped. Empty template keeps file in `path`
+    #[serde(default = "default_folder_template")]
+    pub folder_template: String,
+
+    // Write title, artist, album and cover art to downloaded file. Only mp3 files are tagged
+    #[serde(default = "default_write_tags")]
+    pub write_tags: bool,
+}
+
+// Replaced in `Downloads::folder_template` after download finishes
+// {artist}: artist of music. Creator of playlist if not known
+// {album}: name of playlist when it was downloaded as part of playlist
+pub const FOLDER_PLACEHOLDERS: [&str; 2] = ["{artist}", "{album}"];
+
+// Replaced in `Downloads::arguments` while running downloader
+// {url}: url of music or playlist to download
+// {format}: audio format from `Downloads::format`
+// {output}: `Downloads::output_template` joined with `Downloads::path`
+// {title}: title of music or name of playlist as shown in ytui-music
+pub const DOWNLOAD_PLACEHOLDERS: [&str; 4] = ["{url}", "{format}", "{output}", "{title}"];
+
+fn default_downloader() -> String {
+    String::from("youtube-dl")
+}
+
+fn default_download_arguments() -> Vec<String> {
+    [
+        "--newline",
+        "--extract-audio",
+        "--audio-format",
+        "{format}",
+        "--output",
+        "{output}",
+        "{url}",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .collect()
+}
+
+fn default_output_template() -> String {
+    String::from("%(title)s.%(ext)s")
+}
+
+fn default_folder_template() -> String {
+    String::from("{artist}/{album}")
+}
+
+fn default_write_tags() -> bool {
+    true
+}
+
+// Err with reason if `text` have any placeholder which is not in `available`
+fn check_placeholders(text: &str, available: &[&str]) -> Result<(), String> {
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let end = match rest[start..].find('}') {
+            Some(end) => start + end + 1,
+            None => return Err(format!("Unclosed placeholder in `{}`", text)),
+        };
+        let placeholder = &rest[start..end];
+        if !available.contains(&placeholder) {
+            return Err(format!(
+                "Unknown placeholder {} in `{}`. Available: {}",
+                placeholder,
+                text,
+                available.join(", ")
+            ));
+        }
+        rest = &rest[end..];
+    }
+
+    Ok(())
+}
+
+impl Downloads {
+    // Returns the reason if arguments or output template is not usable
+    pub fn validate(&self) -> Result<(), String> {
+        if self.executable.trim().is_empty() {
+            return Err("`Downloads{ executable }` cannot be empty".to_string());
+        }
+        if !self.arguments.iter().any(|arg| arg.contains("{url}")) {
+            return Err("`Downloads{ arguments }` must contain {url}".to_string());
+        }
+        if self.output_template.trim().is_empty() {
+            return Err("`Downloads{ output_template }` cannot be empty".to_string());
+        }
+
+        // Anything in braces is expected to be a placeholder. Catch the typo before downloading
+        for text in self.arguments.iter().chain([&self.output_template]) {
+            check_placeholders(text, &DOWNLOAD_PLACEHOLDERS)?;
+        }
+        check_placeholders(&self.folder_template, &FOLDER_PLACEHOLDERS)
+    }
+
+    // Directory inside `path` where file of this artist and album is to be kept
+    pub fn render_folder(&self, artist: &str, album: Option<&str>) -> path::PathBuf {
+        let mut folder = path::PathBuf::from(&self.path);
+        for component in self.folder_template.split('/') {
+            let component = component
+                .replace("{artist}", &artist.replace(['/', '\\'], "_"))
+                .replace(
+                    "{album}",
+                    &album.unwrap_or_default().replace(['/', '\\'], "_"),
+                );
+            let component = component.trim();
+            // Skip `.` and `..` too so that file is never moved outside of download path
+            if !component.is_empty() && component != "." && component != ".." {
+                folder.push(component);
+            }
+        }
+
+        folder
+    }
+
+    // Arguments to run downloader with, placeholders replaced
+    pub fn render_arguments(&self, url: &str, title: &str) -> Vec<String> {
+        // Title may contain path seperator which should not create a new directory
+        let file_title = title.replace(['/', '\\'], "_");
+        let output = self
+            .output_template
+            .replace("{url}", url)
+            .replace("{format}", &self.format)
+            .replace("{title}", &file_title);
+        let output = path::Path::new(&self.path)
+            .join(output)
+            .to_string_lossy()
+            .to_string();
+
+        self.arguments
+            .iter()
+            .map(|arg| {
+                arg.replace("{url}", url)
+                    .replace("{format}", &self.format)
+                    .replace("{output}", &output)
+                    .replace("{title}", title)
+            })
+            .collect()
+    }
 }
 
 impl Default for Downloads {
@@ -284,13 +524,44 @@ impl Default for Downloads {
         Downloads {
             path: audio_folder,
             format: "mp3".to_string(),
+            library_roots: Vec::new(),
+            executable: default_downloader(),
+            arguments: default_download_arguments(),
+            output_template: default_output_template(),
+            folder_template: default_folder_template(),
+            write_tags: default_write_tags(),
         }
     }
 }
 
+// How mpv is run to play music
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PlayerBackend {
+    // mpv linked into ytui_music itself
+    Libmpv,
+    // `MpvOptions::executable` is run and controlled over it's json ipc
+    Ipc,
+}
+
 #[derive(Serialize, Deserialize, Debug, PartialEq)]
 pub struct MpvOptions {
     config_path: String,
+
+    #[serde(default = "default_player_backend")]
+    pub backend: PlayerBackend,
+
+    // mpv binary run by `PlayerBackend::Ipc`. Looked up in $PATH if it is not a path
+    #[serde(default = "default_mpv_executable")]
+    pub executable: String,
+}
+
+fn default_player_backend() -> PlayerBackend {
+    PlayerBackend::Libmpv
+}
+
+fn default_mpv_executable() -> String {
+    String::from("mpv")
 }
 
 impl Default for MpvOptions {
@@ -301,6 +572,8 @@ impl Default for MpvOptions {
                 .as_path()
                 .to_string_lossy()
                 .to_string(),
+            backend: default_player_backend(),
+            executable: default_mpv_executable(),
         }
     }
 }
@@ -321,6 +594,32 @@ pub struct Config {
     pub download: Downloads,
 }
 
+// Values given in command line that override the config file for that run only.
+// Nothing here is ever written back to config file
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct Overrides {
+    // Read config from this file instead of the one in config directory
+    pub config_file: Option<path::PathBuf>,
+    pub region: Option<String>,
+    // Use only these servers instead of servers from config file and discovered ones
+    pub servers: Vec<String>,
+    // Do not load mpv.conf from config directory
+    pub no_mpv_config: bool,
+    // Write log to this file instead of the one in config directory
+    pub log_file: Option<path::PathBuf>,
+}
+
+impl Overrides {
+    pub fn apply(&self, config: &mut Config) {
+        if let Some(region) = &self.region {
+            config.constants.region = region.clone();
+        }
+        if !self.servers.is_empty() {
+            config.servers.list = self.servers.clone();
+        }
+    }
+}
+
 impl Config {
     pub fn get_string(&self) -> Option<String> {
         match serde_json::ser::to_string_pretty(self) {
@@ -428,6 +727,17 @@ impl ConfigContainer {
             eprintln!("Continue...")
         }
 
+        // Downloader is only run on demand. But it's better to know the mistake on startup
+        if let Err(reason) = config.download.validate() {
+            eprintln!("Config Error: {}", reason);
+            return None;
+        }
+
+        // Servers discovered by previous refresh are used along with servers from config file
+        if let Some(discovered) = Self::read_discovered_servers() {
+            config.servers.merge(discovered);
+        }
+
         // Most of invidious server do not expect this much of api calls.
         // So be sure we dont kill a single server instead distribute the load.
         // Here, it is achived by rearrenging the server list in random order
@@ -499,13 +809,88 @@ impl ConfigContainer {
             .ok() // convert to Option
     }
 
-    fn get_db_path() -> Option<path::PathBuf> {
+    pub fn get_db_path() -> Option<path::PathBuf> {
         let config_dir = Self::get_config_dir()?;
         let db_path = config_dir.join(SQLITE_DB_NAME);
 
         Some(db_path)
     }
 
+    pub fn get_servers_cache_path() -> Option<path::PathBuf> {
+        let config_dir = Self::get_config_dir()?;
+        let cache_path = config_dir.join(SERVERS_CACHE_FILE_NAME);
+
+        Some(cache_path)
+    }
+
+    // Replace the previously discovered servers with this list.
+    // Servers in config file itself are never touched
+    pub fn cache_discovered_servers(servers: &[String]) -> Option<()> {
+        let cache_path = Self::get_servers_cache_path()?;
+        let content = match serde_json::to_string_pretty(servers) {
+            Ok(val) => val,
+            Err(err) => {
+                eprintln!("Cannot encode discovered servers. Error: {}", err);
+                return None;
+            }
+        };
+
+        match std::fs::write(&cache_path, content) {
+            Ok(_) => Some(()),
+            Err(err) => {
+                eprintln!(
+                    "Cannot write discovered servers to {path}. Error: {err}",
+                    path = cache_path.to_string_lossy(),
+                    err = err
+                );
+                None
+            }
+        }
+    }
+
+    // None if servers were never discovered. Invalid cache is simply ignored as
+    // it can be regenerated anytime
+    fn read_discovered_servers() -> Option<Vec<String>> {
+        let cache_path = Self::get_servers_cache_path()?;
+        let file = File::open(cache_path).ok()?;
+
+        serde_json::from_reader(BufReader::new(file)).ok()
+    }
+
+    // Session is what was being played when user last quit. See ui::Session in front-end
+    pub fn get_session_path() -> Option<path::PathBuf> {
+        let config_dir = Self::get_config_dir()?;
+        let session_path = config_dir.join(SESSION_FILE_NAME);
+
+        Some(session_path)
+    }
+
+    // Unix socket where running ytui_music listen for control commands
+    pub fn get_socket_path() -> Option<path::PathBuf> {
+        let config_dir = Self::get_config_dir()?;
+        let socket_path = config_dir.join(SOCKET_FILE_NAME);
+
+        Some(socket_path)
+    }
+
+    // Unix socket of mpv run by `PlayerBackend::Ipc`. Every process gets it's own mpv
+    pub fn get_mpv_socket_path() -> Option<path::PathBuf> {
+        let config_dir = Self::get_config_dir()?;
+        let socket_path = config_dir.join(format!("mpv-{}.sock", std::process::id()));
+
+        Some(socket_path)
+    }
+
+    pub fn get_log_path() -> Option<path::PathBuf> {
+        if let Some(log_file) = &initilize::OVERRIDES.lock().unwrap().log_file {
+            return Some(log_file.clone());
+        }
+        let config_dir = Self::get_config_dir()?;
+        let log_path = config_dir.join(LOG_FILE_NAME);
+
+        Some(log_path)
+    }
+
     pub fn give_me_storage() -> Option<rusqlite::Connection> {
         let db_path = Self::get_db_path()?;
 
@@ -520,6 +905,19 @@ impl ConfigContainer {
             }
         };
 
+        if let Err(err) = Self::create_tables(&connection) {
+            eprintln!(
+                "Cannot initlize required table in newly created database. Error: {err}",
+                err = err
+            );
+            return None;
+        }
+
+        Some(connection)
+    }
+
+    // Create every table that is not there yet
+    pub fn create_tables(connection: &rusqlite::Connection) -> rusqlite::Result<()> {
         // All the types are are decleared as text.
         // The destination types fetcher::{MusicUnit, Playlistunit, ArtistUnit}
         // fiels are all decleared in string format. So on retriving with SELECT query
@@ -551,17 +949,136 @@ impl ConfigContainer {
             tb_artist = initilize::TB_FAVOURATES_ARTIST
         );
 
-        let res = connection.execute_batch(&create_favourates_table);
+        // History tables mirror the favourates table with two more columns.
+        // last_played: unix timestamp (in milliseconds) of when the item was last played.
+        // play_count: number of times the item was played. Incremented on every replay
+        // Recently played content is then served in descending order of last_played
+        let create_history_table = format!(
+            "
+                CREATE TABLE IF NOT EXISTS {tb_music} (
+                    id          TEXT    NOT NULL    PRIMARY KEY,
+                    title       TEXT    NOT NULL,
+                    author      TEXT    NOT NULL,
+                    duration    TEXT    NOT NULL,
+                    last_played INTEGER NOT NULL,
+                    play_count  INTEGER NOT NULL    DEFAULT 1
+                );
 
-        if let Err(err) = res {
-            eprintln!(
-                "Cannot initlize required table in newly created database. Error: {err}",
-                err = err
-            );
-            return None;
-        }
+                CREATE TABLE IF NOT EXISTS {tb_playlist} (
+                    id          TEXT    NOT NULL    PRIMARY KEY,
+                    name        TEXT    NOT NULL,
+                    author      TEXT    NOT NULL,
+                    count       TEXT    NOT NULL,
+                    last_played INTEGER NOT NULL,
+                    play_count  INTEGER NOT NULL    DEFAULT 1
+                );
 
-        Some(connection)
+                CREATE TABLE IF NOT EXISTS {tb_artist} (
+                    id          TEXT    NOT NULL    PRIMARY KEY,
+                    name        TEXT    NOT NULL,
+                    count       TEXT    NOT NULL,
+                    last_played INTEGER NOT NULL,
+                    play_count  INTEGER NOT NULL    DEFAULT 1
+                );
+           ",
+            tb_music = initilize::TB_HISTORY_MUSIC,
+            tb_playlist = initilize::TB_HISTORY_PLAYLIST,
+            tb_artist = initilize::TB_HISTORY_ARTIST
+        );
+
+        // Music files found in local library. Unlike other tables, id is the absolute path of file
+        // modified: unix timestamp of file modification time when it's tags were last read
+        let create_library_table = format!(
+            "
+                CREATE TABLE IF NOT EXISTS {tb_music} (
+                    id          TEXT    NOT NULL    PRIMARY KEY,
+                    title       TEXT    NOT NULL,
+                    author      TEXT    NOT NULL,
+                    duration    TEXT    NOT NULL,
+                    modified    INTEGER NOT NULL
+                );
+           ",
+            tb_music = initilize::TB_LOCAL_MUSIC
+        );
+
+        // Playlists created by user itself. Music of all such playlist are kept in single table
+        // position: order of music in playlist. Music with lower position is played first.
+        // Position are not guranteed to be continuous as music may be removed from in between
+        let create_user_playlist_table = format!(
+            "
+                CREATE TABLE IF NOT EXISTS {tb_playlist} (
+                    id      INTEGER NOT NULL    PRIMARY KEY AUTOINCREMENT,
+                    name    TEXT    NOT NULL    UNIQUE
+                );
+
+                CREATE TABLE IF NOT EXISTS {tb_music} (
+                    playlist_id INTEGER NOT NULL,
+                    position    INTEGER NOT NULL,
+                    id          TEXT    NOT NULL,
+                    title       TEXT    NOT NULL,
+                    author      TEXT    NOT NULL,
+                    duration    TEXT    NOT NULL,
+                    PRIMARY KEY (playlist_id, id)
+                );
+           ",
+            tb_playlist = initilize::TB_USER_PLAYLIST,
+            tb_music = initilize::TB_USER_PLAYLIST_MUSIC
+        );
+
+        // Downloads which have finished successfully. Downloading same url again only updates the row
+        // path: file written by downloader. Empty if downloader didn't tell
+        // finished_at: unix timestamp (in seconds) of when the download finished
+        let create_download_history_table = format!(
+            "
+                CREATE TABLE IF NOT EXISTS {tb_download} (
+                    url         TEXT    NOT NULL    PRIMARY KEY,
+                    title       TEXT    NOT NULL,
+                    path        TEXT    NOT NULL,
+                    finished_at INTEGER NOT NULL
+                );
+           ",
+            tb_download = initilize::TB_DOWNLOAD_HISTORY
+        );
+
+        // Index of downloaded music by youtube id so that same music is not downloaded again.
+        // Playlists are remembered so that new music of them can be downloaded on sync.
+        // synced_at: unix timestamp (in seconds) of when new music of playlist were last looked for
+        let create_downloads_index_table = format!(
+            "
+                CREATE TABLE IF NOT EXISTS {tb_music} (
+                    id              TEXT    NOT NULL    PRIMARY KEY,
+                    path            TEXT    NOT NULL,
+                    downloaded_at   INTEGER NOT NULL
+                );
+
+                CREATE TABLE IF NOT EXISTS {tb_playlist} (
+                    id          TEXT    NOT NULL    PRIMARY KEY,
+                    name        TEXT    NOT NULL,
+                    author      TEXT    NOT NULL,
+                    count       TEXT    NOT NULL,
+                    synced_at   INTEGER NOT NULL
+                );
+           ",
+            tb_music = initilize::TB_DOWNLOADED_MUSIC,
+            tb_playlist = initilize::TB_DOWNLOADED_PLAYLIST
+        );
+
+        connection
+            .execute_batch(&create_favourates_table)
+            .and_then(|_| connection.execute_batch(&create_history_table))
+            .and_then(|_| connection.execute_batch(&create_library_table))
+            .and_then(|_| connection.execute_batch(&create_user_playlist_table))
+            .and_then(|_| connection.execute_batch(&create_download_history_table))
+            .and_then(|_| connection.execute_batch(&create_downloads_index_table))
+    }
+
+    // Fresh in-memory database with every table of real storage. Tests of every crate use this
+    // instead of STORAGE. Other crates get it with `test-util` feature
+    #[cfg(any(test, feature = "test-util"))]
+    pub fn test_storage() -> rusqlite::Connection {
+        let connection = rusqlite::Connection::open_in_memory().unwrap();
+        Self::create_tables(&connection).unwrap();
+        connection
     }
 
     fn get_config_path() -> Option<path::PathBuf> {
@@ -571,10 +1088,42 @@ impl ConfigContainer {
         Some(config_path)
     }
 
+    // Files that make up the configuration. These are generated with default values when missing
+    pub fn get_config_files() -> Option<Vec<path::PathBuf>> {
+        let config_dir = Self::get_config_dir()?;
+
+        Some(vec![
+            config_dir.join(CONFIG_FILE_NAME),
+            config_dir.join(MPV_OPTION_FILE_NAME),
+        ])
+    }
+
+    // Copy the file to `<file_name>.<unix timestamp>.bak` in same directory.
+    // Returns the path of backup
+    pub fn backup_file(file_path: &path::Path) -> std::io::Result<path::PathBuf> {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let file_name = file_path
+            .file_name()
+            .unwrap_or_default()
+            .to_string_lossy()
+            .to_string();
+        let backup_path = file_path.with_file_name(format!("{}.{}.bak", file_name, timestamp));
+
+        std::fs::copy(file_path, &backup_path).map(|_| backup_path)
+    }
+
     fn default_config_to_file() -> Option<ConfigContainer> {
-        let config_container = ConfigContainer::default();
+        let mut config_container = ConfigContainer::default();
         config_container.flush();
 
+        // Merge only after flushing so that discovered servers are not written to config file
+        if let Some(discovered) = Self::read_discovered_servers() {
+            config_container.config.servers.merge(discovered);
+        }
+
         Some(config_container)
     }
 
@@ -634,6 +1183,115 @@ mod tests {
         eprintln!("Config path: {}", path.as_path().to_string_lossy());
     }
 
+    #[test]
+    fn validate_download_arguments() {
+        let mut download = Downloads {
+            path: "/music".to_string(),
+            format: "opus".to_string(),
+            library_roots: Vec::new(),
+            executable: "yt-dlp".to_string(),
+            arguments: default_download_arguments(),
+            output_template: "{title} [%(id)s].%(ext)s".to_string(),
+            folder_template: default_folder_template(),
+            write_tags: true,
+        };
+        assert_eq!(download.validate(), Ok(()));
+        assert_eq!(
+            download.render_folder("AC/DC", Some("Best of")),
+            path::PathBuf::from("/music/AC_DC/Best of")
+        );
+        assert_eq!(
+            download.render_folder("AC/DC", None),
+            path::PathBuf::from("/music/AC_DC")
+        );
+        assert_eq!(
+            download.render_arguments("https://youtu.be/abc", "AC/DC - Thunder"),
+            vec![
+                "--newline",
+                "--extract-audio",
+                "--audio-format",
+                "opus",
+                "--output",
+                "/music/AC_DC - Thunder [%(id)s].%(ext)s",
+                "https://youtu.be/abc",
+            ]
+        );
+
+        download.arguments = vec!["--output".to_string(), "{output}".to_string()];
+        assert!(download.validate().is_err());
+
+        download.arguments = vec!["{url}".to_string(), "{formt}".to_string()];
+        assert!(download.validate().unwrap_err().contains("{formt}"));
+
+        download.arguments = default_download_arguments();
+        download.folder_template = "{artist}/{title}".to_string();
+        assert!(download.validate().unwrap_err().contains("{title}"));
+    }
+
+    #[test]
+    fn apply_overrides() {
+        // Default download path is not important here. Do not depend on audio dir of system
+        std::env::set_var(AUDIO_DIR_VAR_KEY, std::env::temp_dir());
+        let mut config = Config::default();
+        let default_servers = config.servers.list.clone();
+
+        Overrides::default().apply(&mut config);
+        assert_eq!(config.constants.region, "NP");
+        assert_eq!(config.servers.list, default_servers);
+
+        let overrides = Overrides {
+            region: Some("US".to_string()),
+            servers: vec!["https://invidious.example".to_string()],
+            ..Default::default()
+        };
+        overrides.apply(&mut config);
+        assert_eq!(config.constants.region, "US");
+        assert_eq!(config.servers.list, vec!["https://invidious.example"]);
+    }
+
+    #[test]
+    fn player_backend_options() {
+        // Config written before backend could be chosen keeps using libmpv
+        let mpv: MpvOptions = serde_json::from_str(r#"{ "config_path": "/tmp" }"#).unwrap();
+        assert_eq!(mpv.backend, PlayerBackend::Libmpv);
+        assert_eq!(mpv.executable, "mpv");
+
+        let mpv: MpvOptions = serde_json::from_str(
+            r#"{ "config_path": "/tmp", "backend": "ipc", "executable": "/opt/mpv" }"#,
+        )
+        .unwrap();
+        assert_eq!(mpv.backend, PlayerBackend::Ipc);
+        assert_eq!(mpv.executable, "/opt/mpv");
+        assert!(
+            serde_json::from_str::<MpvOptions>(r#"{ "config_path": "", "backend": "vlc" }"#)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn backup_file_with_timestamp() {
+        let dir = std::env::temp_dir().join(format!("ytui_backup_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file_path = dir.join(CONFIG_FILE_NAME);
+        std::fs::write(&file_path, "{}").unwrap();
+
+        let backup_path = ConfigContainer::backup_file(&file_path).unwrap();
+        assert_eq!(backup_path.parent(), Some(dir.as_path()));
+        let backup_name = backup_path
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        assert!(backup_name.starts_with("config.json."));
+        assert!(backup_name.ends_with(".bak"));
+        assert_eq!(std::fs::read_to_string(&backup_path).unwrap(), "{}");
+        // Original file is left as it is
+        assert!(file_path.exists());
+
+        assert!(ConfigContainer::backup_file(&dir.join("missing.json")).is_err());
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
     #[test]
     fn inspect_server_list() {
         let path = get_test_config_path();
@@ -0,0 +1,152 @@
+use crate::FetchError;
+use config::initilize::CONFIG;
+use serde::{de::IgnoredAny, Deserialize};
+use std::time::Duration;
+
+// Each instance in the instances document (as served by https://api.invidious.io) is an array of
+// two element. First is the domain name and second is the detail of that instance.
+// [ "yewtu.be", { "api": true, "type": "https", "uri": "https://yewtu.be", "stats": { .. } } ]
+// Domain name is also present in uri so it is ignored
+type InstanceEntry = (IgnoredAny, InstanceDetail);
+
+#[derive(Deserialize)]
+struct InstanceDetail {
+    // Protocol via which this instance is reached. eg: https, onion, i2p
+    #[serde(rename = "type")]
+    instance_type: String,
+    uri: String,
+    // None when the instance is not yet monitored. So it is not known weather api is available
+    #[serde(default)]
+    api: Option<bool>,
+    #[serde(default)]
+    stats: Option<InstanceStats>,
+}
+
+#[derive(Deserialize)]
+struct InstanceStats {
+    software: InstanceSoftware,
+}
+
+#[derive(Deserialize)]
+struct InstanceSoftware {
+    name: String,
+    // Invidious versions are in form of <yyyy.mm.dd>-<commit> so comparing as string also
+    // compares the release date
+    version: String,
+}
+
+// Read the instances document from `source` and return the api url of every instance that
+// can be used by the fetcher.
+// `source` is a http(s) url. Anything else is treated as path to local file which is useful
+// for testing or when instances list is maintained manually
+// Instances are only used if:
+// - it is reachable over https
+// - it has api enabled
+// - it runs invidious of version >= `min_version`. Empty `min_version` accepts any version
+pub async fn discover_servers(source: &str, min_version: &str) -> Result<Vec<String>, FetchError> {
+    let document = if source.starts_with("https://") || source.starts_with("http://") {
+        let client = reqwest::ClientBuilder::default()
+            .user_agent(crate::utils::USER_AGENT)
+            .gzip(true)
+            .timeout(Duration::from_millis(
+                CONFIG.constants.server_time_out as u64,
+            ))
+            .build()
+            .unwrap();
+        let server = source.to_string();
+
+        let response = match client.get(source).send().await {
+            Ok(response) => response,
+            Err(cause) => return Err(FetchError::Network { server, cause }),
+        };
+        let status = response.status();
+        if !status.is_success() {
+            return Err(FetchError::Status { server, status });
+        }
+        match response.text().await {
+            Ok(body) => body,
+            Err(cause) => return Err(FetchError::Network { server, cause }),
+        }
+    } else {
+        let path = source.trim_start_matches("file://");
+        match tokio::fs::read_to_string(path).await {
+            Ok(content) => content,
+            Err(cause) => {
+                return Err(FetchError::File {
+                    path: path.to_string(),
+                    cause,
+                })
+            }
+        }
+    };
+
+    let instances: Vec<InstanceEntry> = match serde_json::from_str(&document) {
+        Ok(instances) => instances,
+        Err(cause) => {
+            return Err(FetchError::Parse {
+                server: source.to_string(),
+                cause,
+            })
+        }
+    };
+
+    Ok(filter_instances(instances, min_version))
+}
+
+fn filter_instances(instances: Vec<InstanceEntry>, min_version: &str) -> Vec<String> {
+    instances
+        .into_iter()
+        .map(|(_, detail)| detail)
+        .filter(|detail| detail.instance_type == "https" && detail.api == Some(true))
+        .filter(|detail| match &detail.stats {
+            Some(stats) => {
+                stats.software.name == "invidious" && stats.software.version.as_str() >= min_version
+            }
+            None => false,
+        })
+        .map(|detail| format!("{}/api/v1", detail.uri.trim_end_matches('/')))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn get_test_document_path() -> String {
+        format!(
+            "{}/src/test-data/invidious_instances.json",
+            env!("CARGO_MANIFEST_DIR")
+        )
+    }
+
+    #[tokio::test]
+    async fn discover_from_local_file() {
+        let servers = discover_servers(&get_test_document_path(), "")
+            .await
+            .unwrap();
+        assert_eq!(
+            servers,
+            vec![
+                "https://yewtu.be/api/v1",
+                "https://vid.puffyan.us/api/v1",
+                "https://inv.riverside.rocks/api/v1",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn discover_filters_old_version() {
+        let source = format!("file://{}", get_test_document_path());
+        let servers = discover_servers(&source, "2022.08").await.unwrap();
+        assert_eq!(
+            servers,
+            vec!["https://yewtu.be/api/v1", "https://vid.puffyan.us/api/v1"]
+        );
+    }
+
+    #[tokio::test]
+    async fn discover_reports_missing_file() {
+        let res = discover_servers("/non/existing/instances.json", "").await;
+        assert!(matches!(res, Err(FetchError::File { .. })));
+    }
+}
@@ -0,0 +1,436 @@
+use id3::TagLike;
+use std::path::Path;
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::process::Command;
+
+// How often caller is asked weather to continue when downloader prints nothing
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// File written by downloader. Playlist download writes many of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadedFile {
+    pub path: String,
+    // Youtube id of the music in this file. None if downloader didn't tell
+    pub video_id: Option<String>,
+}
+
+// What is written to tags of downloaded file. None means keep what downloader wrote
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    // Files as told by downloader. Empty if downloader never told
+    Finished(Vec<DownloadedFile>),
+    // Reason of failure. This is the last error line printed by downloader
+    Failed(String),
+    Cancelled,
+}
+
+// youtube-dl and yt-dlp prints progress (with --newline) as:
+// [download]  45.3% of 3.45MiB at 1.20MiB/s ETA 00:02
+pub fn parse_progress(line: &str) -> Option<f32> {
+    let rest = line.trim().strip_prefix("[download]")?;
+    let percent = rest.split_whitespace().next()?.strip_suffix('%')?;
+    percent.parse::<f32>().ok()
+}
+
+// Path of the file being written. Audio is extracted after download so the destination told by
+// [ExtractAudio] comes last and is the one to keep
+// [download] Destination: Artist - Title.webm
+// [ExtractAudio] Destination: Artist - Title.mp3
+// [download] Artist - Title.mp3 has already been downloaded
+pub fn parse_destination(line: &str) -> Option<String> {
+    let line = line.trim();
+    if let Some((_, path)) = line.split_once("Destination: ") {
+        return Some(path.to_string());
+    }
+    line.strip_prefix("[download] ")?
+        .strip_suffix(" has already been downloaded")
+        .map(|path| path.to_string())
+}
+
+// Before downloading each music, youtube-dl tells the id as:
+// [youtube] dQw4w9WgXcQ: Downloading webpage
+pub fn parse_video_id(line: &str) -> Option<String> {
+    let (id, _) = line.trim().strip_prefix("[youtube] ")?.split_once(": ")?;
+    crate::ids::is_video_id(id).then(|| id.to_string())
+}
+
+// Errors are printed to stderr as `ERROR: reason`
+pub fn parse_error(line: &str) -> Option<String> {
+    line.trim()
+        .strip_prefix("ERROR:")
+        .map(|reason| reason.trim().to_string())
+}
+
+// Command to download given url as configured in `Downloads` section of config
+pub fn build_command(download: &config::Downloads, url: &str, title: &str) -> Command {
+    let mut command = Command::new(&download.executable);
+    command
+        .args(download.render_arguments(url, title))
+        .current_dir(&download.path);
+
+    command
+}
+
+// Run the download command until it exits. `report` is called with Some(progress) whenever
+// downloader prints the progress and with None every POLL_INTERVAL. Download is cancelled
+// (downloader killed) as soon as report returns false
+pub async fn run_download<F>(mut command: Command, mut report: F) -> Outcome
+where
+    F: FnMut(Option<f32>) -> bool,
+{
+    command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true);
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(err) => return Outcome::Failed(format!("Cannot start downloader. {}", err)),
+    };
+
+    // stderr is only needed to know why it failed. So read it seperately till the end
+    let stderr = child.stderr.take().unwrap();
+    let error_reader = tokio::spawn(async move {
+        let mut lines = BufReader::new(stderr).lines();
+        let mut last_error = None;
+        while let Ok(Some(line)) = lines.next_line().await {
+            if let Some(reason) = parse_error(&line) {
+                last_error = Some(reason);
+            }
+        }
+        last_error
+    });
+
+    let mut lines = BufReader::new(child.stdout.take().unwrap()).lines();
+    let mut files: Vec<DownloadedFile> = Vec::new();
+    let mut video_id = None;
+    let mut poll = tokio::time::interval(POLL_INTERVAL);
+    loop {
+        tokio::select! {
+            line = lines.next_line() => match line {
+                Ok(Some(line)) => {
+                    if let Some(id) = parse_video_id(&line) {
+                        video_id = Some(id);
+                    }
+                    if let Some(path) = parse_destination(&line) {
+                        // Extracted audio replaces the downloaded file of same name
+                        let stem = Path::new(&path).with_extension("");
+                        match files.last_mut() {
+                            Some(file) if Path::new(&file.path).with_extension("") == stem => {
+                                file.path = path;
+                            }
+                            _ => files.push(DownloadedFile {
+                                path,
+                                video_id: video_id.clone(),
+                            }),
+                        }
+                    }
+                    let keep_going = match parse_progress(&line) {
+                        Some(progress) => report(Some(progress)),
+                        None => true,
+                    };
+                    if !keep_going {
+                        child.kill().await.ok();
+                        return Outcome::Cancelled;
+                    }
+                }
+                // stdout is closed. Downloader is exiting
+                _ => break,
+            },
+            _ = poll.tick() => {
+                if !report(None) {
+                    child.kill().await.ok();
+                    return Outcome::Cancelled;
+                }
+            }
+        }
+    }
+
+    let status = child.wait().await;
+    let last_error = error_reader.await.ok().flatten();
+    match status {
+        Ok(status) if status.success() => Outcome::Finished(files),
+        Ok(status) => Outcome::Failed(
+            last_error.unwrap_or_else(|| format!("Downloader exited with {}", status)),
+        ),
+        Err(err) => Outcome::Failed(err.to_string()),
+    }
+}
+
+// Only id3 tags are written. Other formats are moved without touching their tags
+pub fn can_write_tags(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("mp3"))
+}
+
+// Returns the artist in tag after writing
+fn write_tags(
+    path: &Path,
+    metadata: &Metadata,
+    cover: Option<Vec<u8>>,
+) -> id3::Result<Option<String>> {
+    let mut tag = id3::Tag::read_from_path(path).unwrap_or_default();
+    if let Some(title) = &metadata.title {
+        tag.set_title(title);
+    }
+    if let Some(artist) = &metadata.artist {
+        tag.set_artist(artist);
+    }
+    if let Some(album) = &metadata.album {
+        tag.set_album(album);
+    }
+    if let Some(cover) = cover {
+        tag.remove_picture_by_type(id3::frame::PictureType::CoverFront);
+        tag.add_frame(id3::frame::Picture {
+            mime_type: "image/jpeg".to_string(),
+            picture_type: id3::frame::PictureType::CoverFront,
+            description: String::new(),
+            data: cover,
+        });
+    }
+    tag.write_to_path(path, id3::Version::Id3v24)?;
+
+    Ok(tag.artist().map(String::from))
+}
+
+// Write the metadata and `cover` to downloaded file and move it to folder as in
+// `Downloads::folder_template`. See Fetcher::get_cover_art for the cover.
+// Returns the path of file after moving. Files are written in blocking thread
+pub async fn post_process(
+    download: &config::Downloads,
+    file: &DownloadedFile,
+    metadata: &Metadata,
+    cover: Option<Vec<u8>>,
+) -> Result<String, String> {
+    // Relative path is relative to where downloader was run
+    let path = Path::new(&download.path).join(&file.path);
+    if !path.is_file() {
+        return Err(format!("{} does not exists", path.to_string_lossy()));
+    }
+
+    let mut artist = metadata.artist.clone();
+    if download.write_tags && can_write_tags(&path) {
+        let (path, metadata) = (path.clone(), metadata.clone());
+        let written = tokio::task::spawn_blocking(move || write_tags(&path, &metadata, cover))
+            .await
+            .map_err(|err| err.to_string())?;
+        match written {
+            Ok(tag_artist) => artist = artist.or(tag_artist),
+            Err(err) => return Err(format!("Cannot write tags. {}", err)),
+        }
+    }
+
+    let folder = download.render_folder(
+        artist.as_deref().unwrap_or_default(),
+        metadata.album.as_deref(),
+    );
+    let target = folder.join(path.file_name().unwrap_or_default());
+    if target != path {
+        let (create, target) = (folder.clone(), target.clone());
+        tokio::task::spawn_blocking(move || {
+            std::fs::create_dir_all(&create).and_then(|_| std::fs::rename(&path, &target))
+        })
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| format!("Cannot move to {}. {}", folder.to_string_lossy(), err))?;
+    }
+
+    Ok(target.to_string_lossy().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Downloads section that runs the given shell script as downloader from a fresh directory
+    #[cfg(unix)]
+    fn fake_downloader(name: &str, script: &str) -> config::Downloads {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("ytui_fake_downloader_{}", name));
+        std::fs::create_dir_all(&dir).unwrap();
+        let executable = dir.join("downloader.sh");
+        std::fs::write(&executable, script).unwrap();
+        std::fs::set_permissions(&executable, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        config::Downloads {
+            path: dir.to_string_lossy().to_string(),
+            format: "mp3".to_string(),
+            library_roots: Vec::new(),
+            executable: executable.to_string_lossy().to_string(),
+            arguments: vec![
+                "{url}".to_string(),
+                "{format}".to_string(),
+                "{output}".to_string(),
+            ],
+            output_template: "{title}.{format}".to_string(),
+            folder_template: "{artist}/{album}".to_string(),
+            write_tags: true,
+        }
+    }
+
+    // Fake downloader echo what a real one would while writing the file it is asked to
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_fake_downloader() {
+        let download = fake_downloader(
+            "success",
+            r#"#!/bin/sh
+echo "[youtube] dQw4w9WgXcQ: Downloading webpage"
+echo "[download] Destination: ${3%.*}.webm"
+echo "[download]  10.0% of 1.00MiB at 1.00MiB/s ETA 00:01"
+echo "[download]  55.5% of 1.00MiB at 1.00MiB/s ETA 00:01"
+echo "[download] 100% of 1.00MiB in 00:01"
+echo "$2" > "$3"
+echo "[ExtractAudio] Destination: $3"
+"#,
+        );
+
+        let mut progress = Vec::new();
+        let command = build_command(&download, "https://youtu.be/abc", "Some title");
+        let outcome = run_download(command, |report| {
+            progress.extend(report);
+            true
+        })
+        .await;
+
+        let output = format!("{}/Some title.mp3", download.path);
+        assert_eq!(
+            outcome,
+            Outcome::Finished(vec![DownloadedFile {
+                path: output.clone(),
+                video_id: Some("dQw4w9WgXcQ".to_string()),
+            }])
+        );
+        assert_eq!(progress, vec![10.0, 55.5, 100.0]);
+        assert_eq!(std::fs::read_to_string(output).unwrap(), "mp3\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn fake_downloader_fails_or_cancelled() {
+        let download = fake_downloader(
+            "failure",
+            r#"#!/bin/sh
+echo "ERROR: Video unavailable" >&2
+exit 1
+"#,
+        );
+        let command = build_command(&download, "https://youtu.be/abc", "Some title");
+        let outcome = run_download(command, |_| true).await;
+        assert_eq!(outcome, Outcome::Failed("Video unavailable".to_string()));
+
+        let download = fake_downloader(
+            "cancel",
+            r#"#!/bin/sh
+echo "[download]   1.0% of 1.00MiB at 1.00KiB/s ETA 16:00"
+sleep 30
+"#,
+        );
+        let command = build_command(&download, "https://youtu.be/abc", "Some title");
+        let started = std::time::Instant::now();
+        let outcome = run_download(command, |report| report.is_none()).await;
+        assert_eq!(outcome, Outcome::Cancelled);
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn tag_and_move_downloaded_file() {
+        let download = fake_downloader("post_process", "#!/bin/sh\n");
+        let file_path = format!("{}/Some title.mp3", download.path);
+        std::fs::write(&file_path, b"not really an audio").unwrap();
+
+        let file = DownloadedFile {
+            path: file_path,
+            video_id: None,
+        };
+        let metadata = Metadata {
+            title: Some("Some title".to_string()),
+            artist: Some("Some/artist".to_string()),
+            album: Some("Some album".to_string()),
+        };
+        let moved_to = post_process(&download, &file, &metadata, Some(b"jpeg".to_vec()))
+            .await
+            .unwrap();
+        assert_eq!(
+            moved_to,
+            format!("{}/Some_artist/Some album/Some title.mp3", download.path)
+        );
+
+        let tag = id3::Tag::read_from_path(&moved_to).unwrap();
+        assert_eq!(tag.title(), Some("Some title"));
+        assert_eq!(tag.artist(), Some("Some/artist"));
+        assert_eq!(tag.album(), Some("Some album"));
+        assert_eq!(tag.pictures().next().unwrap().data, b"jpeg".to_vec());
+        std::fs::remove_dir_all(format!("{}/Some_artist", download.path)).unwrap();
+
+        // Tags of other formats are not written but file is still moved
+        let file = DownloadedFile {
+            path: format!("{}/Other title.opus", download.path),
+            video_id: None,
+        };
+        std::fs::write(&file.path, b"not really an audio").unwrap();
+        assert!(!can_write_tags(Path::new(&file.path)));
+        let moved_to = post_process(&download, &file, &metadata, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(&moved_to).unwrap(),
+            b"not really an audio".to_vec()
+        );
+        std::fs::remove_dir_all(format!("{}/Some_artist", download.path)).unwrap();
+    }
+
+    #[test]
+    fn parse_downloader_output() {
+        assert_eq!(
+            parse_progress("[download]  45.3% of 3.45MiB at 1.20MiB/s ETA 00:02"),
+            Some(45.3)
+        );
+        assert_eq!(
+            parse_progress("[download] 100% of 3.45MiB in 00:03"),
+            Some(100.0)
+        );
+        assert_eq!(
+            parse_progress("[youtube] dQw4w9WgXcQ: Downloading webpage"),
+            None
+        );
+        assert_eq!(parse_progress("[download] Destination: a.webm"), None);
+
+        assert_eq!(
+            parse_destination("[ExtractAudio] Destination: Artist - Title.mp3"),
+            Some("Artist - Title.mp3".to_string())
+        );
+        assert_eq!(
+            parse_destination("[download] Artist - Title.mp3 has already been downloaded"),
+            Some("Artist - Title.mp3".to_string())
+        );
+        assert_eq!(parse_destination("[download]  45.3% of 3.45MiB"), None);
+
+        assert_eq!(
+            parse_video_id("[youtube] dQw4w9WgXcQ: Downloading webpage"),
+            Some("dQw4w9WgXcQ".to_string())
+        );
+        assert_eq!(
+            parse_video_id("[youtube:tab] PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG: Downloading"),
+            None
+        );
+
+        assert_eq!(
+            parse_error("ERROR: Video unavailable"),
+            Some("Video unavailable".to_string())
+        );
+        assert_eq!(parse_error("WARNING: falling back"), None);
+    }
+}
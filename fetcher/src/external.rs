@@ -0,0 +1,221 @@
+use crate::favourates::Favourates;
+use crate::{ids, ArtistUnit, PlaylistUnit};
+use serde::Deserialize;
+
+// Reading subscriptions and playlists exported by other apps so that they can be imported
+// as favourate artists and playlists. Count and author are not part of these exports so they
+// are left as unknown
+
+// Any of the csv from youtube takeout. Following are understood:
+// - subscriptions.csv with `Channel Id,Channel Url,Channel Title`
+// - playlists.csv with `Playlist ID,...,Playlist Title (Original),...`
+// - <playlist name>.csv of older takeout where playlist detail is followed by it's videos
+// Every other row is ignored
+pub fn from_takeout_csv(content: &str) -> Result<Favourates, csv::Error> {
+    enum Section {
+        Channel { id: usize, title: usize },
+        Playlist { id: usize, title: usize },
+        Other,
+    }
+
+    let mut favourates = Favourates::default();
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        // Takeout csv starts with byte order mark
+        .from_reader(content.trim_start_matches('\u{feff}').as_bytes());
+    let mut section = Section::Other;
+
+    for record in reader.records() {
+        let record = record?;
+        let column = |names: &[&str]| {
+            record
+                .iter()
+                .position(|field| names.contains(&field.to_ascii_lowercase().as_str()))
+        };
+
+        // Header row starts a new section. Playlist detail also has channel id so check it first
+        if let Some(id) = column(&["playlist id"]) {
+            section = match column(&["playlist title (original)", "title"]) {
+                Some(title) => Section::Playlist { id, title },
+                None => Section::Other,
+            };
+            continue;
+        }
+        if let Some(id) = column(&["channel id"]) {
+            section = match column(&["channel title"]) {
+                Some(title) => Section::Channel { id, title },
+                None => Section::Other,
+            };
+            continue;
+        }
+        if column(&["video id"]).is_some() {
+            section = Section::Other;
+            continue;
+        }
+
+        let field = |index: usize| record.get(index).unwrap_or_default().to_string();
+        match section {
+            Section::Channel { id, title } if !field(id).is_empty() => {
+                favourates.artist.push(ArtistUnit {
+                    id: field(id),
+                    name: field(title),
+                    video_count: String::from("-"),
+                });
+            }
+            Section::Playlist { id, title } if !field(id).is_empty() => {
+                favourates.playlist.push(PlaylistUnit {
+                    id: field(id),
+                    name: field(title),
+                    author: String::new(),
+                    video_count: String::from("-"),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(favourates)
+}
+
+// Guess if content is csv from youtube takeout by it's header. Useful when there is no file
+// name to look at. eg: when read from stdin
+pub fn is_takeout_csv(content: &str) -> bool {
+    let header = content
+        .trim_start_matches('\u{feff}')
+        .trim_start()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    header.starts_with("channel id") || header.starts_with("playlist id")
+}
+
+#[derive(Deserialize)]
+struct NewPipeSubscriptions {
+    subscriptions: Vec<NewPipeSubscription>,
+}
+
+#[derive(Deserialize)]
+struct NewPipeSubscription {
+    service_id: u32,
+    url: String,
+    name: String,
+}
+
+// Subscription export of newpipe. It looks like:
+// {"app_version": "..", "subscriptions": [{"service_id": 0, "url": "..", "name": ".."}]}
+// Service 0 is youtube. Subscriptions from other services are ignored
+pub fn from_newpipe_json(content: &str) -> Result<Favourates, serde_json::Error> {
+    let exported: NewPipeSubscriptions = serde_json::from_str(content)?;
+
+    let artist = exported
+        .subscriptions
+        .into_iter()
+        .filter(|subscription| subscription.service_id == 0)
+        .filter_map(|subscription| {
+            Some(ArtistUnit {
+                id: ids::channel_id_from_url(&subscription.url)?,
+                name: subscription.name,
+                video_count: String::from("-"),
+            })
+        })
+        .collect();
+
+    Ok(Favourates {
+        artist,
+        ..Default::default()
+    })
+}
+
+// Guess if json is newpipe export rather than the one from `ytui_music export`
+pub fn is_newpipe_json(content: &str) -> bool {
+    serde_json::from_str::<NewPipeSubscriptions>(content).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn artist(id: &str, name: &str) -> ArtistUnit {
+        ArtistUnit {
+            id: id.to_string(),
+            name: name.to_string(),
+            video_count: String::from("-"),
+        }
+    }
+
+    fn playlist(id: &str, name: &str) -> PlaylistUnit {
+        PlaylistUnit {
+            id: id.to_string(),
+            name: name.to_string(),
+            author: String::new(),
+            video_count: String::from("-"),
+        }
+    }
+
+    #[test]
+    fn takeout_csv() {
+        let subscriptions = "\u{feff}Channel Id,Channel Url,Channel Title
+UC1,http://www.youtube.com/channel/UC1,First Channel
+UC2,http://www.youtube.com/channel/UC2,\"Second, with comma\"
+
+";
+        assert_eq!(
+            from_takeout_csv(subscriptions).unwrap(),
+            Favourates {
+                artist: vec![
+                    artist("UC1", "First Channel"),
+                    artist("UC2", "Second, with comma")
+                ],
+                ..Default::default()
+            }
+        );
+
+        let playlists = "Playlist ID,Add new videos to top,Playlist Title (Original),Playlist Title (Original Language),Playlist Create Timestamp,Playlist Update Timestamp,Playlist Video Order,Playlist Visibility
+PL1,False,Road trip,,2021-01-01T00:00:00+00:00,2021-01-02T00:00:00+00:00,Manual,Public
+";
+        assert_eq!(
+            from_takeout_csv(playlists).unwrap().playlist,
+            vec![playlist("PL1", "Road trip")]
+        );
+
+        // Older takeout had one csv per playlist with it's videos listed after detail
+        let old_playlist =
+            "Playlist Id,Channel Id,Time Created,Time Updated,Title,Description,Visibility
+PL2,UC1,2019-01-01 00:00:00 UTC,2019-01-01 00:00:00 UTC,Old one,,Public
+
+Video Id,Time Added
+abc,2019-01-01 00:00:00 UTC
+";
+        assert!(is_takeout_csv(subscriptions));
+        assert!(is_takeout_csv(playlists));
+        assert!(is_takeout_csv(old_playlist));
+        assert!(!is_takeout_csv(r#"{"artist": []}"#));
+
+        let favourates = from_takeout_csv(old_playlist).unwrap();
+        assert_eq!(favourates.playlist, vec![playlist("PL2", "Old one")]);
+        assert!(favourates.artist.is_empty());
+        assert!(favourates.music.is_empty());
+    }
+
+    #[test]
+    fn newpipe_json() {
+        let exported = r#"{
+            "app_version": "0.24.1",
+            "app_version_int": 990,
+            "subscriptions": [
+                {"service_id": 0, "url": "https://www.youtube.com/channel/UC1", "name": "First"},
+                {"service_id": 1, "url": "https://soundcloud.com/someone", "name": "Other"},
+                {"service_id": 0, "url": "https://www.youtube.com/channel/UC2/videos", "name": "Second"}
+            ]
+        }"#;
+        assert!(is_newpipe_json(exported));
+        assert!(!is_newpipe_json(r#"{"music": [], "artist": []}"#));
+        assert_eq!(
+            from_newpipe_json(exported).unwrap().artist,
+            vec![artist("UC1", "First"), artist("UC2", "Second")]
+        );
+    }
+}
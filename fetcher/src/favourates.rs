@@ -0,0 +1,585 @@
+use crate::{ids, library, ArtistUnit, ExtendDuration, MusicUnit, PlaylistUnit};
+use config::initilize::{
+    STORAGE, TB_FAVOURATES_ARTIST, TB_FAVOURATES_MUSIC, TB_FAVOURATES_PLAYLIST,
+};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+
+// Adding and removing favourates is shared by the ui and the cli subcommands.
+// Reading them is done by `Fetcher::get_favourates_*` as it is paged like any other source.
+// Every function return the number of rows changed so that caller can tell if there was
+// anything to remove
+
+// Every favourates in single document. This is the json format of export and import
+#[derive(Serialize, Deserialize, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct Favourates {
+    pub music: Vec<MusicUnit>,
+    pub playlist: Vec<PlaylistUnit>,
+    pub artist: Vec<ArtistUnit>,
+}
+
+// What to do when imported item is already in favourates. Items are same if their id is same
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OnConflict {
+    Skip,
+    Replace,
+}
+
+impl Favourates {
+    // Keep only the first of items with same id. Same item may be listed more than once
+    // eg: when files are concatenated
+    pub fn dedup(&mut self) {
+        fn dedup_by_id<T>(items: &mut Vec<T>, id: fn(&T) -> &str) {
+            let mut seen = HashSet::new();
+            items.retain(|item| seen.insert(id(item).to_string()));
+        }
+        dedup_by_id(&mut self.music, |music| &music.id);
+        dedup_by_id(&mut self.playlist, |playlist| &playlist.id);
+        dedup_by_id(&mut self.artist, |artist| &artist.id);
+    }
+}
+
+#[derive(Default, Debug, PartialEq)]
+pub struct ImportReport {
+    pub added: usize,
+    pub replaced: usize,
+    pub skipped: usize,
+}
+
+pub fn add_music(music: &MusicUnit) -> rusqlite::Result<usize> {
+    insert_music(&STORAGE.lock().unwrap(), music)
+}
+
+pub fn add_playlist(playlist: &PlaylistUnit) -> rusqlite::Result<usize> {
+    insert_playlist(&STORAGE.lock().unwrap(), playlist)
+}
+
+pub fn add_artist(artist: &ArtistUnit) -> rusqlite::Result<usize> {
+    insert_artist(&STORAGE.lock().unwrap(), artist)
+}
+
+fn insert_music(conn: &Connection, music: &MusicUnit) -> rusqlite::Result<usize> {
+    let query = format!(
+        "
+        INSERT OR REPLACE INTO {tb_name}
+        (id, title, author, duration)
+        VALUES
+        (:id, :title, :author, :duration)
+    ",
+        tb_name = TB_FAVOURATES_MUSIC
+    );
+    let args = rusqlite::named_params! {
+        ":id": music.id,
+        ":title": music.name,
+        ":author": music.artist,
+        ":duration": music.duration,
+    };
+
+    conn.execute(&query, args)
+}
+
+fn insert_playlist(conn: &Connection, playlist: &PlaylistUnit) -> rusqlite::Result<usize> {
+    let query = format!(
+        "
+        INSERT OR REPLACE INTO {tb_name}
+        (id, name, author, count)
+        VALUES
+        (:id, :name, :author, :count)
+    ",
+        tb_name = TB_FAVOURATES_PLAYLIST
+    );
+    let args = rusqlite::named_params! {
+        ":id": playlist.id,
+        ":name": playlist.name,
+        ":author": playlist.author,
+        ":count": playlist.video_count,
+    };
+
+    conn.execute(&query, args)
+}
+
+fn insert_artist(conn: &Connection, artist: &ArtistUnit) -> rusqlite::Result<usize> {
+    let query = format!(
+        "
+        INSERT OR REPLACE INTO {tb_name}
+        (id, name, count)
+        VALUES
+        (:id, :name, :count)
+    ",
+        tb_name = TB_FAVOURATES_ARTIST
+    );
+    let args = rusqlite::named_params! {
+        ":id": artist.id,
+        ":name": artist.name,
+        ":count": artist.video_count,
+    };
+
+    conn.execute(&query, args)
+}
+
+fn remove(tb_name: &str, id: &str) -> rusqlite::Result<usize> {
+    let query = format!(
+        "
+        DELETE FROM {tb_name}
+        WHERE id = :id
+    ",
+        tb_name = tb_name
+    );
+
+    STORAGE.lock().unwrap().execute(&query, &[(":id", id)])
+}
+
+pub fn remove_music(music_id: &str) -> rusqlite::Result<usize> {
+    remove(TB_FAVOURATES_MUSIC, music_id)
+}
+
+pub fn remove_playlist(playlist_id: &str) -> rusqlite::Result<usize> {
+    remove(TB_FAVOURATES_PLAYLIST, playlist_id)
+}
+
+pub fn remove_artist(artist_id: &str) -> rusqlite::Result<usize> {
+    remove(TB_FAVOURATES_ARTIST, artist_id)
+}
+
+fn exists(conn: &Connection, tb_name: &str, id: &str) -> rusqlite::Result<bool> {
+    let query = format!(
+        "
+        SELECT EXISTS(SELECT 1 FROM {tb_name} WHERE id = :id)
+    ",
+        tb_name = tb_name
+    );
+
+    conn.query_row(&query, &[(":id", id)], |row| row.get(0))
+}
+
+pub fn read_all() -> rusqlite::Result<Favourates> {
+    let conn = STORAGE.lock().unwrap();
+
+    let query = format!(
+        "
+        SELECT id, title, author, duration
+        FROM {tb_name}
+    ",
+        tb_name = TB_FAVOURATES_MUSIC
+    );
+    let music = conn
+        .prepare(&query)?
+        .query_map([], |row| {
+            Ok(MusicUnit {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                artist: row.get(2)?,
+                duration: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let query = format!(
+        "
+        SELECT id, name, author, count
+        FROM {tb_name}
+    ",
+        tb_name = TB_FAVOURATES_PLAYLIST
+    );
+    let playlist = conn
+        .prepare(&query)?
+        .query_map([], |row| {
+            Ok(PlaylistUnit {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                author: row.get(2)?,
+                video_count: row.get(3)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    let query = format!(
+        "
+        SELECT id, name, count
+        FROM {tb_name}
+    ",
+        tb_name = TB_FAVOURATES_ARTIST
+    );
+    let artist = conn
+        .prepare(&query)?
+        .query_map([], |row| {
+            Ok(ArtistUnit {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                video_count: row.get(2)?,
+            })
+        })?
+        .collect::<rusqlite::Result<Vec<_>>>()?;
+
+    Ok(Favourates {
+        music,
+        playlist,
+        artist,
+    })
+}
+
+// Merge given favourates with the existing ones. Everything is imported or nothing is.
+// Item repeated in `favourates` is imported and counted only once. See `Favourates::dedup`
+pub fn import(
+    conn: &mut Connection,
+    mut favourates: Favourates,
+    on_conflict: OnConflict,
+) -> rusqlite::Result<ImportReport> {
+    favourates.dedup();
+    let tx = conn.transaction()?;
+    let mut report = ImportReport::default();
+
+    let mut import_one = |tb_name: &str,
+                          id: &str,
+                          insert: &dyn Fn() -> rusqlite::Result<usize>|
+     -> rusqlite::Result<()> {
+        let already_exists = exists(&tx, tb_name, id)?;
+        if already_exists && on_conflict == OnConflict::Skip {
+            report.skipped += 1;
+            return Ok(());
+        }
+        insert()?;
+        if already_exists {
+            report.replaced += 1;
+        } else {
+            report.added += 1;
+        }
+        Ok(())
+    };
+    for music in favourates.music.iter() {
+        import_one(TB_FAVOURATES_MUSIC, &music.id, &|| insert_music(&tx, music))?;
+    }
+    for playlist in favourates.playlist.iter() {
+        import_one(TB_FAVOURATES_PLAYLIST, &playlist.id, &|| {
+            insert_playlist(&tx, playlist)
+        })?;
+    }
+    for artist in favourates.artist.iter() {
+        import_one(TB_FAVOURATES_ARTIST, &artist.id, &|| {
+            insert_artist(&tx, artist)
+        })?;
+    }
+
+    tx.commit()?;
+    Ok(report)
+}
+
+// Split into items that are not in favourates yet and those that already are. Nothing is
+// changed so this can be used to preview an import. Repeated items are dropped like `import` does
+pub fn partition(
+    conn: &Connection,
+    mut favourates: Favourates,
+) -> rusqlite::Result<(Favourates, Favourates)> {
+    favourates.dedup();
+
+    fn split<T>(
+        conn: &Connection,
+        tb_name: &str,
+        items: Vec<T>,
+        id: fn(&T) -> &str,
+    ) -> rusqlite::Result<(Vec<T>, Vec<T>)> {
+        let mut new = Vec::new();
+        let mut existing = Vec::new();
+        for item in items {
+            if exists(conn, tb_name, id(&item))? {
+                existing.push(item);
+            } else {
+                new.push(item);
+            }
+        }
+        Ok((new, existing))
+    }
+
+    let (new_music, existing_music) =
+        split(conn, TB_FAVOURATES_MUSIC, favourates.music, |music| {
+            &music.id
+        })?;
+    let (new_playlist, existing_playlist) = split(
+        conn,
+        TB_FAVOURATES_PLAYLIST,
+        favourates.playlist,
+        |playlist| &playlist.id,
+    )?;
+    let (new_artist, existing_artist) =
+        split(conn, TB_FAVOURATES_ARTIST, favourates.artist, |artist| {
+            &artist.id
+        })?;
+
+    Ok((
+        Favourates {
+            music: new_music,
+            playlist: new_playlist,
+            artist: new_artist,
+        },
+        Favourates {
+            music: existing_music,
+            playlist: existing_playlist,
+            artist: existing_artist,
+        },
+    ))
+}
+
+// Duration is shown as "min:sec" or "hr:min:sec". None if it is not in that format
+fn duration_secs(duration: &str) -> Option<u64> {
+    duration.split(':').try_fold(0, |total: u64, component| {
+        component
+            .trim()
+            .parse::<u64>()
+            .ok()
+            .map(|num| total * 60 + num)
+    })
+}
+
+// Youtube music is written as it's url and local music as it's path so that m3u can also be
+// played by other players
+pub fn to_m3u(music: &[MusicUnit]) -> String {
+    let mut m3u = String::from("#EXTM3U\n");
+    for music in music {
+        // -1 is for unknown length
+        let seconds = duration_secs(&music.duration)
+            .map(|secs| secs as i64)
+            .unwrap_or(-1);
+        m3u.push_str(&format!(
+            "#EXTINF:{},{} - {}\n{}\n",
+            seconds,
+            music.artist,
+            music.name,
+            library::music_url(&music.id)
+        ));
+    }
+    m3u
+}
+
+// Read music from m3u. Relative path is taken relative to `base_dir` which should be the
+// directory of m3u file. Entries that are neither youtube url nor local file are ignored
+pub fn from_m3u(content: &str, base_dir: Option<&Path>) -> Vec<MusicUnit> {
+    let mut music_list = Vec::new();
+    // Length and display title from #EXTINF for the entry that follows it
+    let mut info: Option<(String, String)> = None;
+
+    for line in content.lines().map(str::trim) {
+        if let Some(extinf) = line.strip_prefix("#EXTINF:") {
+            info = extinf.split_once(',').map(|(seconds, title)| {
+                let duration = match seconds.trim().parse::<u64>() {
+                    Ok(secs) => Duration::from_secs(secs).to_string(),
+                    // -1 or anything else is unknown length
+                    Err(_) => String::from("-"),
+                };
+                (duration, title.trim().to_string())
+            });
+            continue;
+        }
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let info = info.take();
+        let mut music = if line.starts_with("http://") || line.starts_with("https://") {
+            match ids::video_id_from_url(line) {
+                Some(id) => MusicUnit {
+                    name: id.clone(),
+                    id,
+                    artist: String::new(),
+                    duration: String::from("-"),
+                },
+                None => continue,
+            }
+        } else {
+            let path = match base_dir {
+                Some(base_dir) => base_dir.join(line),
+                None => Path::new(line).to_path_buf(),
+            };
+            if !path.is_absolute() || !path.is_file() {
+                continue;
+            }
+            library::read_music(&path.to_string_lossy())
+        };
+
+        if let Some((duration, title)) = info {
+            let (artist, name) = match title.split_once(" - ") {
+                Some((artist, name)) => (artist.to_string(), name.to_string()),
+                None => (String::new(), title),
+            };
+            music.duration = duration;
+            music.artist = artist;
+            music.name = name;
+        }
+        music_list.push(music);
+    }
+
+    music_list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::ConfigContainer;
+
+    fn music(id: &str, name: &str, artist: &str, duration: &str) -> MusicUnit {
+        MusicUnit {
+            id: id.to_string(),
+            name: name.to_string(),
+            artist: artist.to_string(),
+            duration: duration.to_string(),
+        }
+    }
+
+    #[test]
+    fn favourates_json_round_trip() {
+        let favourates = Favourates {
+            music: vec![music("abc", "Song", "Band", "03:05")],
+            playlist: vec![PlaylistUnit {
+                id: "PLxyz".to_string(),
+                name: "Mix".to_string(),
+                author: "Someone".to_string(),
+                video_count: "12".to_string(),
+            }],
+            artist: vec![ArtistUnit {
+                id: "UCxyz".to_string(),
+                name: "Band".to_string(),
+                video_count: "40".to_string(),
+            }],
+        };
+        let json = serde_json::to_string(&favourates).unwrap();
+        assert_eq!(
+            serde_json::from_str::<Favourates>(&json).unwrap(),
+            favourates
+        );
+
+        // Response from server have numbers instead
+        let from_server: MusicUnit = serde_json::from_str(
+            r#"{"videoId": "abc", "title": "Song", "author": "Band", "lengthSeconds": 185}"#,
+        )
+        .unwrap();
+        assert_eq!(from_server, music("abc", "Song", "Band", "03:05"));
+
+        // Missing type is empty
+        let only_music: Favourates = serde_json::from_str(r#"{"music": []}"#).unwrap();
+        assert_eq!(only_music, Favourates::default());
+    }
+
+    fn playlist(id: &str, name: &str) -> PlaylistUnit {
+        PlaylistUnit {
+            id: id.to_string(),
+            name: name.to_string(),
+            author: String::from("Someone"),
+            video_count: String::from("1"),
+        }
+    }
+
+    fn music_names(conn: &Connection) -> Vec<String> {
+        let query = format!("SELECT title FROM {} ORDER BY id", TB_FAVOURATES_MUSIC);
+        let mut stmt = conn.prepare(&query).unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn import_on_conflict() {
+        let mut conn = ConfigContainer::test_storage();
+        insert_music(&conn, &music("a", "Old a", "Band", "01:00")).unwrap();
+        insert_playlist(&conn, &playlist("PLa", "Old mix")).unwrap();
+
+        let imported = || Favourates {
+            music: vec![
+                music("a", "New a", "Band", "01:00"),
+                music("b", "New b", "Band", "01:00"),
+                music("b", "Repeated b", "Band", "01:00"),
+            ],
+            playlist: vec![playlist("PLa", "New mix")],
+            artist: vec![],
+        };
+
+        // Dry run tells exactly what import does
+        let (new, existing) = partition(&conn, imported()).unwrap();
+        assert_eq!(new.music, vec![music("b", "New b", "Band", "01:00")]);
+        assert_eq!(existing.music.len() + existing.playlist.len(), 2);
+
+        let report = import(&mut conn, imported(), OnConflict::Skip).unwrap();
+        assert_eq!(
+            report,
+            ImportReport {
+                added: 1,
+                replaced: 0,
+                skipped: 2
+            }
+        );
+        assert_eq!(music_names(&conn), ["Old a", "New b"]);
+
+        let report = import(&mut conn, imported(), OnConflict::Replace).unwrap();
+        assert_eq!(
+            report,
+            ImportReport {
+                added: 0,
+                replaced: 3,
+                skipped: 0
+            }
+        );
+        assert_eq!(music_names(&conn), ["New a", "New b"]);
+        let playlist_name: String = conn
+            .query_row(
+                &format!("SELECT name FROM {}", TB_FAVOURATES_PLAYLIST),
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(playlist_name, "New mix");
+
+        let (new, existing) = partition(&conn, imported()).unwrap();
+        assert!(new.music.is_empty());
+        assert_eq!(existing.playlist.len(), 1);
+    }
+
+    #[test]
+    fn m3u_round_trip() {
+        let list = vec![
+            music("abc", "Song", "Band", "03:05"),
+            music("xyz", "Live", "Band - Live", "1:00:00"),
+            music("def", "Stream", "Someone", "-"),
+        ];
+        let m3u = to_m3u(&list);
+        assert!(m3u.starts_with(
+            "#EXTM3U\n#EXTINF:185,Band - Song\nhttps://www.youtube.com/watch?v=abc\n"
+        ));
+        assert!(m3u.contains("#EXTINF:-1,Someone - Stream\n"));
+
+        let read = from_m3u(&m3u, None);
+        assert_eq!(read.len(), 3);
+        assert_eq!(read[0], list[0]);
+        // Only first " - " separates artist from title
+        assert_eq!(read[1], music("xyz", "Live - Live", "Band", "1:00:00"));
+        assert_eq!(read[2], list[2]);
+    }
+
+    #[test]
+    fn m3u_from_other_players() {
+        let dir = std::env::temp_dir().join(format!("ytui_m3u_test_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("local song.mp3"), b"").unwrap();
+
+        let m3u = "#EXTM3U
+            #EXTINF:60,Local Artist - Local Song
+            local song.mp3
+            https://youtu.be/short?t=10
+            https://example.com/not-youtube.mp3
+            missing.mp3
+            #EXTINF:10,No Artist
+            https://www.youtube.com/watch?list=PL1&v=long
+        ";
+        let read = from_m3u(m3u, Some(&dir));
+        let local_path = dir.join("local song.mp3").to_string_lossy().to_string();
+        assert_eq!(
+            read,
+            vec![
+                music(&local_path, "Local Song", "Local Artist", "01:00"),
+                music("short", "short", "", "-"),
+                music("long", "No Artist", "", "00:10"),
+            ]
+        );
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}
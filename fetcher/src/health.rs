@@ -0,0 +1,154 @@
+use std::time::{Duration, Instant};
+
+// Time for which a server is not used after it fails for first time.
+// This is doubled for every consecutive failure of same server
+const BACKOFF_BASE: Duration = Duration::from_secs(5);
+// Cooldown never exceeds this time. So even a dead server is tried once in a while
+// as it may have come back alive
+const BACKOFF_MAX: Duration = Duration::from_secs(10 * 60);
+// A server is considered slow when its average latency is more than this many times
+// of the latency of fastest server available
+const SLOW_FACTOR: u32 = 3;
+
+#[derive(Default, Clone, Debug)]
+struct ServerHealth {
+    // Number of request failed in a row. Reset to 0 on first success
+    consecutive_failures: u32,
+    // None if server can be used right away
+    cooldown_until: Option<Instant>,
+    // Moving average of time taken to recive response from this server.
+    // None if no request have suceed yet
+    latency: Option<Duration>,
+}
+
+// Keeps the observed health of every server in same order as `Fetcher::servers`
+// so that the same index can be used in both
+#[derive(Debug)]
+pub struct HealthTracker {
+    servers: Vec<ServerHealth>,
+}
+
+impl HealthTracker {
+    pub fn new(server_count: usize) -> Self {
+        HealthTracker {
+            servers: vec![ServerHealth::default(); server_count],
+        }
+    }
+
+    pub fn record_success(&mut self, index: usize, latency: Duration) {
+        let health = &mut self.servers[index];
+        health.consecutive_failures = 0;
+        health.cooldown_until = None;
+        // Give more weight to previous observation so that a single slow
+        // response do not mark the server as slow
+        health.latency = Some(match health.latency {
+            Some(previous) => (previous * 3 + latency) / 4,
+            None => latency,
+        });
+    }
+
+    pub fn record_failure(&mut self, index: usize) {
+        let health = &mut self.servers[index];
+        health.consecutive_failures = health.consecutive_failures.saturating_add(1);
+
+        let exponent = std::cmp::min(health.consecutive_failures - 1, 16);
+        let cooldown = std::cmp::min(BACKOFF_BASE * 2u32.pow(exponent), BACKOFF_MAX);
+        health.cooldown_until = Some(Instant::now() + cooldown);
+    }
+
+    fn is_available(&self, index: usize, now: Instant) -> bool {
+        match self.servers[index].cooldown_until {
+            Some(until) => until <= now,
+            None => true,
+        }
+    }
+
+    // Returns the index of server to send next request to.
+    // Servers are still rotated clockwise from `current` so that load is distributed
+    // but servers that are cooling down or are much slower than the fastest one are skipped.
+    // Server whose latency is not yet known is always given a chance.
+    // If every server is cooling down then the one which will be available first is returned
+    pub fn next_server(&self, current: usize) -> usize {
+        let count = self.servers.len();
+        if count == 0 {
+            return 0;
+        }
+        let now = Instant::now();
+
+        let fastest = (0..count)
+            .filter(|index| self.is_available(*index, now))
+            .filter_map(|index| self.servers[index].latency)
+            .min();
+
+        for step in 1..=count {
+            let index = (current + step) % count;
+            if !self.is_available(index, now) {
+                continue;
+            }
+            match (self.servers[index].latency, fastest) {
+                (Some(latency), Some(fastest)) if latency > fastest * SLOW_FACTOR => continue,
+                _ => return index,
+            }
+        }
+
+        (0..count)
+            .min_by_key(|index| self.servers[*index].cooldown_until.unwrap_or(now))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotates_between_healthy_servers() {
+        let tracker = HealthTracker::new(3);
+        assert_eq!(tracker.next_server(0), 1);
+        assert_eq!(tracker.next_server(1), 2);
+        assert_eq!(tracker.next_server(2), 0);
+    }
+
+    #[test]
+    fn skips_failed_server_with_backoff() {
+        let mut tracker = HealthTracker::new(3);
+        tracker.record_failure(1);
+        assert_eq!(tracker.next_server(0), 2);
+
+        tracker.record_failure(1);
+        let cooldown = tracker.servers[1].cooldown_until.unwrap() - Instant::now();
+        assert!(cooldown > BACKOFF_BASE && cooldown <= BACKOFF_BASE * 2);
+
+        tracker.record_success(1, Duration::from_millis(100));
+        assert_eq!(tracker.next_server(0), 1);
+    }
+
+    #[test]
+    fn backoff_is_capped() {
+        let mut tracker = HealthTracker::new(1);
+        for _ in 0..100 {
+            tracker.record_failure(0);
+        }
+        let cooldown = tracker.servers[0].cooldown_until.unwrap() - Instant::now();
+        assert!(cooldown <= BACKOFF_MAX);
+    }
+
+    #[test]
+    fn skips_slow_server() {
+        let mut tracker = HealthTracker::new(3);
+        tracker.record_success(0, Duration::from_millis(100));
+        tracker.record_success(1, Duration::from_millis(1000));
+        tracker.record_success(2, Duration::from_millis(150));
+        assert_eq!(tracker.next_server(0), 2);
+        assert_eq!(tracker.next_server(2), 0);
+    }
+
+    #[test]
+    fn picks_earliest_available_when_all_failed() {
+        let mut tracker = HealthTracker::new(2);
+        tracker.record_failure(0);
+        tracker.record_failure(1);
+        tracker.record_failure(1);
+        assert_eq!(tracker.next_server(0), 0);
+    }
+}
@@ -0,0 +1,241 @@
+use crate::{ArtistUnit, MusicUnit, PlaylistUnit};
+use config::initilize::{STORAGE, TB_HISTORY_ARTIST, TB_HISTORY_MUSIC, TB_HISTORY_PLAYLIST};
+use rusqlite::Connection;
+
+// Recording what was played is done by the ui and reading it back by `Fetcher::get_recent_*`.
+// Replaying an item moves it to the top and increments it's play count.
+// Time is stored in milliseconds. Items played within same millisecond still keep their order
+// as every play is recorded at least a millisecond after the last one. See `last_played`
+
+// Unix timestamp of now in milliseconds or just after the most recent play of table if that is
+// not older than now
+fn last_played(tb_name: &str) -> String {
+    format!(
+        "MAX(
+            CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER),
+            IFNULL((SELECT MAX(last_played) FROM {tb_name}), 0) + 1
+        )",
+        tb_name = tb_name
+    )
+}
+
+pub fn add_music(music: &MusicUnit) -> rusqlite::Result<usize> {
+    insert_music(&STORAGE.lock().unwrap(), music)
+}
+
+pub fn add_playlist(playlist: &PlaylistUnit) -> rusqlite::Result<usize> {
+    insert_playlist(&STORAGE.lock().unwrap(), playlist)
+}
+
+pub fn add_artist(artist: &ArtistUnit) -> rusqlite::Result<usize> {
+    insert_artist(&STORAGE.lock().unwrap(), artist)
+}
+
+fn insert_music(conn: &Connection, music: &MusicUnit) -> rusqlite::Result<usize> {
+    let query = format!(
+        "
+        INSERT INTO {tb_name}
+        (id, title, author, duration, last_played, play_count)
+        VALUES
+        (:id, :title, :author, :duration, {now}, 1)
+        ON CONFLICT(id) DO UPDATE SET
+        last_played = excluded.last_played,
+        play_count = play_count + 1
+    ",
+        tb_name = TB_HISTORY_MUSIC,
+        now = last_played(TB_HISTORY_MUSIC)
+    );
+    let args = rusqlite::named_params! {
+        ":id": music.id,
+        ":title": music.name,
+        ":author": music.artist,
+        ":duration": music.duration,
+    };
+
+    conn.execute(&query, args)
+}
+
+fn insert_playlist(conn: &Connection, playlist: &PlaylistUnit) -> rusqlite::Result<usize> {
+    let query = format!(
+        "
+        INSERT INTO {tb_name}
+        (id, name, author, count, last_played, play_count)
+        VALUES
+        (:id, :name, :author, :count, {now}, 1)
+        ON CONFLICT(id) DO UPDATE SET
+        last_played = excluded.last_played,
+        play_count = play_count + 1
+    ",
+        tb_name = TB_HISTORY_PLAYLIST,
+        now = last_played(TB_HISTORY_PLAYLIST)
+    );
+    let args = rusqlite::named_params! {
+        ":id": playlist.id,
+        ":name": playlist.name,
+        ":author": playlist.author,
+        ":count": playlist.video_count,
+    };
+
+    conn.execute(&query, args)
+}
+
+fn insert_artist(conn: &Connection, artist: &ArtistUnit) -> rusqlite::Result<usize> {
+    let query = format!(
+        "
+        INSERT INTO {tb_name}
+        (id, name, count, last_played, play_count)
+        VALUES
+        (:id, :name, :count, {now}, 1)
+        ON CONFLICT(id) DO UPDATE SET
+        last_played = excluded.last_played,
+        play_count = play_count + 1
+    ",
+        tb_name = TB_HISTORY_ARTIST,
+        now = last_played(TB_HISTORY_ARTIST)
+    );
+    let args = rusqlite::named_params! {
+        ":id": artist.id,
+        ":name": artist.name,
+        ":count": artist.video_count,
+    };
+
+    conn.execute(&query, args)
+}
+
+// Select `columns` of at most `count` items starting from `from`. Most recently played comes first
+fn recent_query(tb_name: &str, columns: &str, from: usize, count: usize) -> String {
+    format!(
+        "
+        SELECT
+        {columns}
+        FROM {tb_name}
+        ORDER BY last_played DESC
+        LIMIT {from}, {count}
+    ",
+        columns = columns,
+        tb_name = tb_name,
+        from = from,
+        count = count
+    )
+}
+
+pub fn recent_music(
+    conn: &Connection,
+    from: usize,
+    count: usize,
+) -> rusqlite::Result<Vec<MusicUnit>> {
+    let query = recent_query(TB_HISTORY_MUSIC, "id, title, author, duration", from, count);
+    let mut stmt = conn.prepare(&query)?;
+    let results = stmt.query_map([], |row| {
+        Ok(MusicUnit {
+            id: row.get(0).unwrap_or_default(),
+            name: row.get(1).unwrap_or("SQL_ERROR".into()),
+            artist: row.get(2).unwrap_or("SQL_ERROR".into()),
+            duration: row.get(3).unwrap_or("3:0".into()),
+        })
+    })?;
+    results.collect()
+}
+
+pub fn recent_playlist(
+    conn: &Connection,
+    from: usize,
+    count: usize,
+) -> rusqlite::Result<Vec<PlaylistUnit>> {
+    let query = recent_query(TB_HISTORY_PLAYLIST, "id, name, author, count", from, count);
+    let mut stmt = conn.prepare(&query)?;
+    let results = stmt.query_map([], |row| {
+        Ok(PlaylistUnit {
+            id: row.get(0).unwrap_or_default(),
+            name: row.get(1).unwrap_or("SQL_ERROR".into()),
+            author: row.get(2).unwrap_or("SQL_ERROR".into()),
+            video_count: row.get(3).unwrap_or("NaN".into()),
+        })
+    })?;
+    results.collect()
+}
+
+pub fn recent_artist(
+    conn: &Connection,
+    from: usize,
+    count: usize,
+) -> rusqlite::Result<Vec<ArtistUnit>> {
+    let query = recent_query(TB_HISTORY_ARTIST, "id, name, count", from, count);
+    let mut stmt = conn.prepare(&query)?;
+    let results = stmt.query_map([], |row| {
+        Ok(ArtistUnit {
+            id: row.get(0).unwrap_or_default(),
+            name: row.get(1).unwrap_or("SQL_ERROR".into()),
+            video_count: row.get(2).unwrap_or("NaN".into()),
+        })
+    })?;
+    results.collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_music;
+    use config::ConfigContainer;
+
+    fn play_count(conn: &Connection, tb_name: &str, id: &str) -> i64 {
+        let query = format!("SELECT play_count FROM {} WHERE id = :id", tb_name);
+        conn.query_row(&query, &[(":id", id)], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn most_recent_first() {
+        let conn = ConfigContainer::test_storage();
+        // All of these are played within same second and likely within same millisecond
+        for id in ["a", "b", "c", "a"] {
+            insert_music(&conn, &test_music(id)).unwrap();
+        }
+        let recent: Vec<String> = recent_music(&conn, 0, 10)
+            .unwrap()
+            .into_iter()
+            .map(|music| music.id)
+            .collect();
+        assert_eq!(recent, ["a", "c", "b"]);
+        assert_eq!(play_count(&conn, TB_HISTORY_MUSIC, "a"), 2);
+        assert_eq!(play_count(&conn, TB_HISTORY_MUSIC, "b"), 1);
+
+        // Paged
+        let second_page = recent_music(&conn, 2, 2).unwrap();
+        assert_eq!(second_page, vec![test_music("b")]);
+        assert!(recent_music(&conn, 3, 2).unwrap().is_empty());
+    }
+
+    #[test]
+    fn playlist_and_artist_history() {
+        let conn = ConfigContainer::test_storage();
+        let playlist = |id: &str| PlaylistUnit {
+            id: id.to_string(),
+            name: String::from("Mix"),
+            author: String::from("Someone"),
+            video_count: String::from("10"),
+        };
+        let artist = |id: &str| ArtistUnit {
+            id: id.to_string(),
+            name: String::from("Band"),
+            video_count: String::from("40"),
+        };
+
+        for id in ["PL1", "PL2", "PL1"] {
+            insert_playlist(&conn, &playlist(id)).unwrap();
+        }
+        assert_eq!(
+            recent_playlist(&conn, 0, 10).unwrap(),
+            vec![playlist("PL1"), playlist("PL2")]
+        );
+        assert_eq!(play_count(&conn, TB_HISTORY_PLAYLIST, "PL1"), 2);
+
+        for id in ["UC1", "UC2"] {
+            insert_artist(&conn, &artist(id)).unwrap();
+        }
+        assert_eq!(
+            recent_artist(&conn, 0, 10).unwrap(),
+            vec![artist("UC2"), artist("UC1")]
+        );
+    }
+}
@@ -0,0 +1,120 @@
+// Ids of youtube items and of playlists created by user. Youtube ids may also be read from
+// the url that user gave instead of the id itself
+
+// Youtube video id is always 11 characters of url safe base64
+pub fn is_video_id(id: &str) -> bool {
+    id.len() == 11
+        && id
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+}
+
+// Youtube playlist id is longer than video id and starts with the kind of playlist.
+// eg: PL for playlist made by user, OL for album and RD for mix
+pub fn is_playlist_id(id: &str) -> bool {
+    const PREFIXES: [&str; 6] = ["PL", "OL", "RD", "UU", "LL", "FL"];
+    id.len() > 11
+        && PREFIXES.iter().any(|prefix| id.starts_with(prefix))
+        && id
+            .chars()
+            .all(|ch| ch.is_ascii_alphanumeric() || ch == '-' || ch == '_')
+}
+
+pub fn playlist_url(playlist_id: &str) -> String {
+    format!("https://www.youtube.com/playlist?list={}", playlist_id)
+}
+
+// Value of first of `keys` found in query of url. eg: v of https://www.youtube.com/watch?v=<id>
+fn query_value<'u>(url: &'u str, keys: &[&str]) -> Option<&'u str> {
+    let (_, query) = url.split_once('?')?;
+    query.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        keys.contains(&key).then_some(value)
+    })
+}
+
+// Video id from https://www.youtube.com/watch?v=<id> or https://youtu.be/<id>
+pub fn video_id_from_url(url: &str) -> Option<String> {
+    if let Some((_, id)) = url.split_once("youtu.be/") {
+        return id.split(['?', '&']).next().map(str::to_string);
+    }
+    query_value(url, &["v"]).map(str::to_string)
+}
+
+// Channel id from https://www.youtube.com/channel/<id>
+pub fn channel_id_from_url(url: &str) -> Option<String> {
+    let (_, id) = url.split_once("/channel/")?;
+    let id = id.split(['/', '?']).next().unwrap_or_default();
+    (!id.is_empty()).then(|| id.to_string())
+}
+
+// Id of whatever youtube url is pointing to. Url with both video and playlist give the one
+// that comes first. Anything else is taken as id itself
+pub fn id_from_arg(arg: &str) -> String {
+    channel_id_from_url(arg)
+        .or_else(|| query_value(arg, &["list", "v"]).map(str::to_string))
+        .or_else(|| video_id_from_url(arg))
+        .unwrap_or_else(|| arg.to_string())
+}
+
+// Playlists created by user are identified as `ytui:<id in user playlist table>`
+// so that they can be told apart from youtube playlists
+pub const USER_PLAYLIST_PREFIX: &str = "ytui:";
+
+// Return the id of user playlist in storage. None if this is not a user playlist
+pub fn user_playlist_id(playlist_id: &str) -> Option<i64> {
+    playlist_id.strip_prefix(USER_PLAYLIST_PREFIX)?.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tell_user_playlist_apart() {
+        assert_eq!(user_playlist_id("ytui:12"), Some(12));
+        assert_eq!(user_playlist_id("PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"), None);
+        assert_eq!(user_playlist_id("ytui:abc"), None);
+    }
+
+    #[test]
+    fn youtube_ids_from_url() {
+        assert_eq!(
+            id_from_arg("https://www.youtube.com/watch?v=kJQP7kiw5Fk&t=10"),
+            "kJQP7kiw5Fk"
+        );
+        assert_eq!(
+            id_from_arg("https://youtu.be/kJQP7kiw5Fk?t=10"),
+            "kJQP7kiw5Fk"
+        );
+        assert_eq!(
+            id_from_arg("https://www.youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"),
+            "PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"
+        );
+        assert_eq!(
+            id_from_arg("https://www.youtube.com/channel/UC1/videos"),
+            "UC1"
+        );
+        assert_eq!(id_from_arg("kJQP7kiw5Fk"), "kJQP7kiw5Fk");
+        // Music is wanted from m3u even if it is part of playlist
+        assert_eq!(
+            video_id_from_url("https://www.youtube.com/watch?list=PL1&v=long").as_deref(),
+            Some("long")
+        );
+        assert_eq!(video_id_from_url("https://example.com/song.mp3"), None);
+        assert_eq!(
+            channel_id_from_url("https://www.youtube.com/channel/"),
+            None
+        );
+
+        assert!(is_video_id("kJQP7kiw5Fk"));
+        assert!(!is_video_id("PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"));
+        assert!(is_playlist_id("PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"));
+        assert!(!is_playlist_id("kJQP7kiw5Fk"));
+        assert!(!is_playlist_id("UCq-Fj5jknLsUf-MWSy4_brA"));
+        assert_eq!(
+            playlist_url("PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"),
+            "https://www.youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI"
+        );
+    }
+}
@@ -1,4 +1,14 @@
 use serde::{self, Deserialize, Serialize};
+pub mod discovery;
+pub mod download;
+pub mod external;
+pub mod favourates;
+mod health;
+pub mod history;
+pub mod ids;
+pub mod library;
+pub mod provider;
+pub mod user_playlist;
 pub mod utils;
 use std::time::Duration;
 
@@ -7,12 +17,23 @@ pub trait ExtendDuration {
     fn from_string(inp: &str) -> Duration;
 }
 
+// Server send numbers but the units serialized by ytui-music itself (eg: exported favourates or
+// `search --json`) already have them as string. Both are accepted so that those can be read back
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumOrStr {
+    Num(u64),
+    Str(String),
+}
+
 fn num_to_str<'de, D>(input: D) -> Result<String, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let num: usize = Deserialize::deserialize(input)?;
-    let mut res = num.to_string();
+    let mut res = match Deserialize::deserialize(input)? {
+        NumOrStr::Num(num) => num.to_string(),
+        NumOrStr::Str(text) => text,
+    };
     res.shrink_to_fit();
     Ok(res)
 }
@@ -25,24 +46,10 @@ where
     // the the video may be live ({islive: true, ..} in response)
     // to keep things simple ignore all those details and this will simply return "0:0"
     // this should be documented to inform the user
-    let sec: u64 = Deserialize::deserialize(input)?;
-    let dur: Duration = Duration::from_secs(sec);
-    Ok(dur.to_string())
-}
-
-// While fecthing playlist videos from endpoint /playlists/:plid
-// response is returned as "videos": [ { <Fields of MusicUnit> } ]
-// this structure is only used to convert such response to Vec<MusicUnit>
-#[derive(Deserialize, Clone, PartialEq)]
-struct FetchPlaylistContentRes {
-    videos: Vec<MusicUnit>,
-}
-
-// Serve same purpose as described in struct FetchPlaylistContentRes but
-// to convert to Vec<PlaylistUnit>
-#[derive(Deserialize, Clone, PartialEq)]
-struct FetchArtistPlaylist {
-    playlists: Vec<PlaylistUnit>,
+    match Deserialize::deserialize(input)? {
+        NumOrStr::Num(sec) => Ok(Duration::from_secs(sec).to_string()),
+        NumOrStr::Str(duration) => Ok(duration),
+    }
 }
 
 // Represent the single playable music item.
@@ -66,6 +73,18 @@ pub struct MusicUnit {
     pub id: String,
 }
 
+// Music titled "Title <id>" by "Artist". Tests of every crate use this to fill list and queue.
+// Other crates get it with `test-util` feature
+#[cfg(any(test, feature = "test-util"))]
+pub fn test_music(id: &str) -> MusicUnit {
+    MusicUnit {
+        id: id.to_string(),
+        name: format!("Title {}", id),
+        artist: String::from("Artist"),
+        duration: String::from("1:00"),
+    }
+}
+
 #[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
 pub struct ArtistUnit {
     #[serde(alias = "author")]
@@ -118,7 +137,8 @@ pub enum ReturnAction {
     // This variat indicates that the fetch has failed and cannot be resolved on retrying
     // This may be due to several reasons including server down, network failure, parse failure
     // Also Failed is active when fetcher had retried and now had exceed the retry count
-    Failed,
+    // The reason of failure is carried along. See FetchError
+    Failed(FetchError),
     // This variant simply indicates that the request has failed but doing the same request for
     // another time may suceed
     Retry,
@@ -132,6 +152,44 @@ pub enum ReturnAction {
     EOR,
 }
 
+// Describes why the fetch has failed. Every variant that is result of web request carries
+// the server to which the request was made so that unreliable server can be identified
+#[derive(Debug)]
+pub enum FetchError {
+    // Request could not be made or response could not be read.
+    // eg: DNS failure, connection refused, timeout
+    Network {
+        server: String,
+        cause: reqwest::Error,
+    },
+    // Server did respond but not with success status code. eg: 429 Too Many Request
+    Status {
+        server: String,
+        status: reqwest::StatusCode,
+    },
+    // Response is recived but does not match the expected format.
+    // This usually happens when server is running different version of invidious
+    Parse {
+        server: String,
+        cause: serde_json::Error,
+    },
+    // Local file could not be read. eg: instances document given as path
+    File {
+        path: String,
+        cause: std::io::Error,
+    },
+    // Error while reading local data like favourates from sqlite storage
+    Storage {
+        table: &'static str,
+        cause: rusqlite::Error,
+    },
+    // Work done in blocking thread panicked. eg: reading tags of malformed file
+    Task {
+        job: &'static str,
+        cause: tokio::task::JoinError,
+    },
+}
+
 pub struct Fetcher {
     // None if nothing of the trending music is selected.
     // Stores the vector of music that is trending in music section in specified region
@@ -167,32 +225,30 @@ pub struct Fetcher {
     */
     artist_content: ArtistRes,
 
-    // List of available servers powered by invidious youtube data fetcher. All the servers should
-    // provide same endpoints to make request to and same pattern of return data. Which actually means
-    // all the servers must be powered by the same mahor version of invidious backend.
+    // One provider for each of the server defined in config. Every provider gives the same
+    // type of data regardless of what the server is powered by. See provider/mod.rs
     // Most of the servers do not expect high amount of request to their api. So to protect this
     // it would be better to frequently change the server time to time even in single session.
     // To distribute the load between multiple servers it would be better if this list is kept growing
-    // See the utils.rs file to see the format of server url.
-    servers: &'static [String],
+    // See the invidious_servers.list file in config to see the format of server url.
+    providers: Vec<Box<dyn provider::MusicProvider>>,
 
     // Container to store the result of search result.
     // First field: (String) is the query being searched for.
     search_res: SearchRes,
 
-    // The reqwest client itself. This is only initilized once per session.
-    client: reqwest::Client,
-
-    // index that reference the servers[] field.
-    // When server need to be changes as described in documentation of servers[] field
+    // index that reference the providers[] field.
+    // When server need to be changes as described in documentation of providers[] field
     // this index is updated (usually rotated clockwise)
     // TODO:
-    // It may be more efficient to directly reference the elemnt from searvers[] rather than
+    // It may be more efficient to directly reference the elemnt from providers[] rather than
     // storing the index and hence preventing accidintal out-of-index access
     active_server_index: usize,
 
+    // Observed health (failures and latency) of each server in `providers`.
+    // This is used while changing the server to avoid dead or slow servers
+    health: health::HealthTracker,
+
     // copy of constants.item_per_list
     item_per_page: usize,
-    // reference to constants.region in config file
-    region: &'static str,
 }
@@ -0,0 +1,415 @@
+use crate::{ExtendDuration, MusicUnit};
+use id3::TagLike;
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::time::{Duration, UNIX_EPOCH};
+
+// Only files with these extensions are added to local library.
+// Tags are read from mp3, flac, ogg and opus. For others, file name is shown as title
+const MUSIC_EXTENSIONS: [&str; 8] = ["mp3", "flac", "ogg", "opus", "m4a", "aac", "wav", "webm"];
+
+// A music file found while scanning the library roots
+#[derive(Debug, PartialEq)]
+pub struct LocalFile {
+    pub path: String,
+    // Last modification time of file as unix timestamp. Tags of file are only read again
+    // if this has changed since last scan
+    pub modified: i64,
+}
+
+// Tags that are needed to build MusicUnit. Any of them may be missing in file
+#[derive(Default, Debug)]
+struct Tags {
+    title: Option<String>,
+    artist: Option<String>,
+    duration: Option<Duration>,
+}
+
+// Local music are identified by their absolute path instead of youtube video id.
+// Youtube ids are never a path so this is enough to tell them apart
+pub fn is_local(music_id: &str) -> bool {
+    Path::new(music_id).is_absolute()
+}
+
+// Return what should be given to the player to play this music
+pub fn music_url(music_id: &str) -> String {
+    if is_local(music_id) {
+        music_id.to_string()
+    } else {
+        format!("https://www.youtube.com/watch?v={}", music_id)
+    }
+}
+
+// Walk every root recursively and return all the music files in them.
+// Roots that do not exist and files with non-utf8 path are silently skipped
+pub fn find_music_files(roots: &[String]) -> Vec<LocalFile> {
+    let mut files = Vec::new();
+
+    for root in roots {
+        let entries = walkdir::WalkDir::new(root)
+            .follow_links(true)
+            .into_iter()
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| entry.file_type().is_file());
+
+        for entry in entries {
+            let path = entry.path();
+            if !has_music_extension(path) {
+                continue;
+            }
+            let path_str = match path.to_str() {
+                Some(path_str) => path_str.to_string(),
+                None => continue,
+            };
+            let modified = entry
+                .metadata()
+                .ok()
+                .and_then(|metadata| metadata.modified().ok())
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|since_epoch| since_epoch.as_secs() as i64)
+                .unwrap_or_default();
+
+            files.push(LocalFile {
+                path: path_str,
+                modified,
+            });
+        }
+    }
+
+    files
+}
+
+fn has_music_extension(path: &Path) -> bool {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some(ext) => MUSIC_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()),
+        None => false,
+    }
+}
+
+// Read the tags from music file at `path` and build MusicUnit out of it.
+// Title falls back to file name when file have no tags or tags cannot be read
+pub fn read_music(path: &str) -> MusicUnit {
+    let extension = Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    let tags = match extension.as_str() {
+        "mp3" => read_id3(path),
+        "flac" => read_flac(path),
+        "ogg" | "opus" => read_ogg(path),
+        _ => None,
+    }
+    .unwrap_or_default();
+
+    let file_name = Path::new(path)
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    MusicUnit {
+        id: path.to_string(),
+        name: tags.title.unwrap_or(file_name),
+        artist: tags.artist.unwrap_or_default(),
+        duration: tags.duration.unwrap_or_default().to_string(),
+    }
+}
+
+fn read_id3(path: &str) -> Option<Tags> {
+    let tag = id3::Tag::read_from_path(path).ok()?;
+    Some(Tags {
+        title: tag.title().map(String::from),
+        artist: tag.artist().map(String::from),
+        // TLEN frame is in milliseconds
+        duration: tag
+            .duration()
+            .map(|millis| Duration::from_millis(millis as u64)),
+    })
+}
+
+// Flac file starts with "fLaC" followed by metadata blocks. Each block have a 4 byte header
+// 1 bit: is this last block, 7 bits: block type, 24 bits: length of block
+// Only STREAMINFO (type 0) and VORBIS_COMMENT (type 4) blocks are read
+fn read_flac(path: &str) -> Option<Tags> {
+    let mut reader = BufReader::new(File::open(path).ok()?);
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic).ok()?;
+    if &magic != b"fLaC" {
+        return None;
+    }
+
+    let mut tags = Tags::default();
+    loop {
+        let mut header = [0; 4];
+        reader.read_exact(&mut header).ok()?;
+        let is_last = header[0] & 0x80 != 0;
+        let block_type = header[0] & 0x7F;
+        let length = u32::from_be_bytes([0, header[1], header[2], header[3]]) as usize;
+
+        match block_type {
+            0 | 4 => {
+                let mut block = vec![0; length];
+                reader.read_exact(&mut block).ok()?;
+                if block_type == 0 {
+                    tags.duration = flac_duration(&block);
+                } else {
+                    parse_vorbis_comment(&block, &mut tags);
+                }
+            }
+            // Other blocks like embedded picture may be large so skip without reading
+            _ => {
+                reader.seek(SeekFrom::Current(length as i64)).ok()?;
+            }
+        }
+
+        if is_last {
+            break;
+        }
+    }
+
+    Some(tags)
+}
+
+// STREAMINFO block have sample rate in 20 bits starting at byte 10
+// and total samples in 36 bits right after 3 bit channels and 5 bit sample size
+fn flac_duration(streaminfo: &[u8]) -> Option<Duration> {
+    if streaminfo.len() < 18 {
+        return None;
+    }
+    let sample_rate = ((streaminfo[10] as u64) << 12)
+        | ((streaminfo[11] as u64) << 4)
+        | ((streaminfo[12] as u64) >> 4);
+    let total_samples = (((streaminfo[13] & 0x0F) as u64) << 32)
+        | u32::from_be_bytes([
+            streaminfo[14],
+            streaminfo[15],
+            streaminfo[16],
+            streaminfo[17],
+        ]) as u64;
+
+    if sample_rate == 0 {
+        return None;
+    }
+    Some(Duration::from_secs(total_samples / sample_rate))
+}
+
+// First packet of ogg stream identifies the codec and second packet have the vorbis comment.
+// Duration is the granule position of last page divided by sample rate.
+fn read_ogg(path: &str) -> Option<Tags> {
+    let file = BufReader::new(File::open(path).ok()?);
+    let mut reader = ogg::PacketReader::new(file);
+
+    let ident = reader.read_packet().ok()??;
+    let serial = ident.stream_serial();
+    // Opus granule position is always in 48khz. But it also includes pre-skip samples
+    let (sample_rate, pre_skip) = if ident.data.starts_with(b"\x01vorbis") && ident.data.len() >= 16
+    {
+        let rate = &ident.data[12..16];
+        (
+            u32::from_le_bytes([rate[0], rate[1], rate[2], rate[3]]) as u64,
+            0,
+        )
+    } else if ident.data.starts_with(b"OpusHead") && ident.data.len() >= 12 {
+        (
+            48_000,
+            u16::from_le_bytes([ident.data[10], ident.data[11]]) as u64,
+        )
+    } else {
+        return None;
+    };
+
+    let mut tags = Tags::default();
+    let comment = reader.read_packet().ok()??;
+    if let Some(data) = comment.data.strip_prefix(b"\x03vorbis") {
+        parse_vorbis_comment(data, &mut tags);
+    } else if let Some(data) = comment.data.strip_prefix(b"OpusTags") {
+        parse_vorbis_comment(data, &mut tags);
+    }
+
+    let mut last_granule = 0;
+    while let Ok(Some(packet)) = reader.read_packet() {
+        if packet.stream_serial() == serial && packet.last_in_page() {
+            last_granule = packet.absgp_page();
+        }
+    }
+    if sample_rate != 0 && last_granule > pre_skip {
+        tags.duration = Some(Duration::from_secs((last_granule - pre_skip) / sample_rate));
+    }
+
+    Some(tags)
+}
+
+// Vorbis comment is used by both flac and ogg. Layout (all numbers are 32 bit little endian):
+// vendor length, vendor string, number of comments, then each comment as length + "KEY=value"
+fn parse_vorbis_comment(data: &[u8], tags: &mut Tags) {
+    fn read_u32(data: &[u8], at: usize) -> Option<usize> {
+        let bytes = data.get(at..at + 4)?;
+        Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    let vendor_length = match read_u32(data, 0) {
+        Some(length) => length,
+        None => return,
+    };
+    let mut position = 4 + vendor_length;
+    let count = match read_u32(data, position) {
+        Some(count) => count,
+        None => return,
+    };
+    position += 4;
+
+    for _ in 0..count {
+        let length = match read_u32(data, position) {
+            Some(length) => length,
+            None => return,
+        };
+        position += 4;
+        let comment = match data.get(position..position + length) {
+            Some(comment) => String::from_utf8_lossy(comment),
+            None => return,
+        };
+        position += length;
+
+        if let Some((key, value)) = comment.split_once('=') {
+            match key.to_ascii_uppercase().as_str() {
+                "TITLE" => tags.title = Some(value.to_string()),
+                "ARTIST" => tags.artist = Some(value.to_string()),
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Create an empty directory inside system temp dir for the test
+    fn test_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("ytui_library_{}", name));
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn vorbis_comment(comments: &[&str]) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&6u32.to_le_bytes());
+        data.extend_from_slice(b"vendor");
+        data.extend_from_slice(&(comments.len() as u32).to_le_bytes());
+        for comment in comments {
+            data.extend_from_slice(&(comment.len() as u32).to_le_bytes());
+            data.extend_from_slice(comment.as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn scan_and_read_tags() {
+        let dir = test_dir("scan");
+        std::fs::create_dir_all(dir.join("Artist")).unwrap();
+
+        // mp3 with only id3 tag and no audio at all is enough to read the tag
+        let mp3_path = dir.join("Artist").join("song.mp3");
+        std::fs::write(&mp3_path, []).unwrap();
+        let mut tag = id3::Tag::new();
+        tag.set_title("Id3 title");
+        tag.set_artist("Id3 artist");
+        tag.set_duration(185_000);
+        tag.write_to_path(&mp3_path, id3::Version::Id3v24).unwrap();
+
+        // flac with streaminfo of 44100hz and 44100 * 200 samples
+        let mut streaminfo = vec![0u8; 34];
+        let sample_rate: u64 = 44100;
+        let total_samples: u64 = 44100 * 200;
+        streaminfo[10] = (sample_rate >> 12) as u8;
+        streaminfo[11] = (sample_rate >> 4) as u8;
+        streaminfo[12] = ((sample_rate & 0x0F) << 4) as u8;
+        streaminfo[13] = ((total_samples >> 32) & 0x0F) as u8;
+        streaminfo[14..18].copy_from_slice(&(total_samples as u32).to_be_bytes());
+        let comment = vorbis_comment(&["title=Flac title", "ARTIST=Flac artist"]);
+        let mut flac = b"fLaC".to_vec();
+        flac.extend_from_slice(&[0, 0, 0, 34]);
+        flac.extend_from_slice(&streaminfo);
+        flac.extend_from_slice(&[0x84, 0, 0, comment.len() as u8]);
+        flac.extend_from_slice(&comment);
+        std::fs::write(dir.join("track.FLAC"), flac).unwrap();
+
+        std::fs::write(dir.join("No tags.m4a"), b"not really m4a").unwrap();
+        std::fs::write(dir.join("cover.jpg"), b"not music").unwrap();
+
+        let mut files = find_music_files(&[dir.to_string_lossy().to_string()]);
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(files.len(), 3);
+        assert!(files.iter().all(|file| file.modified > 0));
+
+        let musics: Vec<MusicUnit> = files.iter().map(|file| read_music(&file.path)).collect();
+        assert_eq!(
+            musics[0],
+            MusicUnit {
+                id: mp3_path.to_string_lossy().to_string(),
+                name: "Id3 title".to_string(),
+                artist: "Id3 artist".to_string(),
+                duration: Duration::from_secs(185).to_string(),
+            }
+        );
+        assert_eq!(musics[1].name, "No tags");
+        assert_eq!(musics[1].artist, "");
+        assert_eq!(musics[2].name, "Flac title");
+        assert_eq!(musics[2].artist, "Flac artist");
+        assert_eq!(musics[2].duration, Duration::from_secs(200).to_string());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn read_ogg_vorbis_tags() {
+        let dir = test_dir("ogg");
+        let path = dir.join("song.ogg");
+
+        let mut ident = b"\x01vorbis".to_vec();
+        ident.extend_from_slice(&[0; 5]);
+        ident.extend_from_slice(&48000u32.to_le_bytes());
+        ident.extend_from_slice(&[0; 14]);
+        let mut comment = b"\x03vorbis".to_vec();
+        comment.extend_from_slice(&vorbis_comment(&["TITLE=Ogg title", "ARTIST=Ogg artist"]));
+
+        let mut content = Vec::new();
+        {
+            let mut writer = ogg::PacketWriter::new(&mut content);
+            let end_page = ogg::PacketWriteEndInfo::EndPage;
+            writer
+                .write_packet(ident.into_boxed_slice(), 1, end_page, 0)
+                .unwrap();
+            writer
+                .write_packet(comment.into_boxed_slice(), 1, end_page, 0)
+                .unwrap();
+            let end_stream = ogg::PacketWriteEndInfo::EndStream;
+            writer
+                .write_packet(vec![0; 10].into_boxed_slice(), 1, end_stream, 48000 * 95)
+                .unwrap();
+        }
+        std::fs::write(&path, content).unwrap();
+
+        let music = read_music(path.to_str().unwrap());
+        assert_eq!(music.name, "Ogg title");
+        assert_eq!(music.artist, "Ogg artist");
+        assert_eq!(music.duration, Duration::from_secs(95).to_string());
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn url_of_local_and_remote_music() {
+        assert_eq!(
+            music_url("/home/me/Music/song.mp3"),
+            "/home/me/Music/song.mp3"
+        );
+        assert_eq!(
+            music_url("kJQP7kiw5Fk"),
+            "https://www.youtube.com/watch?v=kJQP7kiw5Fk"
+        );
+    }
+}
@@ -0,0 +1,161 @@
+use super::{get_bytes, get_json, MusicProvider, ProviderResult};
+use crate::{ArtistUnit, MusicUnit, PlaylistUnit};
+use serde::Deserialize;
+
+// Only these fields are requested from server for respective type so that
+// server do not have to send unnecessary data
+const FIELDS: [&str; 3] = [
+    "videoId,title,author,lengthSeconds",
+    "title,playlistId,author,videoCount",
+    "author,authorId,videoCount",
+];
+const FILTER_TYPE: [&str; 3] = ["music", "playlist", "channel"];
+
+// While fecthing playlist videos from endpoint /playlists/:plid
+// response is returned as "videos": [ { <Fields of MusicUnit> } ]
+// this structure is only used to convert such response to Vec<MusicUnit>
+#[derive(Deserialize, Clone, PartialEq)]
+struct FetchPlaylistContentRes {
+    videos: Vec<MusicUnit>,
+}
+
+// Serve same purpose as described in struct FetchPlaylistContentRes but
+// to convert to Vec<PlaylistUnit>
+#[derive(Deserialize, Clone, PartialEq)]
+struct FetchArtistPlaylist {
+    playlists: Vec<PlaylistUnit>,
+}
+
+// Server powered by invidious. Server url is expected to point to api root.
+// See invidious_servers.list in config for the format
+pub struct Invidious {
+    server: String,
+    client: reqwest::Client,
+    region: &'static str,
+}
+
+impl Invidious {
+    pub fn new(server: &str, client: reqwest::Client, region: &'static str) -> Self {
+        Invidious {
+            server: server.to_string(),
+            client,
+            region,
+        }
+    }
+
+    fn search_path(&self, query: &str, filter_index: usize, page: usize) -> String {
+        format!(
+            "/search?q={query}&type={s_type}&region={region}&page={page}&fields={fields}",
+            query = query,
+            s_type = FILTER_TYPE[filter_index],
+            region = self.region,
+            fields = FIELDS[filter_index],
+            page = page
+        )
+    }
+}
+
+impl MusicProvider for Invidious {
+    fn search_music<'a>(
+        &'a mut self,
+        query: &'a str,
+        page: usize,
+    ) -> ProviderResult<'a, Vec<MusicUnit>> {
+        Box::pin(async move {
+            let path = self.search_path(query, 0, page);
+            get_json(&self.client, &self.server, &path).await
+        })
+    }
+
+    fn search_playlist<'a>(
+        &'a mut self,
+        query: &'a str,
+        page: usize,
+    ) -> ProviderResult<'a, Vec<PlaylistUnit>> {
+        Box::pin(async move {
+            let path = self.search_path(query, 1, page);
+            get_json(&self.client, &self.server, &path).await
+        })
+    }
+
+    fn search_artist<'a>(
+        &'a mut self,
+        query: &'a str,
+        page: usize,
+    ) -> ProviderResult<'a, Vec<ArtistUnit>> {
+        Box::pin(async move {
+            let path = self.search_path(query, 2, page);
+            get_json(&self.client, &self.server, &path).await
+        })
+    }
+
+    fn trending_music(&mut self) -> ProviderResult<'_, Vec<MusicUnit>> {
+        Box::pin(async move {
+            let path = format!(
+                "/trending?type=Music&region={region}&fields={music_field}",
+                region = self.region,
+                music_field = FIELDS[0]
+            );
+            get_json(&self.client, &self.server, &path).await
+        })
+    }
+
+    fn playlist_content<'a>(
+        &'a mut self,
+        playlist_id: &'a str,
+    ) -> ProviderResult<'a, Vec<MusicUnit>> {
+        Box::pin(async move {
+            let path = format!(
+                "/playlists/{playlist_id}?fields=videos({music_field})",
+                playlist_id = playlist_id,
+                music_field = FIELDS[0]
+            );
+            get_json::<FetchPlaylistContentRes>(&self.client, &self.server, &path)
+                .await
+                .map(|res| res.videos)
+        })
+    }
+
+    fn channel_videos<'a>(&'a mut self, channel_id: &'a str) -> ProviderResult<'a, Vec<MusicUnit>> {
+        Box::pin(async move {
+            let path = format!(
+                "/channels/{channel_id}/videos?fields={music_field}",
+                channel_id = channel_id,
+                music_field = FIELDS[0]
+            );
+            get_json(&self.client, &self.server, &path).await
+        })
+    }
+
+    fn channel_playlists<'a>(
+        &'a mut self,
+        channel_id: &'a str,
+    ) -> ProviderResult<'a, Vec<PlaylistUnit>> {
+        Box::pin(async move {
+            let path = format!(
+                "/channels/{channel_id}/playlists?fields=playlists({channel_fields})",
+                channel_id = channel_id,
+                channel_fields = FIELDS[1],
+            );
+            get_json::<FetchArtistPlaylist>(&self.client, &self.server, &path)
+                .await
+                .map(|res| res.playlists)
+        })
+    }
+
+    // Thumbnails are served from root of the instance instead of api root
+    fn cover_art<'a>(&'a mut self, video_id: &'a str) -> ProviderResult<'a, Vec<u8>> {
+        Box::pin(async move {
+            let root = self
+                .server
+                .trim_end_matches('/')
+                .trim_end_matches("/api/v1");
+            let url = format!(
+                "{root}/vi/{video_id}/hqdefault.jpg",
+                root = root,
+                video_id = video_id
+            );
+            get_bytes(&self.client, &self.server, &url).await
+        })
+    }
+}
@@ -0,0 +1,130 @@
+use crate::{ArtistUnit, FetchError, MusicUnit, PlaylistUnit};
+use std::future::Future;
+use std::pin::Pin;
+pub mod invidious;
+pub mod piped;
+
+// Future returned by every method of MusicProvider.
+// Futures are boxed so that different providers can be kept in same list as trait object
+pub type ProviderResult<'a, T> = Pin<Box<dyn Future<Output = Result<T, FetchError>> + Send + 'a>>;
+
+// A source of music data. eg: an invidious server.
+// Provider is only responsible to get the data from it's source and convert it to
+// MusicUnit/PlaylistUnit/ArtistUnit. Caching, pagination of the list and choosing which
+// provider to use is done by the Fetcher itself.
+//
+// Search methods returns a single page of result from the source where `page` start from 0.
+// Provider may return any number of items in a page.
+// Other methods return whole content at once.
+pub trait MusicProvider: Send {
+    fn search_music<'a>(
+        &'a mut self,
+        query: &'a str,
+        page: usize,
+    ) -> ProviderResult<'a, Vec<MusicUnit>>;
+
+    fn search_playlist<'a>(
+        &'a mut self,
+        query: &'a str,
+        page: usize,
+    ) -> ProviderResult<'a, Vec<PlaylistUnit>>;
+
+    fn search_artist<'a>(
+        &'a mut self,
+        query: &'a str,
+        page: usize,
+    ) -> ProviderResult<'a, Vec<ArtistUnit>>;
+
+    fn trending_music(&mut self) -> ProviderResult<'_, Vec<MusicUnit>>;
+
+    fn playlist_content<'a>(
+        &'a mut self,
+        playlist_id: &'a str,
+    ) -> ProviderResult<'a, Vec<MusicUnit>>;
+
+    fn channel_videos<'a>(&'a mut self, channel_id: &'a str) -> ProviderResult<'a, Vec<MusicUnit>>;
+
+    fn channel_playlists<'a>(
+        &'a mut self,
+        channel_id: &'a str,
+    ) -> ProviderResult<'a, Vec<PlaylistUnit>>;
+
+    // Thumbnail of video as jpeg. Written as cover art of downloaded music.
+    // Thumbnail is fetched through the server itself instead of from youtube
+    fn cover_art<'a>(&'a mut self, video_id: &'a str) -> ProviderResult<'a, Vec<u8>>;
+}
+
+// Prefix of server entry in `Servers::list` that is powered by piped.
+// eg: piped+https://pipedapi.kavin.rocks
+// Entry without any prefix is an invidious server
+pub const PIPED_PREFIX: &str = "piped+";
+
+// Create the provider for single entry of `Servers::list` in config
+pub fn from_server(
+    server: &str,
+    client: reqwest::Client,
+    region: &'static str,
+) -> Box<dyn MusicProvider> {
+    match server.strip_prefix(PIPED_PREFIX) {
+        Some(server) => Box::new(piped::Piped::new(server, client, region)),
+        None => Box::new(invidious::Invidious::new(server, client, region)),
+    }
+}
+
+// Send GET request to `server` + `path` and deserialize the json response
+pub(crate) async fn get_json<Res>(
+    client: &reqwest::Client,
+    server: &str,
+    path: &str,
+) -> Result<Res, FetchError>
+where
+    Res: serde::de::DeserializeOwned,
+{
+    let server = server.to_string();
+    let url = server.clone() + path;
+
+    let response = match client.get(url).send().await {
+        Ok(response) => response,
+        Err(cause) => return Err(FetchError::Network { server, cause }),
+    };
+
+    // Server may respond with error page (eg: 429 Too many request) which will
+    // also fail to parse as json. So check the status before reading body to report
+    // the real cause of failure
+    let status = response.status();
+    if !status.is_success() {
+        return Err(FetchError::Status { server, status });
+    }
+
+    // Body is first read as text and then deserialized so that the serde error
+    // is preserved instead of being wrapped by reqwest
+    let body = match response.text().await {
+        Ok(body) => body,
+        Err(cause) => return Err(FetchError::Network { server, cause }),
+    };
+    serde_json::from_str::<Res>(&body).map_err(|cause| FetchError::Parse { server, cause })
+}
+
+// Send GET request to `url` and return the body as it is.
+// Unlike get_json, `url` is full url as it may point outside of `server`. eg: to image proxy
+pub(crate) async fn get_bytes(
+    client: &reqwest::Client,
+    server: &str,
+    url: &str,
+) -> Result<Vec<u8>, FetchError> {
+    let server = server.to_string();
+
+    let response = match client.get(url).send().await {
+        Ok(response) => response,
+        Err(cause) => return Err(FetchError::Network { server, cause }),
+    };
+    let status = response.status();
+    if !status.is_success() {
+        return Err(FetchError::Status { server, status });
+    }
+
+    match response.bytes().await {
+        Ok(body) => Ok(body.to_vec()),
+        Err(cause) => Err(FetchError::Network { server, cause }),
+    }
+}
@@ -0,0 +1,511 @@
+use super::{get_bytes, get_json, MusicProvider, ProviderResult};
+use crate::{ArtistUnit, ExtendDuration, FetchError, MusicUnit, PlaylistUnit};
+use serde::Deserialize;
+use std::time::Duration;
+
+// Value of `filter` query parameter of /search endpoint for music, playlist and artist
+const FILTER_TYPE: [&str; 3] = ["music_songs", "playlists", "channels"];
+
+// Every list returned by piped (search result, trending, playlist videos ...) is a list of
+// these items. Item type is decided by the "type" field.
+// Only the fields that are needed to build MusicUnit/PlaylistUnit/ArtistUnit are read
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum PipedItem {
+    Stream(PipedStream),
+    Playlist(PipedPlaylist),
+    Channel(PipedChannel),
+    // Any other type of item is not shown in the list
+    #[serde(other)]
+    Unknown,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PipedStream {
+    // in form of /watch?v=<videoId>
+    url: String,
+    title: String,
+    #[serde(default)]
+    uploader_name: Option<String>,
+    // -1 for live streams
+    duration: i64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PipedPlaylist {
+    // in form of /playlist?list=<playlistId>
+    url: String,
+    name: String,
+    #[serde(default)]
+    uploader_name: Option<String>,
+    // -1 when piped do not know the count
+    videos: i64,
+}
+
+#[derive(Deserialize)]
+struct PipedChannel {
+    // in form of /channel/<channelId>
+    url: String,
+    name: String,
+    videos: i64,
+}
+
+// Response of /search and /nextpage/search
+// `nextpage` is the token to fetch next page of same query and is null in last page
+#[derive(Deserialize)]
+struct SearchPage {
+    items: Vec<PipedItem>,
+    nextpage: Option<String>,
+}
+
+// Response of /playlists/:id
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PlaylistPage {
+    related_streams: Vec<PipedItem>,
+}
+
+// Response of /channel/:id
+// Playlists of channel are not in the response itself. Instead a tab named "playlists"
+// carries the data that should be passed to /channels/tabs to get the playlists
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChannelPage {
+    related_streams: Vec<PipedItem>,
+    #[serde(default)]
+    tabs: Vec<ChannelTab>,
+}
+
+#[derive(Deserialize)]
+struct ChannelTab {
+    name: String,
+    data: String,
+}
+
+// Response of /channels/tabs
+#[derive(Deserialize)]
+struct ChannelTabPage {
+    content: Vec<PipedItem>,
+}
+
+// Response of /streams/:id. Only the thumbnail is needed from it.
+// Thumbnail url points to the image proxy of piped instead of youtube
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct StreamPage {
+    thumbnail_url: String,
+}
+
+// Piped search can not jump to arbitary page. Each page carries the token to get the next one.
+// So tokens of already fetched pages are remembered for the last query
+// `next[n]` is the token to fetch page n + 1
+#[derive(Default)]
+struct SearchCursor {
+    query: String,
+    filter_index: usize,
+    next: Vec<Option<String>>,
+}
+
+// Server powered by piped. Server url is expected to point to api root of piped
+// eg: https://pipedapi.kavin.rocks
+pub struct Piped {
+    server: String,
+    client: reqwest::Client,
+    region: &'static str,
+    cursor: SearchCursor,
+}
+
+impl Piped {
+    pub fn new(server: &str, client: reqwest::Client, region: &'static str) -> Self {
+        Piped {
+            server: server.trim_end_matches('/').to_string(),
+            client,
+            region,
+            cursor: SearchCursor::default(),
+        }
+    }
+
+    // Return the items in `page` of search result for `query`.
+    // Same as invidious, page start from 0. When token for requested page is not known yet
+    // pages in between are fetched to find it. Empty list is returned after the last page
+    async fn search_items(
+        &mut self,
+        query: &str,
+        filter_index: usize,
+        page: usize,
+    ) -> Result<Vec<PipedItem>, FetchError> {
+        if self.cursor.query != query || self.cursor.filter_index != filter_index {
+            self.cursor = SearchCursor {
+                query: query.to_string(),
+                filter_index,
+                next: Vec::new(),
+            };
+        }
+
+        loop {
+            let fetching = std::cmp::min(page, self.cursor.next.len());
+            let path = if fetching == 0 {
+                format!(
+                    "/search?q={query}&filter={filter}",
+                    query = encode(query),
+                    filter = FILTER_TYPE[filter_index]
+                )
+            } else {
+                match &self.cursor.next[fetching - 1] {
+                    Some(token) => format!(
+                        "/nextpage/search?q={query}&filter={filter}&nextpage={token}",
+                        query = encode(query),
+                        filter = FILTER_TYPE[filter_index],
+                        token = encode(token)
+                    ),
+                    None => return Ok(Vec::new()),
+                }
+            };
+
+            let res: SearchPage = get_json(&self.client, &self.server, &path).await?;
+            if fetching == self.cursor.next.len() {
+                self.cursor.next.push(res.nextpage);
+            }
+            if fetching == page {
+                return Ok(res.items);
+            }
+        }
+    }
+}
+
+impl MusicProvider for Piped {
+    fn search_music<'a>(
+        &'a mut self,
+        query: &'a str,
+        page: usize,
+    ) -> ProviderResult<'a, Vec<MusicUnit>> {
+        Box::pin(async move { Ok(to_music(self.search_items(query, 0, page).await?)) })
+    }
+
+    fn search_playlist<'a>(
+        &'a mut self,
+        query: &'a str,
+        page: usize,
+    ) -> ProviderResult<'a, Vec<PlaylistUnit>> {
+        Box::pin(async move { Ok(to_playlist(self.search_items(query, 1, page).await?)) })
+    }
+
+    fn search_artist<'a>(
+        &'a mut self,
+        query: &'a str,
+        page: usize,
+    ) -> ProviderResult<'a, Vec<ArtistUnit>> {
+        Box::pin(async move { Ok(to_artist(self.search_items(query, 2, page).await?)) })
+    }
+
+    fn trending_music(&mut self) -> ProviderResult<'_, Vec<MusicUnit>> {
+        Box::pin(async move {
+            let path = format!("/trending?region={region}", region = self.region);
+            get_json(&self.client, &self.server, &path)
+                .await
+                .map(to_music)
+        })
+    }
+
+    fn playlist_content<'a>(
+        &'a mut self,
+        playlist_id: &'a str,
+    ) -> ProviderResult<'a, Vec<MusicUnit>> {
+        Box::pin(async move {
+            let path = format!("/playlists/{playlist_id}", playlist_id = playlist_id);
+            get_json::<PlaylistPage>(&self.client, &self.server, &path)
+                .await
+                .map(|res| to_music(res.related_streams))
+        })
+    }
+
+    fn channel_videos<'a>(&'a mut self, channel_id: &'a str) -> ProviderResult<'a, Vec<MusicUnit>> {
+        Box::pin(async move {
+            let path = format!("/channel/{channel_id}", channel_id = channel_id);
+            get_json::<ChannelPage>(&self.client, &self.server, &path)
+                .await
+                .map(|res| to_music(res.related_streams))
+        })
+    }
+
+    fn channel_playlists<'a>(
+        &'a mut self,
+        channel_id: &'a str,
+    ) -> ProviderResult<'a, Vec<PlaylistUnit>> {
+        Box::pin(async move {
+            let path = format!("/channel/{channel_id}", channel_id = channel_id);
+            let channel: ChannelPage = get_json(&self.client, &self.server, &path).await?;
+
+            // Channel without any playlist do not have the tab at all
+            let tab = match channel.tabs.into_iter().find(|tab| tab.name == "playlists") {
+                Some(tab) => tab,
+                None => return Ok(Vec::new()),
+            };
+            let path = format!("/channels/tabs?data={data}", data = encode(&tab.data));
+            get_json::<ChannelTabPage>(&self.client, &self.server, &path)
+                .await
+                .map(|res| to_playlist(res.content))
+        })
+    }
+
+    fn cover_art<'a>(&'a mut self, video_id: &'a str) -> ProviderResult<'a, Vec<u8>> {
+        Box::pin(async move {
+            let path = format!("/streams/{video_id}", video_id = video_id);
+            let stream: StreamPage = get_json(&self.client, &self.server, &path).await?;
+            get_bytes(&self.client, &self.server, &stream.thumbnail_url).await
+        })
+    }
+}
+
+// Return the value after `key` in `url` till the next '&'
+// Used to get the id from urls like /watch?v=<id> or /channel/<id>
+fn id_from_url<'u>(url: &'u str, key: &str) -> Option<&'u str> {
+    let start = url.find(key)? + key.len();
+    url[start..].split('&').next().filter(|id| !id.is_empty())
+}
+
+fn to_music(items: Vec<PipedItem>) -> Vec<MusicUnit> {
+    items
+        .into_iter()
+        .filter_map(|item| match item {
+            PipedItem::Stream(stream) => Some(MusicUnit {
+                id: id_from_url(&stream.url, "v=")?.to_string(),
+                name: stream.title,
+                artist: stream.uploader_name.unwrap_or_default(),
+                // Same as invidious, live streams are shown with duration of 0:0
+                duration: Duration::from_secs(std::cmp::max(stream.duration, 0) as u64).to_string(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn to_playlist(items: Vec<PipedItem>) -> Vec<PlaylistUnit> {
+    items
+        .into_iter()
+        .filter_map(|item| match item {
+            PipedItem::Playlist(playlist) => Some(PlaylistUnit {
+                id: id_from_url(&playlist.url, "list=")?.to_string(),
+                name: playlist.name,
+                author: playlist.uploader_name.unwrap_or_default(),
+                video_count: std::cmp::max(playlist.videos, 0).to_string(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+fn to_artist(items: Vec<PipedItem>) -> Vec<ArtistUnit> {
+    items
+        .into_iter()
+        .filter_map(|item| match item {
+            PipedItem::Channel(channel) => Some(ArtistUnit {
+                id: id_from_url(&channel.url, "/channel/")?.to_string(),
+                name: channel.name,
+                video_count: std::cmp::max(channel.videos, 0).to_string(),
+            }),
+            _ => None,
+        })
+        .collect()
+}
+
+// Percent encode the value to be used in query string.
+// Page tokens and tab data are json themselves so they must be encoded
+fn encode(value: &str) -> String {
+    let mut res = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                res.push(byte as char)
+            }
+            _ => res.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn read_fixture(name: &str) -> String {
+        let path = format!("{}/src/test-data/{}", env!("CARGO_MANIFEST_DIR"), name);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    // Serve the recorded responses from a local port as if it was the piped api
+    // Returns the url of server
+    async fn fixture_server() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buffer = vec![0; 4096];
+                let read = stream.read(&mut buffer).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&buffer[..read]);
+                let path = request.split(' ').nth(1).unwrap_or_default();
+
+                // Thumbnail is served from same server as if it was the image proxy
+                if path.starts_with("/vi/") {
+                    let response = "HTTP/1.1 200 OK\r\nContent-Type: image/jpeg\r\nContent-Length: 4\r\nConnection: close\r\n\r\njpeg";
+                    stream.write_all(response.as_bytes()).await.unwrap();
+                    continue;
+                }
+
+                let fixture = if path.starts_with("/nextpage/search") {
+                    "piped_search_music_next.json"
+                } else if path.starts_with("/search") && path.contains("filter=playlists") {
+                    "piped_search_playlist.json"
+                } else if path.starts_with("/search") && path.contains("filter=channels") {
+                    "piped_search_artist.json"
+                } else if path.starts_with("/search") {
+                    "piped_search_music.json"
+                } else if path.starts_with("/trending") {
+                    "piped_trending.json"
+                } else if path.starts_with("/playlists/") {
+                    "piped_playlist.json"
+                } else if path.starts_with("/channels/tabs") {
+                    "piped_channel_playlists.json"
+                } else if path.starts_with("/streams/") {
+                    "piped_streams.json"
+                } else {
+                    "piped_channel.json"
+                };
+                let body =
+                    read_fixture(fixture).replace("{server}", &format!("http://{}", address));
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+
+        format!("http://{}", address)
+    }
+
+    #[test]
+    fn map_search_items() {
+        let res: SearchPage =
+            serde_json::from_str(&read_fixture("piped_search_music.json")).unwrap();
+        assert!(res.nextpage.is_some());
+        // playlist in music search is ignored
+        assert_eq!(
+            to_music(res.items),
+            vec![
+                MusicUnit {
+                    artist: "Luis Fonsi".to_string(),
+                    name: "Luis Fonsi - Despacito ft. Daddy Yankee".to_string(),
+                    duration: Duration::from_secs(282).to_string(),
+                    id: "kJQP7kiw5Fk".to_string(),
+                },
+                MusicUnit {
+                    artist: "Wiz Khalifa".to_string(),
+                    name: "Wiz Khalifa - See You Again ft. Charlie Puth".to_string(),
+                    duration: Duration::from_secs(3725).to_string(),
+                    id: "RgKAFK5djSk".to_string(),
+                },
+            ]
+        );
+
+        let res: SearchPage =
+            serde_json::from_str(&read_fixture("piped_search_playlist.json")).unwrap();
+        let playlists = to_playlist(res.items);
+        assert_eq!(playlists[0].id, "PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI");
+        assert_eq!(playlists[0].author, "Music Charts");
+        assert_eq!(playlists[0].video_count, "50");
+        assert_eq!(playlists[1].author, "");
+        assert_eq!(playlists[1].video_count, "0");
+
+        let res: SearchPage =
+            serde_json::from_str(&read_fixture("piped_search_artist.json")).unwrap();
+        assert_eq!(
+            to_artist(res.items),
+            vec![ArtistUnit {
+                name: "Luis Fonsi".to_string(),
+                id: "UCxoq-PAQeAdk_zyg8YS0JqA".to_string(),
+                video_count: "152".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn search_follows_page_token() {
+        let server = fixture_server().await;
+        let mut piped = Piped::new(&server, reqwest::Client::new(), "NP");
+
+        // Page 1 is reached without fetching page 0 first
+        let second = piped.search_music("despacito", 1).await.unwrap();
+        assert_eq!(second.len(), 1);
+        assert_eq!(second[0].id, "JGwWNGJdvx8");
+        assert_eq!(second[0].duration, Duration::from_secs(0).to_string());
+
+        let first = piped.search_music("despacito", 0).await.unwrap();
+        assert_eq!(first.len(), 2);
+
+        // There is nothing after last page
+        assert!(piped.search_music("despacito", 2).await.unwrap().is_empty());
+        assert_eq!(piped.cursor.next.len(), 2);
+
+        // New query start over
+        piped.search_artist("fonsi", 0).await.unwrap();
+        assert_eq!(piped.cursor.next, vec![None]);
+    }
+
+    #[tokio::test]
+    async fn fetch_other_contents() {
+        let server = fixture_server().await;
+        let mut piped = Piped::new(&server, reqwest::Client::new(), "NP");
+
+        let trending = piped.trending_music().await.unwrap();
+        assert_eq!(trending.len(), 2);
+        assert_eq!(trending[1].duration, Duration::from_secs(237).to_string());
+
+        let playlist = piped
+            .playlist_content("PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI")
+            .await
+            .unwrap();
+        assert_eq!(playlist.len(), 2);
+        assert_eq!(playlist[0].artist, "Ed Sheeran");
+
+        let videos = piped
+            .channel_videos("UCxoq-PAQeAdk_zyg8YS0JqA")
+            .await
+            .unwrap();
+        assert_eq!(videos[0].id, "kJQP7kiw5Fk");
+
+        let playlists = piped
+            .channel_playlists("UCxoq-PAQeAdk_zyg8YS0JqA")
+            .await
+            .unwrap();
+        assert_eq!(
+            playlists,
+            vec![PlaylistUnit {
+                name: "Luis Fonsi Hits".to_string(),
+                id: "PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG".to_string(),
+                author: "Luis Fonsi".to_string(),
+                video_count: "25".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn cover_art_through_proxy() {
+        let server = fixture_server().await;
+        let mut piped = Piped::new(&server, reqwest::Client::new(), "NP");
+
+        let cover = piped.cover_art("kJQP7kiw5Fk").await.unwrap();
+        assert_eq!(cover, b"jpeg".to_vec());
+    }
+
+    #[test]
+    fn encode_query_value() {
+        assert_eq!(encode("lo-fi beats"), "lo-fi%20beats");
+        assert_eq!(encode("{\"id\":1}"), "%7B%22id%22%3A1%7D");
+    }
+}
@@ -0,0 +1,265 @@
+use crate::MusicUnit;
+use config::initilize::{STORAGE, TB_USER_PLAYLIST, TB_USER_PLAYLIST_MUSIC};
+use rusqlite::Connection;
+
+// Playlists created by user itself. Editing them is done by the ui and reading them back page by
+// page by `Fetcher::get_playlist_content` like any other playlist.
+// Every public function runs on STORAGE. Rest take the connection so that they can be tested
+
+// Return the id of user playlist with this name. Playlist is created if there is none
+pub fn get_or_create(name: &str) -> rusqlite::Result<i64> {
+    select_or_insert(&STORAGE.lock().unwrap(), name)
+}
+
+// Music is added at the end of playlist. Return 0 if music was already in playlist
+pub fn add_music(playlist_id: i64, music: &MusicUnit) -> rusqlite::Result<usize> {
+    insert_music(&STORAGE.lock().unwrap(), playlist_id, music)
+}
+
+// Fails if another playlist with same name already exists
+pub fn rename(playlist_id: i64, name: &str) -> rusqlite::Result<usize> {
+    update_name(&STORAGE.lock().unwrap(), playlist_id, name)
+}
+
+pub fn delete(playlist_id: i64) -> rusqlite::Result<()> {
+    delete_playlist(&mut STORAGE.lock().unwrap(), playlist_id)
+}
+
+pub fn remove_music(playlist_id: i64, music_id: &str) -> rusqlite::Result<usize> {
+    delete_music(&STORAGE.lock().unwrap(), playlist_id, music_id)
+}
+
+// Swap position of music with the one just above/below it in playlist and return the music it
+// was swapped with. None if there is nothing to swap with
+pub fn swap_with_neighbour(
+    playlist_id: i64,
+    music_id: &str,
+    up: bool,
+) -> rusqlite::Result<Option<MusicUnit>> {
+    swap_positions(&mut STORAGE.lock().unwrap(), playlist_id, music_id, up)
+}
+
+// Every music of playlist in it's order
+pub fn musics(playlist_id: i64) -> rusqlite::Result<Vec<MusicUnit>> {
+    select_musics(&STORAGE.lock().unwrap(), playlist_id)
+}
+
+fn select_or_insert(conn: &Connection, name: &str) -> rusqlite::Result<i64> {
+    let insert_query = format!(
+        "INSERT OR IGNORE INTO {tb_name} (name) VALUES (:name)",
+        tb_name = TB_USER_PLAYLIST
+    );
+    conn.execute(&insert_query, &[(":name", name)])?;
+
+    let select_query = format!(
+        "SELECT id FROM {tb_name} WHERE name = :name",
+        tb_name = TB_USER_PLAYLIST
+    );
+    conn.query_row(&select_query, &[(":name", name)], |row| row.get(0))
+}
+
+fn insert_music(conn: &Connection, playlist_id: i64, music: &MusicUnit) -> rusqlite::Result<usize> {
+    let query = format!(
+        "
+        INSERT OR IGNORE INTO {tb_name}
+        (playlist_id, position, id, title, author, duration)
+        VALUES
+        (
+            :playlist_id,
+            (SELECT IFNULL(MAX(position), 0) + 1 FROM {tb_name} WHERE playlist_id = :playlist_id),
+            :id, :title, :author, :duration
+        )
+    ",
+        tb_name = TB_USER_PLAYLIST_MUSIC
+    );
+    let args = rusqlite::named_params! {
+        ":playlist_id": playlist_id,
+        ":id": music.id,
+        ":title": music.name,
+        ":author": music.artist,
+        ":duration": music.duration,
+    };
+    conn.execute(&query, args)
+}
+
+fn update_name(conn: &Connection, playlist_id: i64, name: &str) -> rusqlite::Result<usize> {
+    let query = format!(
+        "UPDATE {tb_name} SET name = :name WHERE id = :id",
+        tb_name = TB_USER_PLAYLIST
+    );
+    conn.execute(
+        &query,
+        rusqlite::named_params! { ":name": name, ":id": playlist_id },
+    )
+}
+
+// Playlist and it's music are deleted together or not at all
+fn delete_playlist(conn: &mut Connection, playlist_id: i64) -> rusqlite::Result<()> {
+    let transaction = conn.transaction()?;
+    transaction.execute(
+        &format!(
+            "DELETE FROM {tb_name} WHERE playlist_id = :id",
+            tb_name = TB_USER_PLAYLIST_MUSIC
+        ),
+        &[(":id", &playlist_id)],
+    )?;
+    transaction.execute(
+        &format!(
+            "DELETE FROM {tb_name} WHERE id = :id",
+            tb_name = TB_USER_PLAYLIST
+        ),
+        &[(":id", &playlist_id)],
+    )?;
+    transaction.commit()
+}
+
+fn delete_music(conn: &Connection, playlist_id: i64, music_id: &str) -> rusqlite::Result<usize> {
+    let query = format!(
+        "DELETE FROM {tb_name} WHERE playlist_id = :playlist_id AND id = :id",
+        tb_name = TB_USER_PLAYLIST_MUSIC
+    );
+    conn.execute(
+        &query,
+        rusqlite::named_params! { ":playlist_id": playlist_id, ":id": music_id },
+    )
+}
+
+fn swap_positions(
+    conn: &mut Connection,
+    playlist_id: i64,
+    music_id: &str,
+    up: bool,
+) -> rusqlite::Result<Option<MusicUnit>> {
+    let (direction, order) = if up { ("<", "DESC") } else { (">", "ASC") };
+    let neighbour_query = format!(
+        "
+        SELECT
+        neighbour.position, neighbour.id, neighbour.title, neighbour.author, neighbour.duration,
+        current.position
+        FROM {tb_name} AS neighbour, {tb_name} AS current
+        WHERE current.playlist_id = :playlist_id AND current.id = :id
+        AND neighbour.playlist_id = :playlist_id
+        AND neighbour.position {direction} current.position
+        ORDER BY neighbour.position {order}
+        LIMIT 1
+    ",
+        tb_name = TB_USER_PLAYLIST_MUSIC,
+        direction = direction,
+        order = order
+    );
+    let update_query = format!(
+        "UPDATE {tb_name} SET position = :position WHERE playlist_id = :playlist_id AND id = :id",
+        tb_name = TB_USER_PLAYLIST_MUSIC
+    );
+
+    let transaction = conn.transaction()?;
+    let neighbour = transaction.query_row(
+        &neighbour_query,
+        rusqlite::named_params! { ":playlist_id": playlist_id, ":id": music_id },
+        |row| {
+            let music = MusicUnit {
+                id: row.get(1)?,
+                name: row.get(2)?,
+                artist: row.get(3)?,
+                duration: row.get(4)?,
+            };
+            Ok((row.get::<_, i64>(0)?, music, row.get::<_, i64>(5)?))
+        },
+    );
+    let (neighbour_position, neighbour, current_position) = match neighbour {
+        Ok(neighbour) => neighbour,
+        Err(rusqlite::Error::QueryReturnedNoRows) => return Ok(None),
+        Err(err) => return Err(err),
+    };
+
+    transaction.execute(
+        &update_query,
+        rusqlite::named_params! { ":position": neighbour_position, ":playlist_id": playlist_id, ":id": music_id },
+    )?;
+    transaction.execute(
+        &update_query,
+        rusqlite::named_params! { ":position": current_position, ":playlist_id": playlist_id, ":id": neighbour.id },
+    )?;
+    transaction.commit()?;
+
+    Ok(Some(neighbour))
+}
+
+fn select_musics(conn: &Connection, playlist_id: i64) -> rusqlite::Result<Vec<MusicUnit>> {
+    let query = format!(
+        "
+        SELECT id, title, author, duration
+        FROM {tb_name}
+        WHERE playlist_id = :playlist_id
+        ORDER BY position
+    ",
+        tb_name = TB_USER_PLAYLIST_MUSIC
+    );
+
+    let mut stmt = conn.prepare(&query)?;
+    let musics = stmt.query_map(&[(":playlist_id", &playlist_id)], |row| {
+        Ok(MusicUnit {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            artist: row.get(2)?,
+            duration: row.get(3)?,
+        })
+    })?;
+    Ok(musics.filter_map(|music| music.ok()).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_music;
+    use config::ConfigContainer;
+
+    fn playlist_musics(conn: &Connection, playlist_id: i64) -> Vec<String> {
+        select_musics(conn, playlist_id)
+            .unwrap()
+            .into_iter()
+            .map(|music| music.id)
+            .collect()
+    }
+
+    #[test]
+    fn user_playlist_queries() {
+        let mut conn = ConfigContainer::test_storage();
+
+        let id = select_or_insert(&conn, "Mix").unwrap();
+        assert_eq!(select_or_insert(&conn, "Mix").unwrap(), id);
+        let other = select_or_insert(&conn, "Other").unwrap();
+        assert_ne!(id, other);
+
+        for music_id in ["a", "b", "c"] {
+            assert_eq!(insert_music(&conn, id, &test_music(music_id)), Ok(1));
+        }
+        assert_eq!(insert_music(&conn, id, &test_music("a")), Ok(0));
+        insert_music(&conn, other, &test_music("a")).unwrap();
+        assert_eq!(playlist_musics(&conn, id), ["a", "b", "c"]);
+
+        // Name is unique
+        assert!(update_name(&conn, id, "Other").is_err());
+        update_name(&conn, id, "Renamed").unwrap();
+        assert_eq!(select_or_insert(&conn, "Renamed").unwrap(), id);
+
+        let swapped = swap_positions(&mut conn, id, "c", true).unwrap();
+        assert_eq!(swapped.map(|music| music.id), Some("b".to_string()));
+        assert_eq!(playlist_musics(&conn, id), ["a", "c", "b"]);
+        let swapped = swap_positions(&mut conn, id, "a", true).unwrap();
+        assert!(swapped.is_none());
+        swap_positions(&mut conn, id, "a", false).unwrap();
+        assert_eq!(playlist_musics(&conn, id), ["c", "a", "b"]);
+
+        delete_music(&conn, id, "a").unwrap();
+        assert_eq!(playlist_musics(&conn, id), ["c", "b"]);
+        // Music is appended after the last one even if there is gap in position
+        insert_music(&conn, id, &test_music("a")).unwrap();
+        assert_eq!(playlist_musics(&conn, id), ["c", "b", "a"]);
+
+        delete_playlist(&mut conn, id).unwrap();
+        assert!(playlist_musics(&conn, id).is_empty());
+        assert_eq!(playlist_musics(&conn, other), ["a"]);
+        assert_ne!(select_or_insert(&conn, "Renamed").unwrap(), id);
+    }
+}
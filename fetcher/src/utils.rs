@@ -1,18 +1,16 @@
-use crate::{Fetcher, ReturnAction};
+use crate::{history, ids, library, provider, FetchError, Fetcher, ReturnAction};
 use config::initilize::{
     CONFIG, STORAGE, TB_FAVOURATES_ARTIST, TB_FAVOURATES_MUSIC, TB_FAVOURATES_PLAYLIST,
+    TB_HISTORY_ARTIST, TB_HISTORY_MUSIC, TB_HISTORY_PLAYLIST, TB_LOCAL_MUSIC, TB_USER_PLAYLIST,
+    TB_USER_PLAYLIST_MUSIC,
 };
 use reqwest;
+use std::collections::{HashMap, HashSet};
 use std::iter::DoubleEndedIterator;
-use std::time::Duration;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/92.0.4515.131 Safari/537.36";
-const FIELDS: [&str; 3] = [
-    "videoId,title,author,lengthSeconds",
-    "title,playlistId,author,videoCount",
-    "author,authorId,videoCount",
-];
-const FILTER_TYPE: [&str; 3] = ["music", "playlist", "channel"];
+pub(crate) const USER_AGENT: &str = "Mozilla/5.0 (X11; Linux x86_64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/92.0.4515.131 Safari/537.36";
 
 impl crate::ExtendDuration for Duration {
     fn to_string(self) -> String {
@@ -61,26 +59,105 @@ impl crate::ExtendDuration for Duration {
     }
 }
 
+impl FetchError {
+    // Short message that fits in the status bar. For full detail use Display implementation
+    pub fn status_text(&self) -> &'static str {
+        match self {
+            FetchError::Network { cause, .. } if cause.is_timeout() => "Timed out..",
+            FetchError::Network { cause, .. } if cause.is_connect() => "Connect error..",
+            FetchError::Network { .. } => "Network error..",
+            FetchError::Status { status, .. } if status.as_u16() == 429 => "Rate limited..",
+            FetchError::Status { status, .. } if status.is_server_error() => "Server error..",
+            FetchError::Status { .. } => "HTTP error..",
+            FetchError::Parse { .. } => "Bad response..",
+            FetchError::File { .. } => "Read error..",
+            FetchError::Storage { .. } => "Storage error..",
+            FetchError::Task { .. } => "Task failed..",
+        }
+    }
+}
+
+impl std::fmt::Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::Network { server, cause } => {
+                write!(f, "Request to {} failed. Error: {}", server, cause)
+            }
+            FetchError::Status { server, status } => {
+                write!(f, "Server {} responded with status {}", server, status)
+            }
+            FetchError::Parse { server, cause } => {
+                write!(f, "Unexpected response from {}. Error: {}", server, cause)
+            }
+            FetchError::File { path, cause } => {
+                write!(f, "Cannot read file {}. Error: {}", path, cause)
+            }
+            FetchError::Storage { table, cause } => {
+                write!(f, "Cannot query {} from storage. Error: {}", table, cause)
+            }
+            FetchError::Task { job, cause } => {
+                write!(f, "{} did not finish. Error: {}", job, cause)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FetchError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            FetchError::Network { cause, .. } => Some(cause),
+            FetchError::Status { .. } => None,
+            FetchError::Parse { cause, .. } => Some(cause),
+            FetchError::File { cause, .. } => Some(cause),
+            FetchError::Storage { cause, .. } => Some(cause),
+            FetchError::Task { cause, .. } => Some(cause),
+        }
+    }
+}
+
 impl Default for Fetcher {
     fn default() -> Self {
+        let client = reqwest::ClientBuilder::default()
+            .user_agent(USER_AGENT)
+            .gzip(true)
+            .timeout(Duration::from_millis(
+                CONFIG.constants.server_time_out as u64,
+            ))
+            .build()
+            .unwrap();
+        let region: &'static str = &CONFIG.constants.region;
+
         super::Fetcher {
             trending_now: None,
             playlist_content: super::PlaylistRes::default(),
             artist_content: super::ArtistRes::default(),
             search_res: super::SearchRes::default(),
-            servers: &CONFIG.servers.list,
-            client: reqwest::ClientBuilder::default()
-                .user_agent(USER_AGENT)
-                .gzip(true)
-                .build()
-                .unwrap(),
+            providers: CONFIG
+                .servers
+                .list
+                .iter()
+                .map(|server| provider::from_server(server, client.clone(), region))
+                .collect(),
             active_server_index: 0,
-            region: &CONFIG.constants.region,
+            health: crate::health::HealthTracker::new(CONFIG.servers.list.len()),
             item_per_page: CONFIG.constants.item_per_list,
         }
     }
 }
 
+// Send the request with one of the provider and evaluate the result.
+// Provider is choosen by rotating the active server. See Fetcher::change_server
+// $method is the method of MusicProvider trait to call with following arguments
+macro_rules! request {
+    ($fetcher: expr, $retry_for: expr, $method: ident ( $($args: expr),* )) => {{
+        $fetcher.change_server();
+        let server_index = $fetcher.active_server_index;
+        let started_at = Instant::now();
+        let res = $fetcher.providers[server_index].$method($($args),*).await;
+        $fetcher.evaluate_response(server_index, started_at.elapsed(), res, $retry_for)
+    }};
+}
+
 macro_rules! search {
     ("music", $fetcher: expr, $query: expr, $page: expr) => {
         search!(
@@ -90,7 +167,7 @@ macro_rules! search {
             $page,
             $fetcher.search_res.music,
             0,
-            super::MusicUnit
+            search_music
         )
     };
     ("playlist", $fetcher: expr, $query: expr, $page: expr) => {
@@ -101,7 +178,7 @@ macro_rules! search {
             $page,
             $fetcher.search_res.playlist,
             1,
-            super::PlaylistUnit
+            search_playlist
         )
     };
     ("artist", $fetcher: expr, $query: expr, $page: expr) => {
@@ -112,19 +189,11 @@ macro_rules! search {
             $page,
             $fetcher.search_res.artist,
             2,
-            super::ArtistUnit
+            search_artist
         )
     };
 
-    ("@internal-core", $fetcher: expr, $query: expr, $page: expr, $store_target: expr, $filter_index: expr, $unit_type: ty) => {{
-        let suffix = format!(
-            "/search?q={query}&type={s_type}&{region}&page={page}&fields={fields}",
-            query = $query,
-            s_type = FILTER_TYPE[$filter_index],
-            region = $fetcher.region,
-            fields = FIELDS[$filter_index],
-            page = $page
-        );
+    ("@internal-core", $fetcher: expr, $query: expr, $page: expr, $store_target: expr, $filter_index: expr, $provider_method: ident) => {{
         let lower_limit = $page * $fetcher.item_per_page;
         let mut upper_limit =
             std::cmp::min($store_target.len(), lower_limit + $fetcher.item_per_page);
@@ -136,7 +205,7 @@ macro_rules! search {
 
         $fetcher.search_res.last_fetched = $filter_index;
         if is_new_query || insufficient_data || is_new_type {
-            let obj = $fetcher.send_request::<Vec<$unit_type>>(&suffix, 1).await;
+            let obj = request!($fetcher, 1, $provider_method($query, $page));
             if is_new_query || is_new_type {
                 $store_target.clear();
             }
@@ -161,36 +230,35 @@ macro_rules! search {
 
 impl Fetcher {
     pub fn change_server(&mut self) {
-        self.active_server_index = (self.active_server_index + 1) % self.servers.len();
+        self.active_server_index = self.health.next_server(self.active_server_index);
     }
 
-    // All the request should be send from this function
-    async fn send_request<'de, Res>(
+    // All the response from provider should be evaluated from this function.
+    // This records the health of server and converts the error to ReturnAction.
+    // Network failure is retried if `retry_for` is more than 0. Server is not changed
+    // here as `request!` rotates it again before retrying
+    fn evaluate_response<Res>(
         &mut self,
-        path: &str,
+        server_index: usize,
+        latency: Duration,
+        response: Result<Res, FetchError>,
         retry_for: i32,
-    ) -> Result<Res, ReturnAction>
-    where
-        Res: serde::de::DeserializeOwned,
-    {
-        self.change_server();
-
-        let url = self.servers[self.active_server_index].to_string() + path;
-        let res = self.client.get(url).send().await;
-
-        match res {
-            Ok(response) => {
-                if let Ok(obj) = response.json::<Res>().await {
-                    Ok(obj)
-                } else {
-                    Err(ReturnAction::Failed)
-                }
+    ) -> Result<Res, ReturnAction> {
+        match response {
+            Ok(obj) => {
+                self.health.record_success(server_index, latency);
+                Ok(obj)
             }
-            Err(_) if retry_for > 0 => {
-                self.change_server();
+            Err(FetchError::Network { .. }) if retry_for > 0 => {
+                self.health.record_failure(server_index);
                 Err(ReturnAction::Retry)
             }
-            Err(_) => Err(ReturnAction::Failed),
+            // A server that do not return expected format is probably running incompatible
+            // version so it is also treated as failure of that server
+            Err(err) => {
+                self.health.record_failure(server_index);
+                Err(ReturnAction::Failed(err))
+            }
         }
     }
 
@@ -201,13 +269,7 @@ impl Fetcher {
         let lower_limit = self.item_per_page * page;
 
         if self.trending_now.is_none() {
-            let suffix = format!(
-                "/trending?type=Music&region={region}&fields={music_field}",
-                region = self.region,
-                music_field = FIELDS[0]
-            );
-
-            let obj = self.send_request::<Vec<super::MusicUnit>>(&suffix, 2).await;
+            let obj = request!(self, 2, trending_music());
             match obj {
                 Ok(mut res) => {
                     res.shrink_to_fit();
@@ -232,24 +294,22 @@ impl Fetcher {
         playlist_id: &str,
         page: usize,
     ) -> Result<Vec<super::MusicUnit>, ReturnAction> {
+        // Content of user playlist may be edited anytime so it is always read from storage
+        if let Some(user_playlist_id) = ids::user_playlist_id(playlist_id) {
+            return self.get_user_playlist_content(user_playlist_id, page);
+        }
+
         let lower_limit = page * self.item_per_page;
 
         let is_new_id = *playlist_id != self.playlist_content.id;
         if is_new_id {
             self.playlist_content.id = playlist_id.to_string();
-            let suffix = format!(
-                "/playlists/{playlist_id}?fields=videos({music_field})",
-                playlist_id = playlist_id,
-                music_field = FIELDS[0]
-            );
-
-            let obj = self
-                .send_request::<super::FetchPlaylistContentRes>(&suffix, 1)
-                .await;
+
+            let obj = request!(self, 1, playlist_content(playlist_id));
             match obj {
                 Ok(mut data) => {
-                    data.videos.shrink_to_fit();
-                    self.playlist_content.music = data.videos;
+                    data.shrink_to_fit();
+                    self.playlist_content.music = data;
                 }
                 Err(e) => return Err(e),
             }
@@ -278,19 +338,12 @@ impl Fetcher {
         let is_new_id = *channel_id != self.artist_content.playlist.0;
         if is_new_id || self.artist_content.playlist.1.is_empty() {
             self.artist_content.playlist.0 = channel_id.to_string();
-            let suffix = format!(
-                "/channels/{channel_id}/playlists?fields=playlists({channel_fields})",
-                channel_id = channel_id,
-                channel_fields = FIELDS[1],
-            );
-
-            let obj = self
-                .send_request::<super::FetchArtistPlaylist>(&suffix, 1)
-                .await;
+
+            let obj = request!(self, 1, channel_playlists(channel_id));
             match obj {
                 Ok(mut data) => {
-                    data.playlists.shrink_to_fit();
-                    self.artist_content.playlist.1 = data.playlists;
+                    data.shrink_to_fit();
+                    self.artist_content.playlist.1 = data;
                 }
                 Err(e) => return Err(e),
             }
@@ -319,13 +372,8 @@ impl Fetcher {
         let is_new_id = *channel_id != self.artist_content.music.0;
         if is_new_id || self.artist_content.music.1.is_empty() {
             self.artist_content.music.0 = channel_id.to_string();
-            let suffix = format!(
-                "/channels/{channel_id}/videos&fields={music_field}",
-                channel_id = channel_id,
-                music_field = FIELDS[0]
-            );
 
-            let obj = self.send_request::<Vec<super::MusicUnit>>(&suffix, 1).await;
+            let obj = request!(self, 1, channel_videos(channel_id));
             match obj {
                 Ok(mut data) => {
                     data.shrink_to_fit();
@@ -369,12 +417,11 @@ impl Fetcher {
 
         let mut stmt = match conn.prepare(&query) {
             Ok(val) => val,
-            Err(err) => {
-                eprintln!(
-                    "Error preparing select statement for favourates music. Error: {err}",
-                    err = err
-                );
-                return Err(ReturnAction::Failed);
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_FAVOURATES_MUSIC,
+                    cause,
+                }));
             }
         };
 
@@ -388,12 +435,11 @@ impl Fetcher {
         });
 
         let res = match results {
-            Err(err) => {
-                eprintln!(
-                    "Cannot get results of favourates music. Error: {err}",
-                    err = err
-                );
-                return Err(ReturnAction::Failed);
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_FAVOURATES_MUSIC,
+                    cause,
+                }));
             }
             Ok(results) => {
                 let mut return_res: Vec<super::MusicUnit> = Vec::with_capacity(self.item_per_page);
@@ -433,12 +479,11 @@ impl Fetcher {
 
         let mut stmt = match conn.prepare(&query) {
             Ok(val) => val,
-            Err(err) => {
-                eprintln!(
-                    "Error preparing select statement for favourates playlist. Error: {err}",
-                    err = err
-                );
-                return Err(ReturnAction::Failed);
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_FAVOURATES_PLAYLIST,
+                    cause,
+                }));
             }
         };
 
@@ -452,12 +497,11 @@ impl Fetcher {
         });
 
         let res = match results {
-            Err(err) => {
-                eprintln!(
-                    "Cannot get results of favourates music. Error: {err}",
-                    err = err
-                );
-                return Err(ReturnAction::Failed);
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_FAVOURATES_PLAYLIST,
+                    cause,
+                }));
             }
             Ok(results) => {
                 let mut return_res: Vec<super::PlaylistUnit> =
@@ -498,12 +542,11 @@ impl Fetcher {
 
         let mut stmt = match conn.prepare(&query) {
             Ok(val) => val,
-            Err(err) => {
-                eprintln!(
-                    "Error preparing select statement for favourates artist. Error: {err}",
-                    err = err
-                );
-                return Err(ReturnAction::Failed);
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_FAVOURATES_ARTIST,
+                    cause,
+                }));
             }
         };
 
@@ -516,12 +559,11 @@ impl Fetcher {
         });
 
         let res = match results {
-            Err(err) => {
-                eprintln!(
-                    "Cannot get results of favourates artist. Error: {err}",
-                    err = err
-                );
-                return Err(ReturnAction::Failed);
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_FAVOURATES_ARTIST,
+                    cause,
+                }));
             }
             Ok(results) => {
                 let mut return_res: Vec<super::ArtistUnit> = Vec::with_capacity(self.item_per_page);
@@ -540,6 +582,320 @@ impl Fetcher {
         Ok(res)
     }
 
+    fn get_user_playlist_content(
+        &self,
+        user_playlist_id: i64,
+        page: usize,
+    ) -> Result<Vec<super::MusicUnit>, ReturnAction> {
+        let lower_limit = page * self.item_per_page;
+        let conn = STORAGE.lock().unwrap();
+
+        let query = format!(
+            "
+            SELECT
+            id, title, author, duration
+            FROM {tb_name}
+            WHERE playlist_id = :playlist_id
+            ORDER BY position
+            LIMIT {from}, {count}
+        ",
+            tb_name = TB_USER_PLAYLIST_MUSIC,
+            from = lower_limit,
+            count = self.item_per_page,
+        );
+
+        let mut stmt = match conn.prepare(&query) {
+            Ok(val) => val,
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_USER_PLAYLIST_MUSIC,
+                    cause,
+                }));
+            }
+        };
+
+        let results = stmt.query_map(&[(":playlist_id", &user_playlist_id)], |row| {
+            Ok(super::MusicUnit {
+                id: row.get(0).unwrap_or_default(),
+                name: row.get(1).unwrap_or("SQL_ERROR".into()),
+                artist: row.get(2).unwrap_or("SQL_ERROR".into()),
+                duration: row.get(3).unwrap_or("0:0".into()),
+            })
+        });
+
+        let res = match results {
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_USER_PLAYLIST_MUSIC,
+                    cause,
+                }));
+            }
+            Ok(results) => {
+                let mut return_res: Vec<super::MusicUnit> = Vec::with_capacity(self.item_per_page);
+                for music in results {
+                    return_res.push(music.unwrap());
+                }
+
+                return_res
+            }
+        };
+
+        if res.is_empty() {
+            return Err(ReturnAction::EOR);
+        }
+
+        Ok(res)
+    }
+
+    // Playlists shown in `My playlist`. Playlists created by user comes first followed by the
+    // youtube playlists added to favourates
+    pub async fn get_saved_playlist(
+        &mut self,
+        page: usize,
+    ) -> Result<Vec<super::PlaylistUnit>, ReturnAction> {
+        let lower_limit = page * self.item_per_page;
+        let conn = STORAGE.lock().unwrap();
+
+        let query = format!(
+            "
+            SELECT id, name, author, count FROM (
+                SELECT
+                '{prefix}' || user_playlist.id AS id, user_playlist.name AS name, 'You' AS author,
+                CAST((
+                    SELECT COUNT(*) FROM {tb_music} AS music
+                    WHERE music.playlist_id = user_playlist.id
+                ) AS TEXT) AS count,
+                0 AS kind, user_playlist.id AS ord
+                FROM {tb_user_playlist} AS user_playlist
+
+                UNION ALL
+
+                SELECT id, name, author, count, 1 AS kind, rowid AS ord
+                FROM {tb_fav_playlist}
+            )
+            ORDER BY kind, ord
+            LIMIT {from}, {count}
+        ",
+            prefix = ids::USER_PLAYLIST_PREFIX,
+            tb_music = TB_USER_PLAYLIST_MUSIC,
+            tb_user_playlist = TB_USER_PLAYLIST,
+            tb_fav_playlist = TB_FAVOURATES_PLAYLIST,
+            from = lower_limit,
+            count = self.item_per_page,
+        );
+
+        let mut stmt = match conn.prepare(&query) {
+            Ok(val) => val,
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_USER_PLAYLIST,
+                    cause,
+                }));
+            }
+        };
+
+        let results = stmt.query_map([], |row| {
+            Ok(super::PlaylistUnit {
+                id: row.get(0).unwrap_or_default(),
+                name: row.get(1).unwrap_or("SQL_ERROR".into()),
+                author: row.get(2).unwrap_or("SQL_ERROR".into()),
+                video_count: row.get(3).unwrap_or("0".into()),
+            })
+        });
+
+        let res = match results {
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_USER_PLAYLIST,
+                    cause,
+                }));
+            }
+            Ok(results) => {
+                let mut return_res: Vec<super::PlaylistUnit> =
+                    Vec::with_capacity(self.item_per_page);
+                for playlist in results {
+                    return_res.push(playlist.unwrap());
+                }
+
+                return_res
+            }
+        };
+
+        if res.is_empty() {
+            return Err(ReturnAction::EOR);
+        }
+
+        Ok(res)
+    }
+
+    // Recently played items are read from the history tables and are always sorted so that
+    // the most recently played item comes first. See history.rs
+    pub async fn get_recent_music(
+        &mut self,
+        page: usize,
+    ) -> Result<Vec<super::MusicUnit>, ReturnAction> {
+        let res = history::recent_music(
+            &STORAGE.lock().unwrap(),
+            page * self.item_per_page,
+            self.item_per_page,
+        )
+        .map_err(|cause| {
+            ReturnAction::Failed(FetchError::Storage {
+                table: TB_HISTORY_MUSIC,
+                cause,
+            })
+        })?;
+
+        if res.is_empty() {
+            return Err(ReturnAction::EOR);
+        }
+
+        Ok(res)
+    }
+
+    pub async fn get_recent_playlist(
+        &mut self,
+        page: usize,
+    ) -> Result<Vec<super::PlaylistUnit>, ReturnAction> {
+        let res = history::recent_playlist(
+            &STORAGE.lock().unwrap(),
+            page * self.item_per_page,
+            self.item_per_page,
+        )
+        .map_err(|cause| {
+            ReturnAction::Failed(FetchError::Storage {
+                table: TB_HISTORY_PLAYLIST,
+                cause,
+            })
+        })?;
+
+        if res.is_empty() {
+            return Err(ReturnAction::EOR);
+        }
+
+        Ok(res)
+    }
+
+    pub async fn get_recent_artist(
+        &mut self,
+        page: usize,
+    ) -> Result<Vec<super::ArtistUnit>, ReturnAction> {
+        let res = history::recent_artist(
+            &STORAGE.lock().unwrap(),
+            page * self.item_per_page,
+            self.item_per_page,
+        )
+        .map_err(|cause| {
+            ReturnAction::Failed(FetchError::Storage {
+                table: TB_HISTORY_ARTIST,
+                cause,
+            })
+        })?;
+
+        if res.is_empty() {
+            return Err(ReturnAction::EOR);
+        }
+
+        Ok(res)
+    }
+
+    // Thumbnail of video to write as cover art of downloaded music.
+    // It is fetched through the servers in config so youtube is never contacted directly
+    pub async fn get_cover_art(&mut self, video_id: &str) -> Result<Vec<u8>, ReturnAction> {
+        request!(self, 1, cover_art(video_id))
+    }
+
+    // Rescan the download path and library roots and bring local library table in sync.
+    // Walking directories and reading tags may take a while for large library so it is
+    // done in blocking thread and storage is only locked to read and write the table
+    pub async fn rescan_local_library(&self) -> Result<(), ReturnAction> {
+        let mut roots = vec![CONFIG.download.path.clone()];
+        roots.extend_from_slice(&CONFIG.download.library_roots);
+
+        let scanned = tokio::task::spawn_blocking(move || scan_local_library(&STORAGE, &roots))
+            .await
+            .map_err(|cause| {
+                ReturnAction::Failed(FetchError::Task {
+                    job: "Local library scan",
+                    cause,
+                })
+            })?;
+        scanned.map_err(|cause| {
+            ReturnAction::Failed(FetchError::Storage {
+                table: TB_LOCAL_MUSIC,
+                cause,
+            })
+        })
+    }
+
+    // Music in local library. When `search_term` is Some, only the music whose title or artist
+    // contains the term are returned. Library is not scanned here, see rescan_local_library
+    pub async fn get_local_music(
+        &mut self,
+        search_term: Option<&str>,
+        page: usize,
+    ) -> Result<Vec<super::MusicUnit>, ReturnAction> {
+        let lower_limit = page * self.item_per_page;
+        let conn = STORAGE.lock().unwrap();
+
+        let query = format!(
+            "
+            SELECT
+            id, title, author, duration
+            FROM {tb_name}
+            WHERE title LIKE :term ESCAPE '\\' OR author LIKE :term ESCAPE '\\'
+            ORDER BY author, title
+            LIMIT {from}, {count}
+        ",
+            tb_name = TB_LOCAL_MUSIC,
+            from = lower_limit,
+            count = self.item_per_page,
+        );
+        let term = format!("%{}%", escape_like(search_term.unwrap_or_default().trim()));
+
+        let mut stmt = match conn.prepare(&query) {
+            Ok(val) => val,
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_LOCAL_MUSIC,
+                    cause,
+                }));
+            }
+        };
+
+        let results = stmt.query_map(&[(":term", &term)], |row| {
+            Ok(super::MusicUnit {
+                id: row.get(0).unwrap_or_default(),
+                name: row.get(1).unwrap_or("SQL_ERROR".into()),
+                artist: row.get(2).unwrap_or("SQL_ERROR".into()),
+                duration: row.get(3).unwrap_or("0:0".into()),
+            })
+        });
+
+        let res = match results {
+            Err(cause) => {
+                return Err(ReturnAction::Failed(FetchError::Storage {
+                    table: TB_LOCAL_MUSIC,
+                    cause,
+                }));
+            }
+            Ok(results) => {
+                let mut return_res: Vec<super::MusicUnit> = Vec::with_capacity(self.item_per_page);
+                for music in results {
+                    return_res.push(music.unwrap());
+                }
+
+                return_res
+            }
+        };
+
+        if res.is_empty() {
+            return Err(ReturnAction::EOR);
+        }
+
+        Ok(res)
+    }
+
     pub async fn search_music(
         &mut self,
         query: &str,
@@ -564,3 +920,175 @@ impl Fetcher {
         search!("artist", self, query, page)
     }
 }
+
+// Term to match literally with LIKE ... ESCAPE '\'. Otherwise % and _ in term act as wildcard
+fn escape_like(term: &str) -> String {
+    let mut escaped = String::with_capacity(term.len());
+    for ch in term.chars() {
+        if matches!(ch, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+// Bring the local library table in sync with music files inside `roots`.
+// Tags are only read again from files that are new or modified since last scan and files
+// that no longer exist are removed from library. Tags are read before locking the storage
+// so that others are not blocked while reading the disk
+fn scan_local_library(
+    storage: &Mutex<rusqlite::Connection>,
+    roots: &[String],
+) -> Result<(), rusqlite::Error> {
+    let files = library::find_music_files(roots);
+
+    let known: HashMap<String, i64> = {
+        let conn = storage.lock().unwrap();
+        let query = format!(
+            "SELECT id, modified FROM {tb_name}",
+            tb_name = TB_LOCAL_MUSIC
+        );
+        let mut stmt = conn.prepare(&query)?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect::<Result<_, _>>()?
+    };
+
+    let changed: Vec<(super::MusicUnit, i64)> = files
+        .iter()
+        .filter(|file| known.get(&file.path) != Some(&file.modified))
+        .map(|file| (library::read_music(&file.path), file.modified))
+        .collect();
+    let found: HashSet<&str> = files.iter().map(|file| file.path.as_str()).collect();
+
+    let mut conn = storage.lock().unwrap();
+    // Whole scan is written in single transaction as it is much faster than
+    // committing every single file
+    let transaction = conn.transaction()?;
+    let insert_query = format!(
+        "
+        INSERT OR REPLACE INTO {tb_name}
+        (id, title, author, duration, modified)
+        VALUES
+        (:id, :title, :author, :duration, :modified)
+    ",
+        tb_name = TB_LOCAL_MUSIC
+    );
+    for (music, modified) in changed.iter() {
+        transaction.execute(
+            &insert_query,
+            rusqlite::named_params! {
+                ":id": music.id,
+                ":title": music.name,
+                ":author": music.artist,
+                ":duration": music.duration,
+                ":modified": modified,
+            },
+        )?;
+    }
+
+    let delete_query = format!(
+        "DELETE FROM {tb_name} WHERE id = :id",
+        tb_name = TB_LOCAL_MUSIC
+    );
+    for path in known.keys() {
+        if !found.contains(path.as_str()) {
+            transaction.execute(&delete_query, &[(":id", path)])?;
+        }
+    }
+
+    transaction.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use config::ConfigContainer;
+
+    fn library_ids(storage: &Mutex<rusqlite::Connection>) -> Vec<String> {
+        let conn = storage.lock().unwrap();
+        let query = format!("SELECT id FROM {} ORDER BY id", TB_LOCAL_MUSIC);
+        let mut stmt = conn.prepare(&query).unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn scan_adds_and_forgets_files() {
+        let dir = std::env::temp_dir().join("ytui_utils_scan");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let first = dir.join("first.mp3");
+        let second = dir.join("second.ogg");
+        std::fs::write(&first, []).unwrap();
+        std::fs::write(&second, []).unwrap();
+
+        let storage = Mutex::new(ConfigContainer::test_storage());
+        let roots = vec![dir.to_string_lossy().to_string()];
+        scan_local_library(&storage, &roots).unwrap();
+        assert_eq!(
+            library_ids(&storage),
+            vec![
+                first.to_string_lossy().to_string(),
+                second.to_string_lossy().to_string()
+            ]
+        );
+
+        std::fs::remove_file(&second).unwrap();
+        scan_local_library(&storage, &roots).unwrap();
+        assert_eq!(
+            library_ids(&storage),
+            vec![first.to_string_lossy().to_string()]
+        );
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+
+    #[test]
+    fn search_term_is_matched_literally() {
+        let conn = ConfigContainer::test_storage();
+        let matches = |value: &str, term: &str| -> bool {
+            let term = format!("%{}%", escape_like(term));
+            conn.query_row(
+                "SELECT :value LIKE :term ESCAPE '\\'",
+                &[(":value", value), (":term", &term)],
+                |row| row.get(0),
+            )
+            .unwrap()
+        };
+
+        assert!(matches("100% Pure", "100%"));
+        assert!(!matches("1000 Pure", "100%"));
+        assert!(matches("snake_case", "e_c"));
+        assert!(!matches("snakeXcase", "e_c"));
+        assert!(matches("back\\slash", "k\\s"));
+    }
+
+    #[test]
+    fn retry_goes_to_next_healthy_server() {
+        let mut fetcher = Fetcher {
+            trending_now: None,
+            playlist_content: super::super::PlaylistRes::default(),
+            artist_content: super::super::ArtistRes::default(),
+            search_res: super::super::SearchRes::default(),
+            providers: Vec::new(),
+            active_server_index: 0,
+            health: crate::health::HealthTracker::new(3),
+            item_per_page: 10,
+        };
+        // Same as `request!` does before sending every request
+        fetcher.change_server();
+        assert_eq!(fetcher.active_server_index, 1);
+
+        let cause = reqwest::Client::new().get("not a url").build().unwrap_err();
+        let failed: Result<(), _> = Err(FetchError::Network {
+            server: "first".to_string(),
+            cause,
+        });
+        let res = fetcher.evaluate_response(1, Duration::ZERO, failed, 1);
+        assert!(matches!(res, Err(ReturnAction::Retry)));
+
+        fetcher.change_server();
+        assert_eq!(fetcher.active_server_index, 2);
+    }
+}
@@ -1,13 +1,372 @@
-use config::initilize::CONFIG;
+use crate::ui;
+use config::initilize::{CONFIG, STORAGE};
 use reqwest;
-use serde::{self, Deserialize};
+use serde::{self, Deserialize, Serialize};
+use std::path::PathBuf;
 use tokio;
 
-#[derive(Default)]
+// Retry the fetcher call with another server when it asks to do so
+macro_rules! with_retry {
+    ($call: expr) => {{
+        let mut retry_left = 3;
+        loop {
+            match $call.await {
+                Err(fetcher::ReturnAction::Retry) if retry_left > 0 => retry_left -= 1,
+                res => break res,
+            }
+        }
+    }};
+}
+
+#[derive(Default, Debug)]
 pub struct Options {
     exec_name: String,
     sub_command: String,
+    // Positional arguments after sub command
     arguments: Vec<String>,
+    // Flags of the sub command with their value if it takes any. See `SubCommand::flags`
+    flags: Vec<(String, Option<String>)>,
+    // Global flags that override config for this run. See `GLOBAL_FLAGS`
+    overrides: config::Overrides,
+    // --help was passed
+    help: bool,
+}
+
+// A flag accepted in command line. Flag that takes value is given as `--flag value`
+// or `--flag=value`
+struct Flag {
+    name: &'static str,
+    // Placeholder of value shown in help. None if flag does not take any value
+    value: Option<&'static str>,
+    about: &'static str,
+}
+
+struct SubCommand {
+    name: &'static str,
+    // Positional arguments as shown in usage line
+    usage: &'static str,
+    about: &'static str,
+    // Possible values of first argument and what they do
+    arguments: &'static [(&'static str, &'static str)],
+    flags: &'static [Flag],
+}
+
+// These can be given anywhere in command line and work with every sub command
+const GLOBAL_FLAGS: [Flag; 5] = [
+    Flag {
+        name: "--config",
+        value: Some("<path>"),
+        about: "Read config from this file instead of config.json in config directory",
+    },
+    Flag {
+        name: "--region",
+        value: Some("<code>"),
+        about: "Region used to fetch trending music and search result. eg: US",
+    },
+    Flag {
+        name: "--server",
+        value: Some("<url>"),
+        about: "Use only this server instead of servers from config file.\nCan be given more than once. Prefix with piped+ for piped api server",
+    },
+    Flag {
+        name: "--no-mpv-config",
+        value: None,
+        about: "Do not load mpv.conf from config directory",
+    },
+    Flag {
+        name: "--log-file",
+        value: Some("<path>"),
+        about: "Write log to this file instead of ytui_music.log in config directory",
+    },
+];
+
+const ITEM_TYPES: &str = "<music|playlist|artist>";
+
+const DELETE_FLAGS: [Flag; 2] = [
+    Flag {
+        name: "--yes",
+        value: None,
+        about: "Do not ask for confirmation",
+    },
+    Flag {
+        name: "--backup",
+        value: None,
+        about: "Copy each file to <file>.<timestamp>.bak before deleting",
+    },
+];
+
+const SUB_COMMANDS: [SubCommand; 13] = [
+    SubCommand {
+        name: "run",
+        usage: "",
+        about: "Run ytui-music.",
+        arguments: &[],
+        flags: &[],
+    },
+    SubCommand {
+        name: "help",
+        usage: "[sub-command]",
+        about: "Show this help message or help of given sub command.",
+        arguments: &[],
+        flags: &[],
+    },
+    SubCommand {
+        name: "update",
+        usage: "",
+        about: "Update the ytui-music binary to latest version.\nThis will override the current executable so it may require root/admin permission\ndepending on current installation path.",
+        arguments: &[],
+        flags: &[],
+    },
+    SubCommand {
+        name: "delete",
+        usage: "<config|db>",
+        about: "Delete configuration/storage file from config directory.",
+        arguments: &[
+            ("config", "Delete config.json and mpv.conf. Run `info config` for their location.\nDefault config is generated on next run."),
+            ("db", "Delete the database storage. This will delete your saved data like favourates music."),
+        ],
+        flags: &DELETE_FLAGS,
+    },
+    SubCommand {
+        name: "reset",
+        usage: "[config|db]",
+        about: "Delete and generate default config and/or database storage.\nBoth are reset if nothing is given.",
+        arguments: &[],
+        flags: &DELETE_FLAGS,
+    },
+    SubCommand {
+        name: "servers",
+        usage: "<refresh|list>",
+        about: "Manage invidious servers used to fetch data.",
+        arguments: &[
+            ("refresh", "Discover servers from instances document defined in `Servers{discovery_url}`.\nDiscovered servers are saved in config directory and are used along with servers in\nconfig file."),
+            ("list", "Show all the servers currently in use."),
+        ],
+        flags: &[],
+    },
+    SubCommand {
+        name: "info",
+        usage: "<version|shortcuts|keys|config|ytui|about>",
+        about: "Get the information about passed argument.",
+        arguments: &[
+            ("version", "Show version of currently installed ytui-music binary."),
+            ("shortcuts", "Show the current shortcut keys with their action."),
+            ("keys", "Same as shortcuts"),
+            ("config", "Show information about configuration directory and file"),
+            ("ytui", "Show additional information about this software."),
+            ("about", "Same as ytui"),
+        ],
+        flags: &[],
+    },
+    SubCommand {
+        name: "search",
+        usage: "<query>",
+        about: "Search without starting the tui. One result is printed per line as tab separated\nid, title, artist/author and duration/count.",
+        arguments: &[],
+        flags: &[
+            Flag {
+                name: "--type",
+                value: Some(ITEM_TYPES),
+                about: "What to search for. Default is music",
+            },
+            Flag {
+                name: "--json",
+                value: None,
+                about: "Print result as json array instead",
+            },
+        ],
+    },
+    SubCommand {
+        name: "fav",
+        usage: "<list|add|remove> [music|playlist|artist] [id|url]",
+        about: "Manage favourates without starting the tui.",
+        arguments: &[
+            ("list", "Show favourates of given type. Default is music"),
+            ("add", "Add to favourates. Item is looked up in server by it's id. If it cannot be found\n--title (and optionally --author) is used instead."),
+            ("remove", "Remove from favourates."),
+        ],
+        flags: &[
+            Flag {
+                name: "--json",
+                value: None,
+                about: "Print favourates as json array. Only for list",
+            },
+            Flag {
+                name: "--title",
+                value: Some("<title>"),
+                about: "Title to use if item cannot be found. Only for add",
+            },
+            Flag {
+                name: "--author",
+                value: Some("<author>"),
+                about: "Artist/author to use if item cannot be found. Only for add",
+            },
+        ],
+    },
+    SubCommand {
+        name: "play",
+        usage: "<id|url|path>",
+        about: "Play without starting the tui. Title of each music is printed as it starts and\nreturns after everything is played. Id is of youtube video. Url can be of youtube\nvideo or playlist.",
+        arguments: &[],
+        flags: &[],
+    },
+    SubCommand {
+        name: "export",
+        usage: "",
+        about: "Export favourates. Json has favourate music, playlist and artist while m3u has only\nmusic and can be played by other players.",
+        arguments: &[],
+        flags: &[
+            Flag {
+                name: "--format",
+                value: Some("<json|m3u>"),
+                about: "Format of exported file. Default is json",
+            },
+            Flag {
+                name: "--output",
+                value: Some("<path>"),
+                about: "Write to this file instead of stdout",
+            },
+        ],
+    },
+    SubCommand {
+        name: "import",
+        usage: "<path|->",
+        about: "Add favourates from file exported by `export`. Use - to read from stdin.\nLocal files in m3u are added by their path. Subscriptions and playlists can also be\nimported from youtube takeout csv and newpipe subscription json as artist and playlist.",
+        arguments: &[],
+        flags: &[
+            Flag {
+                name: "--format",
+                value: Some("<json|m3u|takeout|newpipe>"),
+                about: "Format of the file. Default is guessed from extension and content",
+            },
+            Flag {
+                name: "--on-conflict",
+                value: Some("<skip|replace>"),
+                about: "What to do when item is already in favourates. Default is skip",
+            },
+            Flag {
+                name: "--dry-run",
+                value: None,
+                about: "Only show what would be added, replaced or skipped",
+            },
+        ],
+    },
+    SubCommand {
+        name: "ctl",
+        usage: "<command> [argument]",
+        about: "Control ytui-music that is already running. Only on unix like systems.",
+        arguments: &[
+            ("play", "Resume what is paused. Or play given <id|url|path> right away"),
+            ("pause", "Pause what is being played"),
+            ("next", "Play next music from queue"),
+            ("prev", "Play previous music from queue"),
+            ("seek", "Seek to given seconds. With + or - seek relative to current position"),
+            ("volume", "Set volume to given level. With + or - change it by given step"),
+            ("enqueue", "Add <id|url|path> to queue. Playlist url is added as whole"),
+            ("status", "Show what is being played as tab separated key and value"),
+        ],
+        flags: &[
+            Flag {
+                name: "--next",
+                value: None,
+                about: "Queue right after music being played instead of at the end. Only for enqueue",
+            },
+            Flag {
+                name: "--playlist",
+                value: None,
+                about: "Given id is of playlist. Only for enqueue",
+            },
+            Flag {
+                name: "--json",
+                value: None,
+                about: "Print player status as json after the command",
+            },
+        ],
+    },
+];
+
+impl SubCommand {
+    fn find(name: &str) -> Option<&'static SubCommand> {
+        SUB_COMMANDS.iter().find(|cmd| cmd.name == name)
+    }
+}
+
+fn find_flag<'f>(flags: &'f [Flag], name: &str) -> Option<&'f Flag> {
+    flags.iter().find(|flag| flag.name == name)
+}
+
+// Label and description are aligned in two columns. Description that does not fit in single
+// line is written with `\n` and continued in same column
+fn push_help_entry(help: &mut String, label: &str, about: &str) {
+    const LABEL_WIDTH: usize = 24;
+    let indent = " ".repeat(LABEL_WIDTH + 4);
+    let about = about.replace('\n', &format!("\n{}", indent));
+    if label.len() > LABEL_WIDTH {
+        help.push_str(&format!("  {}\n{}{}\n", label, indent, about));
+    } else {
+        help.push_str(&format!(
+            "  {:<width$}  {}\n",
+            label,
+            about,
+            width = LABEL_WIDTH
+        ));
+    }
+}
+
+fn flag_label(flag: &Flag) -> String {
+    match flag.value {
+        Some(value) => format!("{} {}", flag.name, value),
+        None => flag.name.to_string(),
+    }
+}
+
+fn help_text(exec_name: &str) -> String {
+    let mut help = format!(
+        "Usage: {} [flags] <sub-command> [arguments]\n\nSub commands:\n",
+        exec_name
+    );
+    for cmd in SUB_COMMANDS.iter() {
+        push_help_entry(&mut help, cmd.name, cmd.about);
+    }
+    help.push_str("\nFlags:\n");
+    for flag in GLOBAL_FLAGS.iter() {
+        push_help_entry(&mut help, &flag_label(flag), flag.about);
+    }
+    push_help_entry(
+        &mut help,
+        "-h, --help",
+        "Show help of sub command or this message",
+    );
+    help.push_str(&format!(
+        "\nRun `{} help <sub-command>` to see arguments and flags of a sub command.",
+        exec_name
+    ));
+    help
+}
+
+fn sub_command_help(exec_name: &str, cmd: &SubCommand) -> String {
+    let mut help = format!("Usage: {} {}", exec_name, cmd.name);
+    if !cmd.usage.is_empty() {
+        help.push_str(&format!(" {}", cmd.usage));
+    }
+    if !cmd.flags.is_empty() {
+        help.push_str(" [flags]");
+    }
+    help.push_str(&format!("\n\n{}\n", cmd.about));
+
+    if !cmd.arguments.is_empty() {
+        help.push_str("\nArguments:\n");
+        for (argument, about) in cmd.arguments {
+            push_help_entry(&mut help, argument, about);
+        }
+    }
+    if !cmd.flags.is_empty() {
+        help.push_str("\nFlags:\n");
+        for flag in cmd.flags {
+            push_help_entry(&mut help, &flag_label(flag), flag.about);
+        }
+    }
+    help
 }
 
 #[derive(Deserialize, Debug)]
@@ -22,8 +381,110 @@ struct ReleaseInfoResponse {
     assets: Vec<AssetOuter>,
 }
 
+// Type of item that search and fav sub commands work on
+#[derive(Clone, Copy, PartialEq)]
+enum ItemKind {
+    Music,
+    Playlist,
+    Artist,
+}
+
+impl ItemKind {
+    fn from_arg(arg: &str) -> Option<Self> {
+        match arg {
+            "music" => Some(ItemKind::Music),
+            "playlist" => Some(ItemKind::Playlist),
+            "artist" => Some(ItemKind::Artist),
+            _ => None,
+        }
+    }
+}
+
+// File format of export and import sub commands. Takeout and newpipe can only be imported
+#[derive(Clone, Copy, PartialEq)]
+enum FileFormat {
+    Json,
+    M3u,
+    Takeout,
+    NewPipe,
+}
+
+impl FileFormat {
+    fn from_arg(arg: &str) -> Option<Self> {
+        match arg {
+            "json" => Some(FileFormat::Json),
+            "m3u" | "m3u8" => Some(FileFormat::M3u),
+            "takeout" => Some(FileFormat::Takeout),
+            "newpipe" => Some(FileFormat::NewPipe),
+            _ => None,
+        }
+    }
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1)
+}
+
+// Print one item per line with fields separated by tab or everything as json array
+fn print_items<T: Serialize>(items: &[T], as_json: bool, to_line: impl Fn(&T) -> String) {
+    if as_json {
+        match serde_json::to_string(items) {
+            Ok(json) => println!("{}", json),
+            Err(err) => exit_with_error(&format!("Cannot serialize result. Error: {}", err)),
+        }
+    } else {
+        for item in items {
+            println!("{}", to_line(item));
+        }
+    }
+}
+
+fn music_line(music: &fetcher::MusicUnit) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        music.id, music.name, music.artist, music.duration
+    )
+}
+
+fn playlist_line(playlist: &fetcher::PlaylistUnit) -> String {
+    format!(
+        "{}\t{}\t{}\t{}",
+        playlist.id, playlist.name, playlist.author, playlist.video_count
+    )
+}
+
+fn artist_line(artist: &fetcher::ArtistUnit) -> String {
+    format!("{}\t{}\t{}", artist.id, artist.name, artist.video_count)
+}
+
+// Turn the result of fetcher into list. Nothing found is not an error for scripts
+fn fetched_or_exit<T>(res: Result<Vec<T>, fetcher::ReturnAction>) -> Vec<T> {
+    match res {
+        Ok(items) => items,
+        Err(fetcher::ReturnAction::EOR) => Vec::new(),
+        Err(fetcher::ReturnAction::Failed(err)) => {
+            exit_with_error(&format!("Cannot fetch result. Error: {}", err))
+        }
+        Err(fetcher::ReturnAction::Retry) => {
+            exit_with_error("Cannot fetch result. Every server tried have failed")
+        }
+    }
+}
+
+fn build_runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .expect("Cannot build tokio runtime")
+}
+
 impl Options {
-    pub fn create_from_args(mut arguments: std::env::Args) -> Result<Self, &'static str> {
+    // First non-flag argument is the sub command and rest are it's arguments.
+    // Global flags can be anywhere but flags of sub command must come after it.
+    // Everything after `--` is taken as argument even if it looks like a flag
+    pub fn create_from_args(arguments: impl IntoIterator<Item = String>) -> Result<Self, String> {
+        let mut arguments = arguments.into_iter();
         let mut result = Options {
             exec_name: "ytui_music".to_string(),
             ..Default::default()
@@ -31,16 +492,91 @@ impl Options {
         if let Some(exec_name) = arguments.next() {
             result.exec_name = exec_name;
         }
-        if let Some(cmd) = arguments.next() {
-            result.sub_command = cmd;
-        }
 
-        result.arguments = arguments.collect::<Vec<String>>();
+        let mut only_arguments = false;
+        while let Some(arg) = arguments.next() {
+            // Negative number is an argument. eg: `ctl seek -10`
+            let is_number = arg.parse::<f64>().is_ok();
+            if only_arguments || !arg.starts_with('-') || arg == "-" || is_number {
+                if result.sub_command.is_empty() {
+                    result.sub_command = arg;
+                } else {
+                    result.arguments.push(arg);
+                }
+                continue;
+            }
+            if arg == "--" {
+                only_arguments = true;
+                continue;
+            }
+            if arg == "-h" || arg == "--help" {
+                result.help = true;
+                continue;
+            }
+
+            let (name, inline_value) = match arg.split_once('=') {
+                Some((name, value)) => (name.to_string(), Some(value.to_string())),
+                None => (arg, None),
+            };
+            let (flag, is_global) = match find_flag(&GLOBAL_FLAGS, &name) {
+                Some(flag) => (flag, true),
+                None => match SubCommand::find(&result.sub_command)
+                    .and_then(|cmd| find_flag(cmd.flags, &name))
+                {
+                    Some(flag) => (flag, false),
+                    None => {
+                        return Err(format!(
+                            "Unknown flag {}. See `{} --help`",
+                            name, result.exec_name
+                        ))
+                    }
+                },
+            };
+            let value = match (flag.value, inline_value) {
+                (None, None) => None,
+                (None, Some(_)) => return Err(format!("{} does not take any value", name)),
+                (Some(_), Some(value)) => Some(value),
+                (Some(_), None) => match arguments.next() {
+                    Some(value) => Some(value),
+                    None => return Err(format!("Missing value for {}", name)),
+                },
+            };
+
+            if !is_global {
+                result.flags.push((name, value));
+                continue;
+            }
+            let overrides = &mut result.overrides;
+            match (flag.name, value) {
+                ("--config", Some(path)) => overrides.config_file = Some(PathBuf::from(path)),
+                ("--region", Some(region)) => overrides.region = Some(region),
+                ("--server", Some(server)) => overrides.servers.push(server),
+                ("--no-mpv-config", _) => overrides.no_mpv_config = true,
+                ("--log-file", Some(path)) => overrides.log_file = Some(PathBuf::from(path)),
+                _ => unreachable!("Every global flag should be handled"),
+            }
+        }
 
         Ok(result)
     }
 
+    fn has_flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|(flag, _)| flag == name)
+    }
+
+    // Value of flag. If flag is given more than once last one is taken
+    fn flag_value(&self, name: &str) -> Option<&str> {
+        self.flags
+            .iter()
+            .rev()
+            .find(|(flag, _)| flag == name)
+            .and_then(|(_, value)| value.as_deref())
+    }
+
     pub fn evaluate(self) -> bool {
+        // Must be set before config is read for the first time
+        *config::initilize::OVERRIDES.lock().unwrap() = self.overrides.clone();
+
         let ascii_art = r##"
 __   ___         _                           _
 \ \ / / |_ _   _(_)      _ __ ___  _   _ ___(_) ___
@@ -52,10 +588,21 @@ __   ___         _                           _
             "CARGO_PKG_AUTHORS",
             "Sudip Ghimire <sudipghimire533@gmail.com>"
         );
-        println!("{}\nAuthor(s): {}\n", ascii_art, author);
+        // Output of these sub commands are meant to be read by other programs
+        let is_scripting = matches!(
+            self.sub_command.as_str(),
+            "search" | "fav" | "play" | "export" | "import" | "ctl"
+        );
+        if !is_scripting {
+            println!("{}\nAuthor(s): {}\n", ascii_art, author);
+        }
+        if self.help {
+            self.show_help();
+            return false;
+        }
 
         let mut should_continue = false;
-        match self.sub_command.trim() {
+        match self.sub_command.as_str() {
             "run" => {
                 self.initialize_globals();
                 should_continue = true;
@@ -70,6 +617,30 @@ __   ___         _                           _
                 _ => self.show_help(),
             },
 
+            "reset" => self.reset(),
+
+            "servers" => match self.arguments.first().map(String::as_str) {
+                Some("refresh") => self.refresh_servers(),
+                Some("list") => self.list_servers(),
+                _ => self.show_help(),
+            },
+
+            "search" => self.search(),
+
+            "fav" => match self.arguments.first().map(String::as_str) {
+                Some("list") => self.list_favourates(),
+                Some("add") => self.add_favourate(),
+                Some("remove") => self.remove_favourate(),
+                _ => self.show_help(),
+            },
+
+            "play" => self.play(),
+
+            "export" => self.export(),
+            "import" => self.import(),
+
+            "ctl" => self.ctl(),
+
             "info" => match &self.arguments.first() {
                 Some(arg) => match arg.as_str() {
                     "version" => self.show_version(),
@@ -125,11 +696,32 @@ __   ___         _                           _
             quit = keys.quit,
             v_inc = keys.vol_increase,
             v_dec = keys.vol_decrease,
+            p_edit = keys.playlist_edit,
+            m_up = keys.move_up,
+            m_down = keys.move_down,
+            p_next = keys.play_next,
+            q_add = keys.queue_append,
+            dl_show = keys.show_downloads,
+            dl_sync = keys.sync_playlist,
         );
     }
 
+    // Help of sub command if it is known. `help <sub-command>` also shows the same
     pub fn show_help(self) {
-        println!(include_str!("help_message.txt"));
+        let name = match self.sub_command.as_str() {
+            "help" => self
+                .arguments
+                .first()
+                .map(String::as_str)
+                .unwrap_or_default(),
+            name => name,
+        };
+        match SubCommand::find(name) {
+            Some(cmd) if cmd.name != "help" => {
+                println!("{}", sub_command_help(&self.exec_name, cmd))
+            }
+            _ => println!("{}", help_text(&self.exec_name)),
+        }
     }
 
     pub fn initialize_globals(&self) {
@@ -290,13 +882,840 @@ __   ___         _                           _
         Some(binary)
     }
 
+    pub fn list_servers(self) {
+        for server in CONFIG.servers.list.iter() {
+            println!("{}", server);
+        }
+    }
+
+    pub fn refresh_servers(self) {
+        let source = &CONFIG.servers.discovery_url;
+        println!(
+            "Discovering invidious servers from {}. Please wait...",
+            source
+        );
+
+        let discovered = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("Cannot build tokio runtime to discover servers")
+            .block_on(fetcher::discovery::discover_servers(
+                source,
+                &CONFIG.servers.min_version,
+            ));
+
+        match discovered {
+            Err(err) => {
+                eprintln!("Cannot refresh server list. Error: {}", err);
+            }
+            Ok(servers) if servers.is_empty() => {
+                eprintln!("No usable server found. Previously discovered servers are kept as is.");
+            }
+            Ok(servers) => match config::ConfigContainer::cache_discovered_servers(&servers) {
+                Some(_) => {
+                    println!(
+                        "Found {} servers. These will be used along with servers in config file.",
+                        servers.len()
+                    );
+                }
+                None => {
+                    eprintln!("Cannot save discovered servers. Server list is not refreshed.");
+                }
+            },
+        }
+    }
+
+    // Ask user before doing something that cannot be undone. --yes answers it already
+    fn confirm(&self, question: &str) -> bool {
+        if self.has_flag("--yes") {
+            return true;
+        }
+
+        eprintln!("{} [yes/no]", question);
+        let mut response = String::new();
+        if std::io::stdin().read_line(&mut response).is_err() {
+            return false;
+        }
+        matches!(
+            response.trim().to_ascii_lowercase().as_str(),
+            "yes" | "y" | "yeah" | "yep"
+        )
+    }
+
+    // Delete those of given files that exists after confirmation. Returns false if user said no.
+    // With --backup every file is backed up first and nothing is deleted if any backup fails
+    fn delete_files(&self, files: Vec<PathBuf>) -> bool {
+        let files: Vec<PathBuf> = files.into_iter().filter(|file| file.exists()).collect();
+        if files.is_empty() {
+            println!("Nothing to delete.");
+            return true;
+        }
+
+        let file_list = files
+            .iter()
+            .map(|file| file.to_string_lossy())
+            .collect::<Vec<_>>()
+            .join(", ");
+        if !self.confirm(&format!("Delete {}?", file_list)) {
+            println!("Nothing is deleted.");
+            return false;
+        }
+
+        if self.has_flag("--backup") {
+            for file in files.iter() {
+                match config::ConfigContainer::backup_file(file) {
+                    Ok(backup) => println!("Backed up to {}", backup.to_string_lossy()),
+                    Err(err) => exit_with_error(&format!(
+                        "Cannot backup {}. Nothing is deleted. Error: {}",
+                        file.to_string_lossy(),
+                        err
+                    )),
+                }
+            }
+        }
+        for file in files.iter() {
+            match std::fs::remove_file(file) {
+                Ok(_) => println!("Deleted {}", file.to_string_lossy()),
+                Err(err) => exit_with_error(&format!(
+                    "Cannot delete {}. Error: {}",
+                    file.to_string_lossy(),
+                    err
+                )),
+            }
+        }
+        true
+    }
+
     pub fn delete_config(self) {
-        eprintln!("This function is currently unimplented.");
-        eprintln!("You may try to manually delete config.json and mpv.conf file under ytui_music directory in config directory");
+        match config::ConfigContainer::get_config_files() {
+            Some(files) => {
+                self.delete_files(files);
+            }
+            None => exit_with_error("Cannot locate config directory."),
+        }
     }
 
     pub fn delete_db(self) {
-        eprintln!("This function is currently unimplented.");
-        eprintln!("You may try to manually delete storage.db3 file under ytui_music directory in config directory");
+        match config::ConfigContainer::get_db_path() {
+            Some(db_path) => {
+                self.delete_files(vec![db_path]);
+            }
+            None => exit_with_error("Cannot locate config directory."),
+        }
+    }
+
+    pub fn reset(self) {
+        let (reset_config, reset_db) = match self.arguments.first().map(String::as_str) {
+            None => (true, true),
+            Some("config") => (true, false),
+            Some("db") => (false, true),
+            Some(_) => return self.show_help(),
+        };
+
+        let mut files = Vec::new();
+        if reset_config {
+            files.extend(config::ConfigContainer::get_config_files().unwrap_or_default());
+        }
+        if reset_db {
+            files.extend(config::ConfigContainer::get_db_path());
+        }
+        if files.is_empty() {
+            exit_with_error("Cannot locate config directory.");
+        }
+        if !self.delete_files(files) {
+            return;
+        }
+
+        // Both are generated with default values when they do not exists
+        if reset_config {
+            match config::ConfigContainer::give_me_config() {
+                Some(_) => println!("Default config is generated."),
+                None => exit_with_error("Cannot generate default config."),
+            }
+        }
+        if reset_db {
+            match config::ConfigContainer::give_me_storage() {
+                Some(_) => println!("Empty database storage is created."),
+                None => exit_with_error("Cannot create database storage."),
+            }
+        }
+    }
+}
+
+// Sub commands that do not start the tui. Output is kept plain so that they can be used
+// from shell scripts and status bars
+impl Options {
+    pub fn search(self) {
+        let as_json = self.has_flag("--json");
+        let kind = match self.flag_value("--type") {
+            None => ItemKind::Music,
+            Some(kind) => ItemKind::from_arg(kind).unwrap_or_else(|| {
+                exit_with_error("--type must be one of music, playlist or artist")
+            }),
+        };
+        let query = self.arguments.join(" ");
+        if query.trim().is_empty() {
+            exit_with_error(
+                "Usage: ytui_music search <query> [--type music|playlist|artist] [--json]",
+            );
+        }
+
+        self.initialize_globals();
+        build_runtime().block_on(async move {
+            let mut fetcher = fetcher::Fetcher::default();
+            match kind {
+                ItemKind::Music => {
+                    let items = fetched_or_exit(with_retry!(fetcher.search_music(&query, 0)));
+                    print_items(&items, as_json, music_line);
+                }
+                ItemKind::Playlist => {
+                    let items = fetched_or_exit(with_retry!(fetcher.search_playlist(&query, 0)));
+                    print_items(&items, as_json, playlist_line);
+                }
+                ItemKind::Artist => {
+                    let items = fetched_or_exit(with_retry!(fetcher.search_artist(&query, 0)));
+                    print_items(&items, as_json, artist_line);
+                }
+            }
+        });
+    }
+
+    // fav list [music|playlist|artist] [--json]
+    pub fn list_favourates(self) {
+        let as_json = self.has_flag("--json");
+        let kind = match self.arguments.get(1) {
+            None => ItemKind::Music,
+            Some(kind) => ItemKind::from_arg(kind).unwrap_or_else(|| {
+                exit_with_error("Usage: ytui_music fav list [music|playlist|artist] [--json]")
+            }),
+        };
+
+        self.initialize_globals();
+        // Favourates are read page by page like any other source. Read until the end
+        macro_rules! read_all {
+            ($fetcher: expr, $method: ident) => {{
+                let mut items = Vec::new();
+                let mut page = 0;
+                loop {
+                    match $fetcher.$method(page).await {
+                        Ok(data) => items.extend(data),
+                        res => {
+                            items.extend(fetched_or_exit(res));
+                            break;
+                        }
+                    }
+                    page += 1;
+                }
+                items
+            }};
+        }
+        build_runtime().block_on(async move {
+            let mut fetcher = fetcher::Fetcher::default();
+            match kind {
+                ItemKind::Music => {
+                    let items = read_all!(fetcher, get_favourates_music);
+                    print_items(&items, as_json, music_line);
+                }
+                ItemKind::Playlist => {
+                    let items = read_all!(fetcher, get_favourates_playlist);
+                    print_items(&items, as_json, playlist_line);
+                }
+                ItemKind::Artist => {
+                    let items = read_all!(fetcher, get_favourates_artist);
+                    print_items(&items, as_json, artist_line);
+                }
+            }
+        });
+    }
+
+    // fav add <music|playlist|artist> <id|url> [--title <title>] [--author <author>]
+    // There is no way to ask server about a single item. So it is searched by it's id and
+    // exact match is taken. If server cannot find it, --title and --author are used instead
+    pub fn add_favourate(self) {
+        let usage = "Usage: ytui_music fav add <music|playlist|artist> <id|url> [--title <title>] [--author <author>]";
+        let title = self.flag_value("--title").map(str::to_string);
+        let author = self.flag_value("--author").map(str::to_string);
+        let (kind, id) = match (self.arguments.get(1), self.arguments.get(2)) {
+            (Some(kind), Some(id)) => match ItemKind::from_arg(kind) {
+                Some(kind) => (kind, fetcher::ids::id_from_arg(id)),
+                None => exit_with_error(usage),
+            },
+            _ => exit_with_error(usage),
+        };
+
+        self.initialize_globals();
+        let not_found = || -> ! {
+            exit_with_error(&format!(
+                "Cannot find {}. Pass --title to add it anyway",
+                id
+            ))
+        };
+        let res = build_runtime().block_on(async {
+            let mut fetcher = fetcher::Fetcher::default();
+            match kind {
+                ItemKind::Music => {
+                    let found = with_retry!(fetcher.search_music(&id, 0))
+                        .ok()
+                        .and_then(|items| items.into_iter().find(|item| item.id == id));
+                    let music = match (found, &title) {
+                        (Some(music), _) => music,
+                        (None, Some(title)) => fetcher::MusicUnit {
+                            id: id.clone(),
+                            name: title.clone(),
+                            artist: author.clone().unwrap_or_default(),
+                            duration: String::from("-"),
+                        },
+                        (None, None) => not_found(),
+                    };
+                    fetcher::favourates::add_music(&music).map(|_| music.name)
+                }
+                ItemKind::Playlist => {
+                    let found = with_retry!(fetcher.search_playlist(&id, 0))
+                        .ok()
+                        .and_then(|items| items.into_iter().find(|item| item.id == id));
+                    let playlist = match (found, &title) {
+                        (Some(playlist), _) => playlist,
+                        (None, Some(title)) => fetcher::PlaylistUnit {
+                            id: id.clone(),
+                            name: title.clone(),
+                            author: author.clone().unwrap_or_default(),
+                            video_count: String::from("-"),
+                        },
+                        (None, None) => not_found(),
+                    };
+                    fetcher::favourates::add_playlist(&playlist).map(|_| playlist.name)
+                }
+                ItemKind::Artist => {
+                    let found = with_retry!(fetcher.search_artist(&id, 0))
+                        .ok()
+                        .and_then(|items| items.into_iter().find(|item| item.id == id));
+                    let artist = match (found, &title) {
+                        (Some(artist), _) => artist,
+                        (None, Some(title)) => fetcher::ArtistUnit {
+                            id: id.clone(),
+                            name: title.clone(),
+                            video_count: String::from("-"),
+                        },
+                        (None, None) => not_found(),
+                    };
+                    fetcher::favourates::add_artist(&artist).map(|_| artist.name)
+                }
+            }
+        });
+
+        match res {
+            Ok(name) => println!("Added {} to favourates", name),
+            Err(err) => exit_with_error(&format!("Cannot add to favourates. Error: {}", err)),
+        }
+    }
+
+    // fav remove <music|playlist|artist> <id|url>
+    pub fn remove_favourate(self) {
+        let usage = "Usage: ytui_music fav remove <music|playlist|artist> <id|url>";
+        let (kind, id) = match (self.arguments.get(1), self.arguments.get(2)) {
+            (Some(kind), Some(id)) => match ItemKind::from_arg(kind) {
+                Some(kind) => (kind, fetcher::ids::id_from_arg(id)),
+                None => exit_with_error(usage),
+            },
+            _ => exit_with_error(usage),
+        };
+
+        self.initialize_globals();
+        let res = match kind {
+            ItemKind::Music => fetcher::favourates::remove_music(&id),
+            ItemKind::Playlist => fetcher::favourates::remove_playlist(&id),
+            ItemKind::Artist => fetcher::favourates::remove_artist(&id),
+        };
+        match res {
+            Ok(0) => exit_with_error(&format!("{} is not in favourates", id)),
+            Ok(_) => println!("Removed {} from favourates", id),
+            Err(err) => exit_with_error(&format!("Cannot remove from favourates. Error: {}", err)),
+        }
+    }
+
+    // play <id|url|path>
+    // Title of every music is printed as it starts. Returns when everything is played
+    pub fn play(self) {
+        let target = match self.arguments.first() {
+            Some(target) => target.clone(),
+            None => exit_with_error("Usage: ytui_music play <id|url|path>"),
+        };
+        let url = if target.starts_with("http://") || target.starts_with("https://") {
+            target.clone()
+        } else if let Ok(path) = std::fs::canonicalize(&target) {
+            path.to_string_lossy().into_owned()
+        } else if fetcher::ids::is_playlist_id(&target) {
+            fetcher::ids::playlist_url(&target)
+        } else {
+            fetcher::library::music_url(&target)
+        };
+
+        self.initialize_globals();
+        let player = ui::player::from_config().unwrap_or_else(|err| {
+            exit_with_error(&format!("Cannot initilize mpv. Error: {}", err))
+        });
+
+        let mut events = player.events();
+        if let Err(err) = player.load(&url) {
+            // Exiting does not drop anything. mpv run by ipc backend would keep running
+            std::mem::drop(events);
+            std::mem::drop(player);
+            exit_with_error(&format!("Cannot play {}. Error: {}", target, err));
+        }
+
+        // Player become idle when nothing is left to play
+        let mut started = false;
+        let mut played_any = false;
+        loop {
+            match events.wait_event(-1.0) {
+                Some(ui::playback::PlayerEvent::Started) => started = true,
+                Some(ui::playback::PlayerEvent::Idle(true)) if started => break,
+                Some(ui::playback::PlayerEvent::Loaded) => {
+                    played_any = true;
+                    if let Some(title) = player.title() {
+                        println!("{}", title);
+                    }
+                }
+                Some(ui::playback::PlayerEvent::Shutdown) => break,
+                _ => {}
+            }
+        }
+        // mpv run by ipc backend is stopped on drop. Exiting below would leave it running
+        std::mem::drop(events);
+        std::mem::drop(player);
+
+        if !played_any {
+            exit_with_error(&format!("Cannot play {}", target));
+        }
+    }
+
+    fn format_flag(&self) -> Option<FileFormat> {
+        self.flag_value("--format").map(|format| {
+            FileFormat::from_arg(format).unwrap_or_else(|| {
+                exit_with_error("--format must be one of json, m3u, takeout or newpipe")
+            })
+        })
+    }
+
+    // export [--format json|m3u] [--output <path>]
+    pub fn export(self) {
+        let format = self.format_flag().unwrap_or(FileFormat::Json);
+
+        self.initialize_globals();
+        let favourates = fetcher::favourates::read_all().unwrap_or_else(|err| {
+            exit_with_error(&format!("Cannot read favourates. Error: {}", err))
+        });
+        let content = match format {
+            FileFormat::Json => match serde_json::to_string_pretty(&favourates) {
+                Ok(json) => json + "\n",
+                Err(err) => {
+                    exit_with_error(&format!("Cannot serialize favourates. Error: {}", err))
+                }
+            },
+            FileFormat::M3u => fetcher::favourates::to_m3u(&favourates.music),
+            FileFormat::Takeout | FileFormat::NewPipe => {
+                exit_with_error("Only json and m3u can be exported")
+            }
+        };
+
+        match self.flag_value("--output") {
+            None => print!("{}", content),
+            Some(path) => match std::fs::write(path, content) {
+                // Keep stderr so that stdout only ever has exported content
+                Ok(_) => eprintln!("Exported favourates to {}", path),
+                Err(err) => exit_with_error(&format!("Cannot write to {}. Error: {}", path, err)),
+            },
+        }
+    }
+
+    // import <path|-> [--format json|m3u|takeout|newpipe] [--on-conflict skip|replace] [--dry-run]
+    pub fn import(self) {
+        let usage = "Usage: ytui_music import <path|-> [--format json|m3u|takeout|newpipe] [--on-conflict skip|replace] [--dry-run]";
+        let source = match self.arguments.first() {
+            Some(source) => source.as_str(),
+            None => exit_with_error(usage),
+        };
+        let on_conflict = match self.flag_value("--on-conflict") {
+            None | Some("skip") => fetcher::favourates::OnConflict::Skip,
+            Some("replace") => fetcher::favourates::OnConflict::Replace,
+            Some(_) => exit_with_error("--on-conflict must be either skip or replace"),
+        };
+
+        let read = if source == "-" {
+            let mut content = String::new();
+            std::io::Read::read_to_string(&mut std::io::stdin(), &mut content).map(|_| content)
+        } else {
+            std::fs::read_to_string(source)
+        };
+        let content = read.unwrap_or_else(|err| {
+            exit_with_error(&format!("Cannot read {}. Error: {}", source, err))
+        });
+
+        let format = self.format_flag().unwrap_or_else(|| {
+            let is_m3u_file = source.ends_with(".m3u") || source.ends_with(".m3u8");
+            if is_m3u_file || content.trim_start().starts_with("#EXTM3U") {
+                FileFormat::M3u
+            } else if source.ends_with(".csv") || fetcher::external::is_takeout_csv(&content) {
+                FileFormat::Takeout
+            } else if fetcher::external::is_newpipe_json(&content) {
+                FileFormat::NewPipe
+            } else {
+                FileFormat::Json
+            }
+        });
+        let favourates =
+            match format {
+                FileFormat::Json => serde_json::from_str(&content).unwrap_or_else(|err| {
+                    exit_with_error(&format!("{} is not a valid export. Error: {}", source, err))
+                }),
+                FileFormat::M3u => {
+                    // Relative path in m3u is relative to the m3u file itself
+                    let base_dir = if source == "-" {
+                        std::env::current_dir().ok()
+                    } else {
+                        std::fs::canonicalize(source)
+                            .ok()
+                            .and_then(|path| path.parent().map(|dir| dir.to_path_buf()))
+                    };
+                    fetcher::favourates::Favourates {
+                        music: fetcher::favourates::from_m3u(&content, base_dir.as_deref()),
+                        ..Default::default()
+                    }
+                }
+                FileFormat::Takeout => fetcher::external::from_takeout_csv(&content)
+                    .unwrap_or_else(|err| {
+                        exit_with_error(&format!("{} is not a valid csv. Error: {}", source, err))
+                    }),
+                FileFormat::NewPipe => fetcher::external::from_newpipe_json(&content)
+                    .unwrap_or_else(|err| {
+                        exit_with_error(&format!(
+                            "{} is not a valid newpipe export. Error: {}",
+                            source, err
+                        ))
+                    }),
+            };
+
+        self.initialize_globals();
+        if self.has_flag("--dry-run") {
+            return Self::preview_import(favourates, on_conflict);
+        }
+        let mut conn = STORAGE.lock().unwrap();
+        match fetcher::favourates::import(&mut conn, favourates, on_conflict) {
+            Ok(report) => println!(
+                "Added {}, replaced {} and skipped {} already existing favourates",
+                report.added, report.replaced, report.skipped
+            ),
+            Err(err) => exit_with_error(&format!(
+                "Cannot import favourates. Nothing is imported. Error: {}",
+                err
+            )),
+        }
+    }
+
+    // Print what import would do with every item, one per line as tab separated action, type
+    // and the item itself. Nothing is saved
+    fn preview_import(
+        favourates: fetcher::favourates::Favourates,
+        on_conflict: fetcher::favourates::OnConflict,
+    ) {
+        let (new, existing) = fetcher::favourates::partition(&STORAGE.lock().unwrap(), favourates)
+            .unwrap_or_else(|err| {
+                exit_with_error(&format!("Cannot read favourates. Error: {}", err))
+            });
+        let existing_action = match on_conflict {
+            fetcher::favourates::OnConflict::Skip => "skip",
+            fetcher::favourates::OnConflict::Replace => "replace",
+        };
+
+        for (action, favourates) in [("add", &new), (existing_action, &existing)] {
+            for music in favourates.music.iter() {
+                println!("{}\tmusic\t{}", action, music_line(music));
+            }
+            for playlist in favourates.playlist.iter() {
+                println!("{}\tplaylist\t{}", action, playlist_line(playlist));
+            }
+            for artist in favourates.artist.iter() {
+                println!("{}\tartist\t{}", action, artist_line(artist));
+            }
+        }
+
+        let count = |favourates: &fetcher::favourates::Favourates| {
+            favourates.music.len() + favourates.playlist.len() + favourates.artist.len()
+        };
+        // Summary goes to stderr so that stdout can be processed line by line
+        eprintln!(
+            "Dry run: would add {} and {} {} already existing favourates. Nothing is changed",
+            count(&new),
+            existing_action,
+            count(&existing)
+        );
+    }
+}
+
+// Client of control socket. See ui/ipc.rs
+impl Options {
+    // Build the request from arguments. Local path is made absolute as running ytui_music may
+    // not be in same directory
+    fn ctl_request(&self) -> Result<ui::ipc::Request, String> {
+        let argument = self.arguments.get(1).map(String::as_str);
+        let music_id = |arg: &str| match std::fs::canonicalize(arg) {
+            Ok(path) => path.to_string_lossy().into_owned(),
+            Err(_) => fetcher::ids::id_from_arg(arg),
+        };
+        // +10 and -10 are relative while 10 is absolute
+        let number = |arg: Option<&str>| -> Result<(String, bool), String> {
+            let arg = arg.ok_or("Missing value")?.trim();
+            let is_relative = arg.starts_with('+') || arg.starts_with('-');
+            Ok((arg.trim_start_matches('+').to_string(), is_relative))
+        };
+
+        let request = match self.arguments.first().map(String::as_str) {
+            Some("play") => ui::ipc::Request::Play {
+                id: argument.map(music_id),
+            },
+            Some("pause") => ui::ipc::Request::Pause,
+            Some("next") => ui::ipc::Request::Next,
+            Some("prev") => ui::ipc::Request::Prev,
+            Some("status") => ui::ipc::Request::Status,
+            Some("seek") => {
+                let (seconds, is_relative) = number(argument)?;
+                ui::ipc::Request::Seek {
+                    seconds: seconds
+                        .parse()
+                        .map_err(|_| format!("{} is not valid seconds", seconds))?,
+                    absolute: !is_relative,
+                }
+            }
+            Some("volume") => {
+                let (level, is_relative) = number(argument)?;
+                let invalid = |_| format!("{} is not valid volume", level);
+                if is_relative {
+                    ui::ipc::Request::Volume {
+                        level: None,
+                        change: Some(level.parse().map_err(invalid)?),
+                    }
+                } else {
+                    ui::ipc::Request::Volume {
+                        level: Some(level.parse().map_err(invalid)?),
+                        change: None,
+                    }
+                }
+            }
+            Some("enqueue") => {
+                let target = argument.ok_or("Missing id, url or path")?;
+                ui::ipc::Request::Enqueue {
+                    playlist: self.has_flag("--playlist")
+                        || target.contains("/playlist?")
+                        || fetcher::ids::is_playlist_id(target),
+                    id: music_id(target),
+                    next: self.has_flag("--next"),
+                }
+            }
+            _ => return Err(String::from("Unknown command")),
+        };
+        Ok(request)
+    }
+
+    #[cfg(unix)]
+    fn send_request(request: &ui::ipc::Request) -> Result<ui::ipc::Response, String> {
+        use std::io::{BufRead, Write};
+
+        let socket_path =
+            config::ConfigContainer::get_socket_path().ok_or("Cannot locate config directory")?;
+        let mut stream = std::os::unix::net::UnixStream::connect(&socket_path)
+            .map_err(|err| format!("ytui_music is not running. Error: {}", err))?;
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+            .ok();
+
+        let mut line = serde_json::to_string(request).map_err(|err| err.to_string())?;
+        line.push('\n');
+        stream
+            .write_all(line.as_bytes())
+            .map_err(|err| format!("Cannot send command. Error: {}", err))?;
+
+        let mut reply = String::new();
+        std::io::BufReader::new(stream)
+            .read_line(&mut reply)
+            .map_err(|err| format!("No response from ytui_music. Error: {}", err))?;
+        serde_json::from_str(&reply).map_err(|err| format!("Invalid response. Error: {}", err))
+    }
+
+    #[cfg(not(unix))]
+    fn send_request(_request: &ui::ipc::Request) -> Result<ui::ipc::Response, String> {
+        Err(String::from("ctl is only supported on unix like systems"))
+    }
+
+    // ctl <play|pause|next|prev|seek|volume|enqueue|status> [argument] [--json]
+    pub fn ctl(self) {
+        let request = self.ctl_request().unwrap_or_else(|err| {
+            exit_with_error(&format!(
+                "{}\nUsage: ytui_music ctl <play|pause|next|prev|seek|volume|enqueue|status> [argument]",
+                err
+            ))
+        });
+
+        self.initialize_globals();
+        let response = Self::send_request(&request).unwrap_or_else(|err| exit_with_error(&err));
+        if let Some(err) = response.error {
+            exit_with_error(&err);
+        }
+
+        let status = match response.status {
+            Some(status) => status,
+            None => return,
+        };
+        if self.has_flag("--json") {
+            match serde_json::to_string(&status) {
+                Ok(json) => println!("{}", json),
+                Err(err) => exit_with_error(&format!("Cannot serialize status. Error: {}", err)),
+            }
+        } else if request == ui::ipc::Request::Status {
+            use fetcher::ExtendDuration;
+            let duration = |secs| std::time::Duration::from_secs(secs).to_string();
+            let on_off = |flag| if flag { "on" } else { "off" };
+            let state = match (&status.title, status.paused) {
+                (None, _) => "stopped",
+                (Some(_), _) if status.stopped => "stopped",
+                (Some(_), true) => "paused",
+                (Some(_), false) => "playing",
+            };
+
+            println!("title\t{}", status.title.as_deref().unwrap_or("-"));
+            println!("state\t{}", state);
+            println!(
+                "position\t{}/{}",
+                duration(status.elapsed),
+                duration(status.duration)
+            );
+            println!("volume\t{}", status.volume);
+            println!("shuffle\t{}", on_off(status.shuffle));
+            println!(
+                "repeat\t{}",
+                if status.repeat_queue {
+                    "queue"
+                } else {
+                    "music"
+                }
+            );
+            println!("queue\t{}", status.queue_length);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::create_from_args(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parse_sub_command_with_flags() {
+        let opts = parse(&[
+            "ytui_music",
+            "search",
+            "lofi",
+            "--json",
+            "beats",
+            "--type",
+            "playlist",
+        ])
+        .unwrap();
+        assert_eq!(opts.exec_name, "ytui_music");
+        assert_eq!(opts.sub_command, "search");
+        assert_eq!(opts.arguments, vec!["lofi", "beats"]);
+        assert!(opts.has_flag("--json"));
+        assert_eq!(opts.flag_value("--type"), Some("playlist"));
+        assert_eq!(opts.flag_value("--json"), None);
+        assert!(!opts.help);
+
+        let opts = parse(&["ytui_music", "fav", "add", "music", "id", "--title=A = B"]).unwrap();
+        assert_eq!(opts.arguments, vec!["add", "music", "id"]);
+        assert_eq!(opts.flag_value("--title"), Some("A = B"));
+
+        let opts = parse(&["ytui_music", "ctl", "seek", "-10", "--json"]).unwrap();
+        assert_eq!(opts.arguments, vec!["seek", "-10"]);
+        assert!(opts.has_flag("--json"));
+
+        // After -- nothing is a flag
+        let opts = parse(&["ytui_music", "search", "--", "--json", "-h"]).unwrap();
+        assert_eq!(opts.arguments, vec!["--json", "-h"]);
+        assert!(!opts.has_flag("--json"));
+        assert!(!opts.help);
+    }
+
+    #[test]
+    fn parse_global_flags() {
+        let opts = parse(&[
+            "ytui_music",
+            "--region=US",
+            "run",
+            "--server",
+            "https://one.example",
+            "--no-mpv-config",
+            "--server",
+            "piped+https://two.example",
+            "--config",
+            "/tmp/config.json",
+            "--log-file",
+            "/tmp/ytui.log",
+        ])
+        .unwrap();
+        assert_eq!(opts.sub_command, "run");
+        assert!(opts.arguments.is_empty());
+        assert!(opts.flags.is_empty());
+        assert_eq!(
+            opts.overrides,
+            config::Overrides {
+                config_file: Some(PathBuf::from("/tmp/config.json")),
+                region: Some("US".to_string()),
+                servers: vec![
+                    "https://one.example".to_string(),
+                    "piped+https://two.example".to_string()
+                ],
+                no_mpv_config: true,
+                log_file: Some(PathBuf::from("/tmp/ytui.log")),
+            }
+        );
+
+        let opts = parse(&["ytui_music"]).unwrap();
+        assert!(opts.sub_command.is_empty());
+        assert_eq!(opts.overrides, config::Overrides::default());
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse(&["ytui_music", "run", "--verbose"])
+            .unwrap_err()
+            .contains("--verbose"));
+        assert!(parse(&["ytui_music", "run", "--region"])
+            .unwrap_err()
+            .contains("Missing value"));
+        assert!(parse(&["ytui_music", "run", "--no-mpv-config=yes"]).is_err());
+        // Flag of one sub command is not known to another
+        assert!(parse(&["ytui_music", "play", "id", "--json"]).is_err());
+        // Flag of sub command must come after it
+        assert!(parse(&["ytui_music", "--json", "search", "lofi"]).is_err());
+    }
+
+    #[test]
+    fn parse_help() {
+        let opts = parse(&["ytui_music", "fav", "-h"]).unwrap();
+        assert!(opts.help);
+        assert_eq!(opts.sub_command, "fav");
+
+        let help = help_text("ytui_music");
+        for cmd in SUB_COMMANDS.iter() {
+            assert!(help.contains(cmd.name));
+        }
+        for flag in GLOBAL_FLAGS.iter() {
+            assert!(help.contains(&flag_label(flag)));
+        }
+
+        let help = sub_command_help("ytui_music", SubCommand::find("search").unwrap());
+        assert!(help.starts_with("Usage: ytui_music search <query> [flags]"));
+        assert!(help.contains("--type <music|playlist|artist>"));
     }
 }
@@ -2,8 +2,30 @@ use crate::ui::{
     self,
     event::{MIDDLE_ARTIST_INDEX, MIDDLE_MUSIC_INDEX, MIDDLE_PLAYLIST_INDEX},
 };
+use std::io::Write;
 use std::sync::{Arc, Condvar, Mutex};
 
+// Status bar only have room for short message. So the full reason why the fetch or download
+// failed is appended to the log file in config directory
+pub fn log_error(err: &dyn std::fmt::Display) {
+    let log_path = match config::ConfigContainer::get_log_path() {
+        Some(path) => path,
+        None => return,
+    };
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    if let Ok(mut log_file) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path)
+    {
+        writeln!(log_file, "[{}] {}", timestamp, err).ok();
+    }
+}
+
 macro_rules! handle_response {
     ($response: expr, $state_original: expr, $win_index: expr, $target: ident) => {{
         let mut state = $state_original.lock().unwrap();
@@ -17,8 +39,9 @@ macro_rules! handle_response {
             }
             Err(e) => {
                 match e {
-                    fetcher::ReturnAction::Failed => {
-                        state.status = "Fetch error..";
+                    fetcher::ReturnAction::Failed(err) => {
+                        state.status = err.status_text();
+                        log_error(&err);
                     }
                     fetcher::ReturnAction::EOR => {
                         state.status = "Result end..";
@@ -55,11 +78,38 @@ macro_rules! handle_response {
     }};
 }
 
+// Fetch every page of playlist content. Whatever is fetched before failure is returned
+async fn fetch_whole_playlist(
+    fetcher: &mut fetcher::Fetcher,
+    playlist_id: &str,
+) -> Vec<fetcher::MusicUnit> {
+    let mut musics = Vec::new();
+    let mut page = 0;
+    let mut retry_left = 3;
+    loop {
+        match fetcher.get_playlist_content(playlist_id, page).await {
+            Ok(data) => {
+                musics.extend(data);
+                page += 1;
+            }
+            Err(fetcher::ReturnAction::Retry) if retry_left > 0 => retry_left -= 1,
+            Err(fetcher::ReturnAction::Failed(err)) => {
+                log_error(&err);
+                break;
+            }
+            Err(_) => break,
+        }
+    }
+    musics
+}
+
 pub async fn communicator<'st, 'nt>(
     state_original: &'st mut Arc<Mutex<ui::State<'_>>>,
     notifier: &'nt mut Arc<Condvar>,
 ) {
     let mut fetcher = fetcher::Fetcher::default();
+    // Painter, downloader and others all wait on same notifier. So everyone is woken up,
+    // otherwise single wakeup may land on someone that have nothing to do with the change
 
     // variables with prev_ suffex are to be compared with respective current variables from state.
     // This is to check weather anything have changed from previous data request from user so that
@@ -111,7 +161,7 @@ pub async fn communicator<'st, 'nt>(
             state.playlistbar.0.clear();
             state.status = "Fetch playlist..";
 
-            notifier.notify_one();
+            notifier.notify_all();
 
             // condition of if made sure that fetched_page[MIDDLE_PLAYLIST_INDEX] is Some vlaue so
             // unwrapping it is safe.
@@ -139,11 +189,10 @@ pub async fn communicator<'st, 'nt>(
                     playlist_content = fetcher.get_playlist_of_channel(artist_id, page).await;
                 }
                 ui::PlaylistbarSource::Favourates => {
-                    playlist_content = fetcher.get_favourates_playlist(page).await;
+                    playlist_content = fetcher.get_saved_playlist(page).await;
                 }
                 ui::PlaylistbarSource::RecentlyPlayed => {
-                    // TODO
-                    playlist_content = Ok(Vec::new());
+                    playlist_content = fetcher.get_recent_playlist(page).await;
                 }
             }
 
@@ -156,7 +205,7 @@ pub async fn communicator<'st, 'nt>(
             );
             need_retry[MIDDLE_PLAYLIST_INDEX] = retry;
             state_original.lock().unwrap().active = ui::Window::Playlistbar;
-            notifier.notify_one();
+            notifier.notify_all();
         } else {
             // State is always unlocked in above block and dropped in if block. But when if block
             // condition is not met then the state will never be unlocked so always drop the state.
@@ -178,7 +227,7 @@ pub async fn communicator<'st, 'nt>(
         {
             state.artistbar.0.clear();
             state.status = "Fetch artists..";
-            notifier.notify_one();
+            notifier.notify_all();
 
             let page = state.fetched_page[MIDDLE_ARTIST_INDEX].unwrap();
             prev_artistbar_source = state.filled_source.2.clone();
@@ -194,8 +243,7 @@ pub async fn communicator<'st, 'nt>(
                     artist_content = fetcher.get_favourates_artist(page).await;
                 }
                 ui::ArtistbarSource::RecentlyPlayed => {
-                    // TODO:
-                    artist_content = Ok(Vec::new());
+                    artist_content = fetcher.get_recent_artist(page).await;
                 }
             }
 
@@ -207,7 +255,7 @@ pub async fn communicator<'st, 'nt>(
             );
             need_retry[MIDDLE_ARTIST_INDEX] = retry;
             state_original.lock().unwrap().active = ui::Window::Artistbar;
-            notifier.notify_one();
+            notifier.notify_all();
         } else {
             std::mem::drop(state);
         }
@@ -216,14 +264,16 @@ pub async fn communicator<'st, 'nt>(
         let mut state = state_original.lock().unwrap();
         if state.filled_source.0 != prev_musicbar_source
             || need_retry[MIDDLE_MUSIC_INDEX]
+            || state.rescan_library
             || (state.fetched_page[MIDDLE_MUSIC_INDEX] != prev_music_page
                 && state.fetched_page[MIDDLE_MUSIC_INDEX].is_some())
         {
             state.musicbar.0.clear();
             state.status = "Fetch music..";
-            notifier.notify_one();
+            notifier.notify_all();
 
             let page = state.fetched_page[MIDDLE_MUSIC_INDEX].unwrap();
+            let rescan_library = std::mem::take(&mut state.rescan_library);
             prev_musicbar_source = state.filled_source.0.clone();
             prev_music_page = Some(page);
             std::mem::drop(state);
@@ -246,8 +296,18 @@ pub async fn communicator<'st, 'nt>(
                     music_content = fetcher.get_favourates_music(page).await;
                 }
                 ui::MusicbarSource::RecentlyPlayed => {
-                    // TODO: handle each variant with accurate function
-                    music_content = Ok(Vec::new());
+                    music_content = fetcher.get_recent_music(page).await;
+                }
+                ui::MusicbarSource::Local(ref term) => {
+                    let scanned = if rescan_library {
+                        fetcher.rescan_local_library().await
+                    } else {
+                        Ok(())
+                    };
+                    music_content = match scanned {
+                        Ok(()) => fetcher.get_local_music(term.as_deref(), page).await,
+                        Err(err) => Err(err),
+                    };
                 }
             }
 
@@ -255,11 +315,44 @@ pub async fn communicator<'st, 'nt>(
                 handle_response!(music_content, state_original, MIDDLE_MUSIC_INDEX, musicbar);
             need_retry[MIDDLE_MUSIC_INDEX] = retry;
             state_original.lock().unwrap().active = ui::Window::Musicbar;
-            notifier.notify_one();
+            notifier.notify_all();
         } else {
             // If above if block is not executed state lock should however be released
             // so that state can be lock again for following if block
             std::mem::drop(state);
         }
+
+        // Content of playlist is requested to be added in queue. Fetch every page of it
+        let to_queue = state_original.lock().unwrap().to_queue.take();
+        if let Some((playlist_id, mode)) = to_queue {
+            let musics = fetch_whole_playlist(&mut fetcher, &playlist_id).await;
+
+            let mut state = state_original.lock().unwrap();
+            if musics.is_empty() {
+                state.status = "Nothing queued..";
+            } else {
+                state.enqueue_musics(&musics, mode);
+            }
+            notifier.notify_all();
+        }
+
+        // Playlists requested to be downloaded or synced. Only music not downloaded yet is queued
+        let to_download = std::mem::take(&mut state_original.lock().unwrap().to_download);
+        for playlist in to_download {
+            let musics = fetch_whole_playlist(&mut fetcher, &playlist.id).await;
+
+            let mut state = state_original.lock().unwrap();
+            if musics.is_empty() {
+                state.status = "Nothing to download..";
+            } else {
+                state.sync_playlist_downloads(
+                    &config::initilize::STORAGE.lock().unwrap(),
+                    &playlist,
+                    musics,
+                );
+            }
+            // Wake downloader too
+            notifier.notify_all();
+        }
     }
 }
@@ -0,0 +1,175 @@
+use crate::ui;
+use config::initilize::{CONFIG, STORAGE};
+use std::sync::{Arc, Condvar, Mutex};
+
+// Music is tagged as it is shown in ytui-music. For music of youtube playlist only album is
+// known so title and artist written by downloader are kept
+fn file_metadata(download: &ui::DownloadItem) -> fetcher::download::Metadata {
+    let playlist = download.playlist.as_ref();
+    match &download.music {
+        Some(music) => fetcher::download::Metadata {
+            title: Some(music.name.clone()),
+            artist: Some(music.artist.clone()),
+            album: playlist.map(|playlist| playlist.name.clone()),
+        },
+        None => fetcher::download::Metadata {
+            title: None,
+            artist: None,
+            album: playlist.map(|playlist| playlist.name.clone()),
+        },
+    }
+}
+
+// Cover art is only fetched for files that tags are written to.
+// Download is not failed for missing cover so errors are only logged
+async fn fetch_cover(
+    fetcher: &mut fetcher::Fetcher,
+    file: &fetcher::download::DownloadedFile,
+) -> Option<Vec<u8>> {
+    let video_id = file.video_id.as_ref()?;
+    if !CONFIG.download.write_tags
+        || !fetcher::download::can_write_tags(std::path::Path::new(&file.path))
+    {
+        return None;
+    }
+
+    let mut retry_left = 1;
+    loop {
+        match fetcher.get_cover_art(video_id).await {
+            Ok(cover) => return Some(cover),
+            Err(fetcher::ReturnAction::Retry) if retry_left > 0 => retry_left -= 1,
+            Err(fetcher::ReturnAction::Failed(err)) => {
+                crate::communicator::log_error(&err);
+                return None;
+            }
+            Err(_) => return None,
+        }
+    }
+}
+
+// Downloads are queued in state by event handler. This loop pick them one by one in order they
+// were queued and run them while updating progress in state.
+// Download is marked as Cancelled by event handler when user cancels it. Running download then
+// notice it and kill the downloader
+pub async fn downloader(
+    state_original: &mut Arc<Mutex<ui::State<'_>>>,
+    notifier: &mut Arc<Condvar>,
+) {
+    let mut fetcher = fetcher::Fetcher::default();
+    loop {
+        // Wait until something is queued. Lock is released before anything is awaited
+        let download = {
+            let mut state = state_original.lock().unwrap();
+            let index = loop {
+                if state.active == ui::Window::None {
+                    return;
+                }
+                match state
+                    .downloads
+                    .0
+                    .iter()
+                    .position(|download| download.status == ui::DownloadStatus::Queued)
+                {
+                    Some(index) => break index,
+                    None => state = notifier.wait(state).unwrap(),
+                }
+            };
+            state.downloads.0[index].status = ui::DownloadStatus::Running(0.0);
+            state.downloads.0[index].clone()
+        };
+        notifier.notify_all();
+
+        // Items of download list may be removed in between. So search by url everytime.
+        // There can be at most one running download of any url
+        let is_running = |item: &ui::DownloadItem| {
+            item.url == download.url && matches!(item.status, ui::DownloadStatus::Running(_))
+        };
+
+        let command =
+            fetcher::download::build_command(&CONFIG.download, &download.url, &download.title);
+        let outcome = fetcher::download::run_download(command, |progress| {
+            let mut state = state_original.lock().unwrap();
+            // Download is killed when quitting
+            if state.active == ui::Window::None {
+                return false;
+            }
+            match state.downloads.0.iter_mut().find(|item| is_running(item)) {
+                Some(item) => {
+                    if let Some(progress) = progress {
+                        item.status = ui::DownloadStatus::Running(progress);
+                        notifier.notify_all();
+                    }
+                    true
+                }
+                // User have cancelled it
+                None => false,
+            }
+        })
+        .await;
+
+        // Write tags and move the files. Failing to do so is not a failure of download itself
+        // as file is already on the disk
+        let mut saved = Vec::new();
+        if let fetcher::download::Outcome::Finished(ref files) = outcome {
+            for file in files {
+                let metadata = file_metadata(&download);
+                let cover = fetch_cover(&mut fetcher, file).await;
+                let processed =
+                    fetcher::download::post_process(&CONFIG.download, file, &metadata, cover).await;
+                let path = match processed {
+                    Ok(path) => {
+                        if CONFIG.download.write_tags
+                            && !fetcher::download::can_write_tags(std::path::Path::new(&path))
+                        {
+                            crate::communicator::log_error(&format!(
+                                "Tags are only written to mp3. {} is left as downloaded",
+                                path
+                            ));
+                        }
+                        path
+                    }
+                    Err(err) => {
+                        crate::communicator::log_error(&err);
+                        file.path.clone()
+                    }
+                };
+                // Id of music downloaded alone is known even if downloader did not print it
+                let music_id = file
+                    .video_id
+                    .clone()
+                    .or_else(|| download.music.as_ref().map(|music| music.id.clone()));
+                saved.push((music_id, path));
+            }
+        }
+        let last_path = saved
+            .last()
+            .map(|(_, path)| path.clone())
+            .unwrap_or_default();
+
+        let mut state = state_original.lock().unwrap();
+        let conn = STORAGE.lock().unwrap();
+        for (music_id, path) in &saved {
+            if let Some(music_id) = music_id {
+                state.add_to_downloads_index(&conn, music_id, path);
+            }
+        }
+        std::mem::drop(conn);
+        let status = match outcome {
+            fetcher::download::Outcome::Finished(_) => {
+                state.add_download_to_history(&download, &last_path);
+                state.status = "Downloaded..";
+                ui::DownloadStatus::Finished
+            }
+            fetcher::download::Outcome::Failed(reason) => {
+                state.status = "Download failed..";
+                ui::DownloadStatus::Failed(reason)
+            }
+            fetcher::download::Outcome::Cancelled => ui::DownloadStatus::Cancelled,
+        };
+        if let Some(item) = state.downloads.0.iter_mut().find(|item| is_running(item)) {
+            item.status = status;
+        }
+        std::mem::drop(state);
+        notifier.notify_all();
+    }
+}
@@ -4,6 +4,7 @@ use std::sync::Mutex;
 use std::thread;
 mod cli;
 mod communicator;
+mod downloader;
 mod ui;
 
 /*
@@ -14,8 +15,12 @@ mod ui;
 * 2) comminucate is the sync thread for the comminucator which act as the bridge bwteen backend and
 *    front-end. It checks for data required, get data from fetcher and also handles the filling of
 *    data in respective place
+* 3) download is the sync thread which runs the queued downloads one by one
+* 4) observe is the sync thread which waits for events from mpv and updates playback state
+* 5) control is the sync thread which listens for commands from `ytui_music ctl` (unix only)
+* 6) mpris is the sync thread which serves media keys and playerctl over D-Bus (linux only)
 * And the main thread itself will pass the control to `draw_ui` which handles rendering or painting
-* to the terminal. This painter function as well as other 2 spawned thread above runs in a loop and
+* to the terminal. This painter function as well as other spawned thread above runs in a loop and
 * all those loop and terminated when user hits key to quit the application.
 *
 * See below files for respective function
@@ -32,6 +37,18 @@ mod ui;
 * __communicator.rs__: This file reads the state variable, compares it to previous state and change
 * the data to be rendered. This includes calling the fetcher backed, navigating pages and so on.
 *
+* __downloader.rs__: This file waits for download to be queued in state variable and runs them
+* while updating their progress.
+*
+* __ui/playback.rs__: This file listens to what mpv is doing (title, position, pause, ..) and keeps
+* the state updated so that nothing else needs to ask mpv.
+*
+* __ui/ipc.rs__: This file accepts commands from a unix socket and responds the same way as
+* event.rs does for key press.
+*
+* __ui/mpris.rs__: This file exposes the player as MPRIS on session D-Bus so that desktop media
+* keys and tools like playerctl can control it.
+*
 * All the comminucation required are done via a single state variable which stores the state as
 * well as presented data. Given state variable is shared via wrapping in condavr so that one thread
 * can notify other thread when it bring some change in state
@@ -57,18 +74,28 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     }
 
-    let state = Arc::new(Mutex::new(ui::State::default()));
+    let mut state = ui::State::default();
+    // Continue from where user left last time
+    state.restore_session();
+    state.load_downloads_index();
+    let state = Arc::new(Mutex::new(state));
     let cvar = Arc::new(Condvar::new());
 
-    let (handler, communicate);
+    let (handler, communicate, download, observe);
+    #[cfg(unix)]
+    let control;
+    #[cfg(target_os = "linux")]
+    let mpris;
     {
         // same state is shared among all thread
         let mut state_for_painter = Arc::clone(&state);
         let mut state_for_handler = Arc::clone(&state);
         let mut state_for_communicator = Arc::clone(&state);
+        let mut state_for_downloader = Arc::clone(&state);
         let mut cvar_for_painter = Arc::clone(&cvar);
         let mut cvar_for_handler = Arc::clone(&cvar);
         let mut cvar_for_communicator = Arc::clone(&cvar);
+        let mut cvar_for_downloader = Arc::clone(&cvar);
 
         handler = thread::spawn(move || {
             tokio::runtime::Builder::new_multi_thread()
@@ -94,11 +121,66 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
                 });
         });
 
+        download = thread::spawn(move || {
+            tokio::runtime::Builder::new_multi_thread()
+                .enable_all()
+                .build()
+                .unwrap()
+                .block_on(async move {
+                    downloader::downloader(&mut state_for_downloader, &mut cvar_for_downloader)
+                        .await;
+                });
+        });
+
+        {
+            let mut state_for_observer = Arc::clone(&state);
+            let mut cvar_for_observer = Arc::clone(&cvar);
+            observe = thread::spawn(move || {
+                ui::playback::observer(&mut state_for_observer, &mut cvar_for_observer);
+            });
+        }
+
+        #[cfg(unix)]
+        {
+            let mut state_for_control = Arc::clone(&state);
+            let mut cvar_for_control = Arc::clone(&cvar);
+            control = thread::spawn(move || {
+                tokio::runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap()
+                    .block_on(async move {
+                        ui::ipc::listener(&mut state_for_control, &mut cvar_for_control).await;
+                    });
+            });
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            let mut state_for_mpris = Arc::clone(&state);
+            let mut cvar_for_mpris = Arc::clone(&cvar);
+            mpris = thread::spawn(move || {
+                tokio::runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap()
+                    .block_on(async move {
+                        ui::mpris::server(&mut state_for_mpris, &mut cvar_for_mpris).await;
+                    });
+            });
+        }
+
         ui::draw_ui(&mut state_for_painter, &mut cvar_for_painter);
     }
 
     handler.join().unwrap();
     communicate.join().unwrap();
+    download.join().unwrap();
+    observe.join().unwrap();
+    #[cfg(unix)]
+    control.join().unwrap();
+    #[cfg(target_os = "linux")]
+    mpris.join().unwrap();
 
     println!();
     Ok(())
@@ -1,4 +1,4 @@
-use crate::ui::{self, utils::ExtendMpv};
+use crate::ui;
 use config::initilize::{CONFIG, STORAGE};
 use crossterm::event::{self, Event, KeyCode, KeyModifiers};
 use std::{
@@ -89,8 +89,6 @@ pub async fn event_sender(
         video_count: "NaN".to_string(),
     }];
 
-    let download_counter: Arc<Mutex<u32>> = Arc::new(Mutex::new(0));
-
     // There is several option in sidebar like trending/ favourates,
     // this handler will change the selected option from sidebar depending on the direction user
     // move (Up or DOwn).
@@ -151,6 +149,28 @@ pub async fn event_sender(
         notifier.notify_all();
     };
 
+    // simialr to advance_music_list but instead rotate data in `downloads` variable of state
+    let advance_download_list = |direction: HeadTo| {
+        let mut state = state_original.lock().unwrap();
+        let next_index = match state.downloads.1.selected() {
+            None => 0,
+            Some(current) => advance_index(current, state.downloads.0.len(), direction),
+        };
+        state.downloads.1.select(Some(next_index));
+        notifier.notify_all();
+    };
+
+    // simialr to advance_music_list but instead rotate data in `queue` variable of state
+    let advance_queue_list = |direction: HeadTo| {
+        let mut state = state_original.lock().unwrap();
+        let next_index = match state.queue.1.selected() {
+            None => 0,
+            Some(current) => advance_index(current, state.queue.0.len(), direction),
+        };
+        state.queue.1.select(Some(next_index));
+        notifier.notify_all();
+    };
+
     // When active window is set to NONE, it means user had requested to quit the application,
     // This handle will fire when user hits QUIT_SH_KEY
     // Before breaking the loop which this function is running on
@@ -162,7 +182,7 @@ pub async fn event_sender(
         // Do not quit when some download is in progress as it may leave partial file on the disk.
         // If it is urgent required to quit the application user should also press ALT key along
         // with CTRL and QUIT key
-        if !force_quit && *download_counter.lock().unwrap() > 0 {
+        if !force_quit && state.has_pending_download() {
             state.active = ui::Window::Popup(
                 "Error",
                 "Some download are in progress. Press this shortcut with ALT key to force quit"
@@ -171,6 +191,9 @@ pub async fn event_sender(
             return false;
         }
 
+        // Remember what was being played so it can be resumed on next startup
+        state.save_session();
+
         // setting active window to None is to quit
         state.active = ui::Window::None;
         // Also make sure databse is flushed.
@@ -205,13 +228,15 @@ pub async fn event_sender(
         match state.active {
             ui::Window::Searchbar | ui::Window::Popup(..) => {
                 state.search.0.clear();
+                state.prompt = None;
                 drop_and_call!(state, moveto_next_window);
             }
-            ui::Window::BottomControl => {
+            ui::Window::BottomControl | ui::Window::Downloads => {
                 drop_and_call!(state, moveto_next_window);
             }
             ui::Window::Sidebar
             | ui::Window::Musicbar
+            | ui::Window::Queue
             | ui::Window::Playlistbar
             | ui::Window::Artistbar => {
                 state.active = ui::Window::BottomControl;
@@ -251,6 +276,110 @@ pub async fn event_sender(
     let activate_search = || {
         let mut state = state_original.lock().unwrap();
         state.active = ui::Window::Searchbar;
+        // Searchbar might have been left with the prompt of user playlist
+        if state.prompt.take().is_some() {
+            state.search.0.clear();
+        }
+        notifier.notify_all();
+    };
+
+    // This handler is fired when user press PLAYLIST_EDIT_SH_KEY
+    // This will reuse the searchbar to ask for name of user playlist
+    // - From musicbar: add the focused music to the playlist of typed name
+    // - From playlistbar with user playlist focused: rename that playlist
+    // - Otherwise: create a new empty playlist
+    let handle_playlist_edit = || {
+        let mut state = state_original.lock().unwrap();
+        let selected_music = state
+            .musicbar
+            .1
+            .selected()
+            .and_then(|index| state.musicbar.0.get(index).cloned());
+        let selected_playlist = state
+            .playlistbar
+            .1
+            .selected()
+            .and_then(|index| state.playlistbar.0.get(index).cloned());
+
+        let (prompt, initial_text) = match (&state.active, selected_music, selected_playlist) {
+            (ui::Window::Musicbar, Some(music), _) => (ui::Prompt::AddTo(music), String::new()),
+            (ui::Window::Playlistbar, _, Some(playlist))
+                if fetcher::ids::user_playlist_id(&playlist.id).is_some() =>
+            {
+                (ui::Prompt::Rename(playlist.id), playlist.name)
+            }
+            _ => (ui::Prompt::Create, String::new()),
+        };
+
+        state.prompt = Some(prompt);
+        state.search.0 = initial_text;
+        state.active = ui::Window::Searchbar;
+        notifier.notify_all();
+    };
+
+    // Called when enter is pressed in searchbar while it was asking for playlist name
+    let run_prompt = |prompt: ui::Prompt| {
+        let mut state = state_original.lock().unwrap();
+        let name = state.search.0.trim().to_string();
+        state.search.0.clear();
+
+        if name.is_empty() {
+            state.status = "Empty name..";
+        } else {
+            match prompt {
+                ui::Prompt::AddTo(music) => state.add_music_to_user_playlist(&name, &music),
+                ui::Prompt::Create => state.create_user_playlist(&name),
+                ui::Prompt::Rename(playlist_id) => state.rename_user_playlist(&playlist_id, &name),
+            }
+        }
+        drop_and_call!(state, moveto_next_window);
+    };
+
+    // Move focused music up or down in user playlist or in queue
+    let move_in_playlist = |up: bool| {
+        let mut state = state_original.lock().unwrap();
+        match state.active {
+            ui::Window::Musicbar => state.move_music_in_user_playlist(up),
+            ui::Window::Queue => state.move_in_queue(up),
+            _ => return,
+        }
+        notifier.notify_all();
+    };
+
+    // Add focused music or playlist to queue instead of replacing what is being played
+    let handle_queue = |mode: ui::QueueMode| {
+        let mut state = state_original.lock().unwrap();
+        match state.active {
+            ui::Window::Musicbar => {
+                let selected_music = state
+                    .musicbar
+                    .1
+                    .selected()
+                    .and_then(|index| state.musicbar.0.get(index).cloned());
+                match selected_music {
+                    Some(music) => state.enqueue_musics(&[music], mode),
+                    None => state.status = "Nothing selected..",
+                }
+            }
+            ui::Window::Playlistbar => {
+                let selected_playlist = state
+                    .playlistbar
+                    .1
+                    .selected()
+                    .and_then(|index| state.playlistbar.0.get(index))
+                    .map(|playlist| playlist.id.clone());
+                match selected_playlist {
+                    // Content of playlist is fetched and queued by communicator
+                    Some(playlist_id) => {
+                        state.to_queue = Some((playlist_id, mode));
+                        state.status = "Queueing..";
+                    }
+                    None => state.status = "Nothing selected..",
+                }
+            }
+            ui::Window::Queue if mode == ui::QueueMode::Next => state.play_next_from_queue(),
+            _ => return,
+        }
         notifier.notify_all();
     };
 
@@ -264,6 +393,8 @@ pub async fn event_sender(
         match state.active {
             ui::Window::Sidebar => drop_and_call!(state, advance_sidebar, direction),
             ui::Window::Musicbar => drop_and_call!(state, advance_music_list, direction),
+            ui::Window::Queue => drop_and_call!(state, advance_queue_list, direction),
+            ui::Window::Downloads => drop_and_call!(state, advance_download_list, direction),
             ui::Window::Playlistbar => drop_and_call!(state, advance_playlist_list, direction),
             ui::Window::Artistbar => drop_and_call!(state, advance_artist_list, direction),
             _ => match direction {
@@ -299,6 +430,10 @@ pub async fn event_sender(
                 search_term[&CONFIG.constants.search_by_type[2].len() - 1..].to_string();
             state.fetched_page[2] = Some(0);
             state.filled_source.2 = ui::ArtistbarSource::Search(search_term);
+        } else if let Some(search_term) = search_term.strip_prefix(&CONFIG.constants.search_local) {
+            let search_term = search_term.trim().to_string();
+            state.fetched_page[MIDDLE_MUSIC_INDEX] = Some(0);
+            state.filled_source.0 = ui::MusicbarSource::Local(Some(search_term));
         }
         // If nothing of the prefix is defined then search for all type
         else {
@@ -350,6 +485,27 @@ pub async fn event_sender(
         notifier.notify_all();
     };
 
+    // Recently played source fills all of musicbar, playlistbar and artistbar at once
+    let fill_recently_played = || {
+        let mut state = state_original.lock().unwrap();
+        state.filled_source = (
+            ui::MusicbarSource::RecentlyPlayed,
+            ui::PlaylistbarSource::RecentlyPlayed,
+            ui::ArtistbarSource::RecentlyPlayed,
+        );
+        state.fetched_page = [Some(0); 3];
+        notifier.notify_all();
+    };
+
+    // Show all the music from local library. Library is rescanned everytime this is selected
+    let fill_local_library = || {
+        let mut state = state_original.lock().unwrap();
+        state.filled_source.0 = ui::MusicbarSource::Local(None);
+        state.fetched_page[MIDDLE_MUSIC_INDEX] = Some(0);
+        state.rescan_library = true;
+        notifier.notify_all();
+    };
+
     let fill_music_from_playlist = |direction: HeadTo| {
         let mut state = state_original.lock().unwrap();
         if let ui::MusicbarSource::Playlist(playlist_id) = &state.filled_source.0 {
@@ -403,7 +559,11 @@ pub async fn event_sender(
                 // It implied to change the track
                 return drop_and_call!(state, change_track, direction);
             }
-            ui::Window::Searchbar | ui::Window::Sidebar | ui::Window::Popup(..) => {
+            ui::Window::Searchbar
+            | ui::Window::Sidebar
+            | ui::Window::Queue
+            | ui::Window::Downloads
+            | ui::Window::Popup(..) => {
                 // If none of above windows are active then nothing to navigate.
                 // Early return instead of initilizing `target_index`
                 return;
@@ -437,24 +597,15 @@ pub async fn event_sender(
 
     let handle_repeat = || {
         let mut state = state_original.lock().unwrap();
-        state.player.repeat_nothing();
-        if state.playback_behaviour.repeat {
-            state.player.repeat_one();
-        } else {
-            state.player.repeat_playlist();
-        }
-        state.playback_behaviour.repeat = !state.playback_behaviour.repeat;
+        let repeat_queue = !state.playback_behaviour.repeat;
+        state.set_repeat(repeat_queue);
         notifier.notify_all();
     };
 
     let toggle_shuffle = || {
         let mut state = state_original.lock().unwrap();
-        if state.playback_behaviour.shuffle {
-            state.player.unshuffle();
-        } else {
-            state.player.shuffle();
-        }
-        state.playback_behaviour.shuffle = !state.playback_behaviour.shuffle;
+        let shuffle = !state.playback_behaviour.shuffle;
+        state.set_shuffle(shuffle);
         notifier.notify_all();
     };
 
@@ -463,55 +614,84 @@ pub async fn event_sender(
         notifier.notify_all();
     };
 
-    let handle_download = || async {
+    // Queue music of focused playlist that are not downloaded yet. If no playlist is focused,
+    // sync every playlist that was downloaded before
+    let sync_playlist = || {
         let mut state = state_original.lock().unwrap();
 
-        // TODO: Ask for conformation before downloading
-        let mut command = tokio::process::Command::new("youtube-dl");
-        let download_url;
-        if let Some(focused_index) = state.musicbar.1.selected() {
-            let music_id = &state.musicbar.0[focused_index].id;
-            download_url = format!("https://www.youtube.com/watch?v={}", music_id);
-        } else if let Some(focused_index) = state.playlistbar.1.selected() {
-            let playlist_id = &state.playlistbar.0[focused_index].id;
-            download_url = format!("https://www.youtube.com/playlist?list={}", playlist_id);
-        } else {
-            return;
+        let focused_playlist = state
+            .playlistbar
+            .1
+            .selected()
+            .and_then(|index| state.playlistbar.0.get(index).cloned());
+        match focused_playlist {
+            // User playlist is not known to youtube so it's music are already with us
+            Some(playlist) => match fetcher::ids::user_playlist_id(&playlist.id) {
+                Some(user_playlist_id) => {
+                    let musics = state.user_playlist_music(user_playlist_id);
+                    state.sync_playlist_downloads(&STORAGE.lock().unwrap(), &playlist, musics);
+                }
+                // Content of playlist is fetched by communicator
+                None => {
+                    state.to_download.push(playlist);
+                    state.status = "Syncing..";
+                }
+            },
+            None => {
+                let playlists = state.downloaded_playlists();
+                if playlists.is_empty() {
+                    state.status = "Nothing to sync..";
+                } else {
+                    for playlist in playlists {
+                        match fetcher::ids::user_playlist_id(&playlist.id) {
+                            Some(user_playlist_id) => {
+                                let musics = state.user_playlist_music(user_playlist_id);
+                                let conn = STORAGE.lock().unwrap();
+                                state.sync_playlist_downloads(&conn, &playlist, musics);
+                            }
+                            None => state.to_download.push(playlist),
+                        }
+                    }
+                    state.status = "Syncing..";
+                }
+            }
         }
+        notifier.notify_all();
+    };
 
-        state.status = "Download started..";
-        state.active = ui::Window::Popup(
-            "Downloading...",
-            format!(
-                "Download of {} have an eye on your Music folder",
-                download_url
-            ),
-        );
-        command.arg(download_url);
-
-        command
-            .stdin(std::process::Stdio::null())
-            .stdout(std::process::Stdio::null())
-            .stderr(std::process::Stdio::null())
-            .args(&["--extract-audio", "--audio-format", &CONFIG.download.format])
-            .current_dir(&CONFIG.download.path)
-            .kill_on_drop(false);
-
-        std::mem::drop(state);
+    // Queue the focused music or playlist for download. See downloader.rs
+    // Anything that is already downloaded is skipped
+    let handle_download = || {
+        let mut state = state_original.lock().unwrap();
 
-        *download_counter.lock().unwrap() += 1;
-        let counter_clone = Arc::clone(&download_counter);
+        // TODO: Ask for conformation before downloading
+        if let Some(focused_index) = state.musicbar.1.selected() {
+            let music = state.musicbar.0[focused_index].clone();
+            state.forget_missing_downloads(&STORAGE.lock().unwrap(), std::slice::from_ref(&music));
+            // Music from local library is already on the disk
+            if fetcher::library::is_local(&music.id) {
+                state.status = "Already local..";
+            } else if state.is_downloaded(&music.id) {
+                state.status = "Already downloaded..";
+            } else {
+                let url = format!("https://www.youtube.com/watch?v={}", music.id);
+                state.queue_download(url, Some(music), None);
+            }
+            notifier.notify_all();
+        } else if state.playlistbar.1.selected().is_some() {
+            drop_and_call!(state, sync_playlist);
+        }
+    };
 
-        // Wait for 5 second just to make sure that command has finished executing.
-        // It usually donot take all those 5 seconds
-        // Anyway, download won't finish before 5 seconds
-        // Then just wait for command to finish by waiting for exit status
-        // decrease the download queue count
-        tokio::task::spawn(async move {
-            tokio::time::sleep(Duration::from_secs(5)).await;
-            command.status().await.unwrap();
-            *counter_clone.lock().unwrap() -= 1;
-        });
+    // Show the list of downloads over everything else. Pressing again hides it
+    let toggle_downloads = || {
+        let mut state = state_original.lock().unwrap();
+        if state.active == ui::Window::Downloads {
+            drop_and_call!(state, moveto_next_window);
+        } else {
+            state.active = ui::Window::Downloads;
+            notifier.notify_all();
+        }
     };
 
     // If play is true it means also play the playlist
@@ -523,10 +703,17 @@ pub async fn event_sender(
             if play {
                 state.activate_playlist(&playlist_id);
             } else {
-                let message = format!(
-                    "Playlist url: https://youtu.be/playlist?list={}",
-                    playlist_id
-                );
+                let message = if fetcher::ids::user_playlist_id(&playlist_id).is_some() {
+                    format!(
+                        "Your playlist: {}",
+                        state.playlistbar.0[selected_index].name
+                    )
+                } else {
+                    format!(
+                        "Playlist url: https://youtu.be/playlist?list={}",
+                        playlist_id
+                    )
+                };
                 state.active = ui::Window::Popup("Info!", message);
             }
             state.filled_source.0 = ui::MusicbarSource::Playlist(playlist_id);
@@ -542,7 +729,11 @@ pub async fn event_sender(
                 let music_id = music_id.clone();
                 state.play_music(&music_id);
             } else {
-                let message = format!("Music url: https://youtu.be/{}", music_id);
+                let message = if fetcher::library::is_local(music_id) {
+                    format!("Music path: {}", music_id)
+                } else {
+                    format!("Music url: https://youtu.be/{}", music_id)
+                };
                 state.active = ui::Window::Popup("Info!", message);
                 notifier.notify_all();
             }
@@ -573,7 +764,7 @@ pub async fn event_sender(
     };
 
     let handle_view = || {
-        let state = state_original.lock().unwrap();
+        let mut state = state_original.lock().unwrap();
         match state.active {
             ui::Window::Playlistbar => {
                 drop_and_call!(state, select_playlist, false);
@@ -581,6 +772,13 @@ pub async fn event_sender(
             ui::Window::Musicbar => {
                 drop_and_call!(state, select_music, false);
             }
+            ui::Window::Queue => {
+                if let Some(item) = state.queue.1.selected().and_then(|i| state.queue.0.get(i)) {
+                    let message = format!("Queued: {}", item.filename);
+                    state.active = ui::Window::Popup("Info!", message);
+                    notifier.notify_all();
+                }
+            }
             ui::Window::Artistbar => {}
             _ => {}
         }
@@ -610,22 +808,43 @@ pub async fn event_sender(
                     ui::SidebarOption::Following => {
                         drop_and_call!(state, fill_fav_artist, HeadTo::Initial);
                     }
+                    ui::SidebarOption::RecentlyPlayed => {
+                        drop_and_call!(state, fill_recently_played);
+                    }
+                    ui::SidebarOption::LocalLibrary => {
+                        drop_and_call!(state, fill_local_library);
+                    }
                     ui::SidebarOption::Search => drop_and_call!(state, activate_search),
                 }
             }
-            ui::Window::Searchbar => {
-                drop_and_call!(state, start_search);
-            }
+            ui::Window::Searchbar => match state.prompt.take() {
+                Some(prompt) => drop_and_call!(state, run_prompt, prompt),
+                None => drop_and_call!(state, start_search),
+            },
 
             // On enter play the music
             ui::Window::Musicbar => drop_and_call!(state, select_music, true),
 
+            // On enter retry the failed download
+            ui::Window::Downloads => {
+                state.retry_download();
+                notifier.notify_all();
+            }
+
+            // On enter jump to this music in queue
+            ui::Window::Queue => {
+                state.play_from_queue();
+                notifier.notify_all();
+            }
+
             // On enter selection view the playlist content as well as play it
             ui::Window::Playlistbar => drop_and_call!(state, select_playlist, true),
 
             ui::Window::Artistbar => {
                 if let Some(selected_index) = state.artistbar.1.selected() {
-                    let artist_id = state.artistbar.0[selected_index].id.clone();
+                    let artist = state.artistbar.0[selected_index].clone();
+                    state.add_artist_to_history(&artist);
+                    let artist_id = artist.id;
                     state.filled_source.0 = ui::MusicbarSource::Artist(artist_id.clone());
                     state.filled_source.1 = ui::PlaylistbarSource::Artist(artist_id);
                     std::mem::drop(state);
@@ -649,7 +868,19 @@ pub async fn event_sender(
                     // yeah. I am sure this is fine (up until now).
                     let selected_music =
                         &state.musicbar.0[selected_index] as *const fetcher::MusicUnit;
-                    if add {
+                    let user_playlist = match &state.filled_source.0 {
+                        ui::MusicbarSource::Playlist(playlist_id)
+                            if fetcher::ids::user_playlist_id(playlist_id).is_some() =>
+                        {
+                            Some(playlist_id.clone())
+                        }
+                        _ => None,
+                    };
+                    // Removing music while browsing user playlist removes it from that playlist
+                    if let (false, Some(playlist_id)) = (add, user_playlist) {
+                        let music_id = unsafe { &*selected_music }.id.clone();
+                        state.remove_music_from_user_playlist(&playlist_id, &music_id);
+                    } else if add {
                         state.add_music_to_favourates(unsafe { &*selected_music });
                     } else {
                         state.remove_music_from_favourates(unsafe { &*selected_music });
@@ -663,7 +894,15 @@ pub async fn event_sender(
                 if let Some(selected_index) = state.playlistbar.1.selected() {
                     let selected_playlist =
                         &state.playlistbar.0[selected_index] as *const fetcher::PlaylistUnit;
-                    if add {
+                    let playlist_id = unsafe { &*selected_playlist }.id.clone();
+                    // User playlist is always in saved list. Removing it deletes the playlist
+                    if fetcher::ids::user_playlist_id(&playlist_id).is_some() {
+                        if add {
+                            state.status = "Already saved..";
+                        } else {
+                            state.delete_user_playlist(&playlist_id);
+                        }
+                    } else if add {
                         state.add_playlist_to_favourates(unsafe { &*selected_playlist });
                     } else {
                         state.remove_playlist_from_favourates(unsafe { &*selected_playlist });
@@ -673,6 +912,27 @@ pub async fn event_sender(
                 }
             }
 
+            ui::Window::Downloads => {
+                if state.downloads.1.selected().is_none() {
+                    state.status = "Nothing selected..";
+                } else if add {
+                    state.status = "Not supported..";
+                } else {
+                    state.cancel_download();
+                }
+            }
+
+            // Queue only contains what is to be played. So only removing is possible
+            ui::Window::Queue => {
+                if state.queue.1.selected().is_none() {
+                    state.status = "Nothing selected..";
+                } else if add {
+                    state.status = "Not supported..";
+                } else {
+                    state.remove_from_queue();
+                }
+            }
+
             ui::Window::Artistbar => {
                 if let Some(selected_index) = state.artistbar.1.selected() {
                     let selected_artist =
@@ -745,6 +1005,16 @@ pub async fn event_sender(
                                 handle_favourates(true);
                             } else if ch == CONFIG.shortcut_keys.favourates_remove {
                                 handle_favourates(false);
+                            } else if ch == CONFIG.shortcut_keys.playlist_edit {
+                                handle_playlist_edit();
+                            } else if ch == CONFIG.shortcut_keys.move_up {
+                                move_in_playlist(true);
+                            } else if ch == CONFIG.shortcut_keys.move_down {
+                                move_in_playlist(false);
+                            } else if ch == CONFIG.shortcut_keys.play_next {
+                                handle_queue(ui::QueueMode::Next);
+                            } else if ch == CONFIG.shortcut_keys.queue_append {
+                                handle_queue(ui::QueueMode::Append);
                             } else if ch == CONFIG.shortcut_keys.prev {
                                 if is_with_control {
                                     change_track(HeadTo::Prev);
@@ -758,7 +1028,11 @@ pub async fn event_sender(
                                     handle_nav(HeadTo::Next);
                                 }
                             } else if ch == CONFIG.shortcut_keys.download && is_with_control {
-                                handle_download().await;
+                                handle_download();
+                            } else if ch == CONFIG.shortcut_keys.sync_playlist {
+                                sync_playlist();
+                            } else if ch == CONFIG.shortcut_keys.show_downloads {
+                                toggle_downloads();
                             } else if ch == CONFIG.shortcut_keys.vol_increase {
                                 change_volume(HeadTo::Next);
                             } else if ch == CONFIG.shortcut_keys.vol_decrease {
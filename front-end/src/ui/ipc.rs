@@ -0,0 +1,323 @@
+use crate::ui;
+use serde::{Deserialize, Serialize};
+#[cfg(unix)]
+use {
+    config::initilize::CONFIG,
+    std::sync::{Arc, Condvar, Mutex},
+    std::time::Duration,
+    tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    tokio::net::{UnixListener, UnixStream},
+};
+
+/*
+* Running ytui_music can be controlled from outside with `ytui_music ctl`. Listener waits for
+* connection in unix socket inside config directory. Every line sent to the socket is a json
+* `Request` and is answered with a single line of json `Response`. eg:
+* -> {"command": "seek", "seconds": -10}
+* <- {"status": {"title": "..", "paused": false, ...}}
+* Requests are done by calling the same state methods that key handlers in event.rs use, so the
+* result is same as if user had pressed the shortcut.
+*/
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "lowercase")]
+pub enum Request {
+    // Play the music right away. Resume what was paused if id is not given
+    Play {
+        id: Option<String>,
+    },
+    Pause,
+    Next,
+    Prev,
+    // Seek by given seconds from current position. To the position itself if absolute
+    Seek {
+        seconds: f64,
+        #[serde(default)]
+        absolute: bool,
+    },
+    // Either set volume to level or change it by given step
+    Volume {
+        level: Option<u8>,
+        change: Option<i8>,
+    },
+    // Add music or whole playlist to the queue. Right after music being played if next is true
+    Enqueue {
+        id: String,
+        #[serde(default)]
+        playlist: bool,
+        #[serde(default)]
+        next: bool,
+    },
+    Status,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Response {
+    // Reason if request was not done
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    // Status of player after the request. None only if request itself is invalid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status: Option<ui::PlayerStatus>,
+}
+
+// Music in musicbar are complete. Local file have title and artist in it's tags. Otherwise only
+// id is known and title is found by mpv itself
+fn music_for_id(state: &ui::State, music_id: &str) -> fetcher::MusicUnit {
+    if let Some(music) = state.musicbar.0.iter().find(|music| music.id == music_id) {
+        return music.clone();
+    }
+    if fetcher::library::is_local(music_id) {
+        return fetcher::library::read_music(music_id);
+    }
+    fetcher::MusicUnit {
+        id: music_id.to_string(),
+        name: String::new(),
+        artist: String::new(),
+        duration: String::from("-"),
+    }
+}
+
+fn dispatch(request: Request, state: &mut ui::State) -> Result<(), String> {
+    match request {
+        Request::Play { id: Some(music_id) } => {
+            let music = music_for_id(state, &music_id);
+            state.play_now(&music);
+        }
+        Request::Play { id: None } => {
+            if state.bottom.playing.is_none() {
+                return Err(String::from("Nothing to play"));
+            }
+            state.set_paused(false);
+        }
+        Request::Pause => state.set_paused(true),
+        Request::Next => state.player.play_next(),
+        Request::Prev => state.player.play_prev(),
+        Request::Seek { seconds, absolute } => {
+            let res = if absolute {
+                state.player.seek_absolute(seconds)
+            } else if seconds < 0.0 {
+                state.player.seek_backward(-seconds)
+            } else {
+                state.player.seek_forward(seconds)
+            };
+            res.map_err(|_| String::from("Cannot seek"))?;
+        }
+        Request::Volume { level, change } => {
+            let volume = match (level, change) {
+                (Some(level), None) => state.player.set_volume(level),
+                (None, Some(step)) => state.player.change_volume(step),
+                _ => return Err(String::from("Give either level or change of volume")),
+            };
+            match volume {
+                Some(volume) => state.playback_behaviour.volume = volume,
+                None => return Err(String::from("Cannot change volume")),
+            }
+        }
+        Request::Enqueue { id, playlist, next } => {
+            let mode = if next {
+                ui::QueueMode::Next
+            } else {
+                ui::QueueMode::Append
+            };
+            match (playlist, fetcher::ids::user_playlist_id(&id)) {
+                // User playlist is not known to youtube so it's music are already with us
+                (true, Some(user_playlist_id)) => {
+                    let musics = state.user_playlist_music(user_playlist_id);
+                    if musics.is_empty() {
+                        return Err(String::from("Playlist is empty"));
+                    }
+                    state.enqueue_musics(&musics, mode);
+                }
+                // Content of playlist is fetched and queued by communicator
+                (true, None) => {
+                    state.to_queue = Some((id, mode));
+                    state.status = "Queueing..";
+                }
+                (false, _) => {
+                    let music = music_for_id(state, &id);
+                    state.enqueue_musics(&[music], mode);
+                }
+            }
+        }
+        Request::Status => {}
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn respond(request: Request, state: &Mutex<ui::State>, notifier: &Condvar) -> Response {
+    let mut state = state.lock().unwrap();
+    let res = dispatch(request, &mut state);
+    let status = state.player_status();
+    std::mem::drop(state);
+    notifier.notify_all();
+
+    Response {
+        error: res.err(),
+        status: Some(status),
+    }
+}
+
+#[cfg(unix)]
+async fn serve(stream: UnixStream, state: Arc<Mutex<ui::State<'static>>>, notifier: Arc<Condvar>) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = match serde_json::from_str::<Request>(&line) {
+            Ok(request) => respond(request, &state, &notifier),
+            Err(err) => Response {
+                error: Some(format!("Invalid request. {}", err)),
+                status: None,
+            },
+        };
+
+        let mut reply = serde_json::to_string(&response).unwrap_or_default();
+        reply.push('\n');
+        if writer.write_all(reply.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+// This runs in it's own thread like event_sender and keeps accepting connections until user
+// quits. Each connection is served in it's own task so a client that keeps connection open
+// does not block others
+#[cfg(unix)]
+pub async fn listener(state: &mut Arc<Mutex<ui::State<'static>>>, notifier: &mut Arc<Condvar>) {
+    let socket_path = match config::ConfigContainer::get_socket_path() {
+        Some(socket_path) => socket_path,
+        None => return,
+    };
+
+    // Socket is left behind if ytui_music was not quit properly last time. But if it still
+    // accepts connection then another ytui_music is running and is the one being controlled
+    if socket_path.exists() {
+        if std::os::unix::net::UnixStream::connect(&socket_path).is_ok() {
+            state.lock().unwrap().status = "Control in use..";
+            return;
+        }
+        std::fs::remove_file(&socket_path).ok();
+    }
+    let listener = match UnixListener::bind(&socket_path) {
+        Ok(listener) => listener,
+        Err(_) => {
+            state.lock().unwrap().status = "Control error..";
+            return;
+        }
+    };
+    // Only the user running ytui_music should be able to control it
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&socket_path, std::fs::Permissions::from_mode(0o600)).ok();
+    }
+
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => {
+                if let Ok((stream, _)) = accepted {
+                    tokio::spawn(serve(stream, Arc::clone(state), Arc::clone(notifier)));
+                }
+            }
+            _ = tokio::time::sleep(Duration::from_millis(CONFIG.constants.refresh_rate)) => {}
+        }
+
+        if state.lock().unwrap().active == ui::Window::None {
+            break;
+        }
+    }
+
+    std::fs::remove_file(&socket_path).ok();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::player::{FakePlayer, Player};
+
+    #[test]
+    fn parse_requests() {
+        let parse = |line: &str| serde_json::from_str::<Request>(line).unwrap();
+
+        assert_eq!(parse(r#"{"command": "play"}"#), Request::Play { id: None });
+        assert_eq!(
+            parse(r#"{"command": "play", "id": "abc"}"#),
+            Request::Play {
+                id: Some("abc".to_string())
+            }
+        );
+        assert_eq!(
+            parse(r#"{"command": "seek", "seconds": -10}"#),
+            Request::Seek {
+                seconds: -10.0,
+                absolute: false
+            }
+        );
+        assert_eq!(
+            parse(r#"{"command": "volume", "change": -5}"#),
+            Request::Volume {
+                level: None,
+                change: Some(-5)
+            }
+        );
+        assert_eq!(
+            parse(r#"{"command": "enqueue", "id": "PL1", "playlist": true}"#),
+            Request::Enqueue {
+                id: "PL1".to_string(),
+                playlist: true,
+                next: false
+            }
+        );
+        assert!(serde_json::from_str::<Request>(r#"{"command": "dance"}"#).is_err());
+        assert!(serde_json::from_str::<Request>(r#"{"command": "seek"}"#).is_err());
+
+        // What client sends is what server reads
+        let request = Request::Enqueue {
+            id: "abc".to_string(),
+            playlist: false,
+            next: true,
+        };
+        assert_eq!(parse(&serde_json::to_string(&request).unwrap()), request);
+    }
+
+    #[test]
+    fn dispatch_requests() {
+        let player = std::sync::Arc::new(FakePlayer::default());
+        let mut state = ui::State::with_player(std::sync::Arc::clone(&player) as _);
+
+        assert!(dispatch(Request::Play { id: None }, &mut state).is_err());
+        dispatch(
+            Request::Enqueue {
+                id: "abc".to_string(),
+                playlist: false,
+                next: false,
+            },
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(state.player_status().queue_length, 1);
+
+        let volume = |level, change| Request::Volume { level, change };
+        dispatch(volume(Some(30), None), &mut state).unwrap();
+        dispatch(volume(None, Some(-5)), &mut state).unwrap();
+        assert_eq!(state.playback_behaviour.volume, 25);
+        assert!(dispatch(volume(Some(30), Some(5)), &mut state).is_err());
+
+        dispatch(
+            Request::Seek {
+                seconds: 42.0,
+                absolute: true,
+            },
+            &mut state,
+        )
+        .unwrap();
+        assert_eq!(player.elapsed(), Some(42.0));
+
+        dispatch(Request::Pause, &mut state).unwrap();
+        assert!(state.player_status().paused);
+    }
+}
@@ -1,13 +1,19 @@
 pub mod event;
+pub mod ipc;
+#[cfg(target_os = "linux")]
+pub mod mpris;
+#[cfg(unix)]
+pub mod mpv_ipc;
+pub mod playback;
+pub mod player;
 mod utils;
 use std::sync::Condvar;
 use tui::{backend::CrosstermBackend, Terminal};
 // These are the imports also used in __utils.rs__ so make this import shareable
 mod shared_import {
     pub use fetcher;
-    pub use libmpv;
     pub use serde::{Deserialize, Serialize};
-    pub use std::convert::{From, Into, TryFrom, TryInto};
+    pub use std::convert::{From, Into, TryFrom};
     pub use std::{
         sync::{Arc, Mutex},
         time::Duration,
@@ -80,19 +86,20 @@ pub struct SideBar {
 }
 
 // --------------------------------------
-// |                                    |
-// |        Rect (musicbar)             |
-// |                                    |
+// |                        |           |
+// |    Rect (musicbar)     |   Rect    |
+// |                        |  (queue)  |
 // |-------------------------------------
 // |                                    |
 // |        MiddleBottom                |
 // |                                    |
 // --------------------------------------
-// Split the area vertically. first section is the area where musics are shown which is actually
-// the individual video from youtube. See `Fetcher::MusicUnit` type
+// Split the area vertically. first section is again splitted horizontally. Left of it is the area
+// where musics are shown which is actually the individual video from youtube.
+// See `Fetcher::MusicUnit` type. Right of it shows what is queued in mpv. See `QueueItem`
 // See `MiddleBottom` for more
 pub struct MiddleLayout {
-    layout: Rect,
+    layout: [Rect; 2],
     bottom: MiddleBottom,
 }
 
@@ -123,9 +130,9 @@ pub struct BottomLayout {
 // |    Searchbar                           |  Statusbar    |
 // |--------------------------------------------------------|
 // |         |                                              |
-// |         |                                              |
-// |         |                  MusicBar                    |
-// |         |                                              |
+// |         |                                 |            |
+// |         |            MusicBar             |   Queue    |
+// |         |                                 |            |
 // |         |                                              |
 // | Sidebar |----------------------------------------------|
 // |         |                      |                       |
@@ -146,11 +153,13 @@ pub struct Position {
     pub status: Rect,
     pub shortcut: Rect,
     pub music: Rect,
+    pub queue: Rect,
     pub playlist: Rect,
     pub artist: Rect,
     pub music_info: Rect,
     pub bottom_icons: Rect,
     pub popup: Rect,
+    pub downloads: Rect,
 }
 
 // This function will:
@@ -213,11 +222,12 @@ pub fn draw_ui(state: &mut Arc<Mutex<State>>, cvar: &mut Arc<Condvar>) {
                 // short time interval copying anything for that purpose would be consuming more
                 // cpu. And it may be good time to play with unsafe
                 let state_ptr = &mut state_unlocked as *mut std::sync::MutexGuard<'_, State<'_>>;
-                let (mut music_state, mut playlist_state, mut artist_state);
+                let (mut music_state, mut playlist_state, mut artist_state, queue_state);
                 unsafe {
                     music_state = &mut (*state_ptr).musicbar.1;
                     playlist_state = &mut (*state_ptr).playlistbar.1;
                     artist_state = &mut (*state_ptr).artistbar.1;
+                    queue_state = &mut (*state_ptr).queue.1;
                 }
 
                 let music_table = MiddleLayout::get_music_container(&mut state_unlocked);
@@ -231,7 +241,8 @@ pub fn draw_ui(state: &mut Arc<Mutex<State>>, cvar: &mut Arc<Condvar>) {
                 let artist_table = MiddleBottom::get_artist_container(&mut state_unlocked);
                 screen.render_stateful_widget(artist_table, position.artist, &mut artist_state);
 
-                state_unlocked.refresh_mpv_status();
+                let queue_table = MiddleLayout::get_queue_container(&mut state_unlocked);
+                screen.render_stateful_widget(queue_table, position.queue, queue_state);
 
                 screen.render_widget(
                     BottomLayout::get_status_bar(&state_unlocked),
@@ -247,6 +258,17 @@ pub fn draw_ui(state: &mut Arc<Mutex<State>>, cvar: &mut Arc<Condvar>) {
                 // see some things like progress of music player
                 if let Window::Popup(title, ref content) = state_unlocked.active {
                     utils::show_pupop_text(screen, [title, content], &position.popup);
+                } else if state_unlocked.active == Window::Downloads {
+                    let state_ptr =
+                        &mut state_unlocked as *mut std::sync::MutexGuard<'_, State<'_>>;
+                    let download_state = unsafe { &mut (*state_ptr).downloads.1 };
+                    let download_table = utils::get_downloads_container(&state_unlocked);
+                    screen.render_widget(widgets::Clear, position.downloads);
+                    screen.render_stateful_widget(
+                        download_table,
+                        position.downloads,
+                        download_state,
+                    );
                 }
             })
             .unwrap();
@@ -284,7 +306,9 @@ pub enum SidebarOption {
     Liked = 2,
     Saved = 3,
     Following = 4,
-    Search = 5,
+    RecentlyPlayed = 5,
+    LocalLibrary = 6,
+    Search = 7,
 }
 
 #[derive(PartialEq, Clone)]
@@ -292,10 +316,13 @@ pub enum Window {
     Searchbar,
     Sidebar,
     Musicbar,
+    Queue,
     Playlistbar,
     Artistbar,
     BottomControl,
     Popup(&'static str, String),
+    // List of downloads shown over everything else like popup
+    Downloads,
     None,
 }
 
@@ -307,9 +334,22 @@ pub struct BottomState {
     // false in Some means music is paused
     // None means playing nothing. eg: At the start of program
     playing: Option<(String, bool)>,
+    // Music was stopped from MPRIS. It is paused at the start and continues from there when played
+    stopped: bool,
+}
+
+// Searchbar is also used to type the name of user playlist. When state have some prompt, text typed
+// in searchbar is used to do this action on user playlist instead of searching
+#[derive(Clone, PartialEq)]
+pub enum Prompt {
+    // Add this music to the playlist with typed name
+    AddTo(fetcher::MusicUnit),
+    Create,
+    // Rename user playlist with this id
+    Rename(String),
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum MusicbarSource {
     Search(String),
     Trending,
@@ -317,21 +357,42 @@ pub enum MusicbarSource {
     Favourates,
     Playlist(String),
     Artist(String),
+    // Music files from local library. Some(term) when searching inside the library
+    Local(Option<String>),
 }
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum PlaylistbarSource {
     Search(String),
     RecentlyPlayed,
     Favourates,
     Artist(String),
 }
-#[derive(Clone, PartialEq, Eq, Debug)]
+#[derive(Clone, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum ArtistbarSource {
     Search(String),
     RecentlyPlayed,
     Favourates,
 }
 
+// Where to add music in queue
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum QueueMode {
+    // At the end of queue
+    Append,
+    // Right after the music being played
+    Next,
+}
+
+// Single entry of mpv's internal playlist
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QueueItem {
+    pub title: String,
+    // url or path as loaded in mpv
+    pub filename: String,
+    // true if this is the entry mpv is currently playing
+    pub current: bool,
+}
+
 #[derive(Debug)]
 pub struct PlaybackBehaviour {
     // true if user wishes to suffle the playlist
@@ -345,6 +406,65 @@ pub struct PlaybackBehaviour {
     volume: u8,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum DownloadStatus {
+    // Waiting for previous downloads to finish
+    Queued,
+    // Downloaded percentage
+    Running(f32),
+    Finished,
+    // Reason of failure
+    Failed(String),
+    Cancelled,
+}
+
+// Single item in download list. Downloads are run one at a time by downloader. See downloader.rs
+#[derive(Debug, Clone, PartialEq)]
+pub struct DownloadItem {
+    pub url: String,
+    pub title: String,
+    pub status: DownloadStatus,
+    // Where the url came from. These are written to tags of downloaded file
+    // Playlist is Some when it is downloaded as whole or music is downloaded as part of playlist
+    pub music: Option<fetcher::MusicUnit>,
+    pub playlist: Option<fetcher::PlaylistUnit>,
+}
+
+// Saved to session file when quitting and restored on next startup. So that user can continue from
+// where they left. See `State::save_session` and `State::restore_session`
+#[derive(Serialize, Deserialize)]
+pub struct Session {
+    // Item marked as current is resumed
+    pub queue: Vec<QueueItem>,
+    // Position in seconds of the current item
+    pub elapsed: f64,
+    pub volume: u8,
+    pub shuffle: bool,
+    pub repeat: bool,
+    pub filled_source: (MusicbarSource, PlaylistbarSource, ArtistbarSource),
+}
+
+// What player is doing at the moment. This is what `ytui_music ctl status` shows. See ipc.rs
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PlayerStatus {
+    // None when nothing is being played
+    pub title: Option<String>,
+    pub paused: bool,
+    // Paused at the start of music. See `State::stop`
+    #[serde(default)]
+    pub stopped: bool,
+    // Position and length of music being played in seconds
+    pub elapsed: u64,
+    pub duration: u64,
+    pub volume: u8,
+    pub shuffle: bool,
+    // true if whole queue is repeated, false if only the music being played is repeated
+    pub repeat_queue: bool,
+    pub queue_length: usize,
+    // Same as text shown in statusbar. eg: "Queued.."
+    pub message: String,
+}
+
 pub struct State<'p> {
     sidebar: ListState,
     bottom: BottomState,
@@ -363,17 +483,50 @@ pub struct State<'p> {
     // Same as playlistbar but keeps a list of artistbar
     pub artistbar: (Vec<fetcher::ArtistUnit>, TableState),
 
+    // Same as musicbar but this is not filled by communicator. Instead this always mirror the
+    // playlist of mpv. See `State::refresh_queue`
+    pub queue: (Vec<QueueItem>, TableState),
+
+    // mpv only knows the url of music appended by us. Title of those music is remembered here
+    // so that queue can show the title instead of url
+    pub queue_titles: std::collections::HashMap<String, String>,
+
+    // Playlist whose content is to be added in queue. As content of playlist needs to be fetched
+    // this is done by communicator. See `State::enqueue_musics`
+    pub to_queue: Option<(String, QueueMode)>,
+
+    // Playlists whose music not downloaded yet are to be queued for download. Content of
+    // playlist is fetched by communicator. See `State::sync_playlist_downloads`
+    pub to_download: Vec<fetcher::PlaylistUnit>,
+
+    // Youtube id of every downloaded music mapped to path where it was saved. This mirrors
+    // downloaded_music table so that musicbar can mark downloaded music without querying db
+    pub downloaded: std::collections::HashMap<String, String>,
+
+    // Position in seconds to seek to once music restored from session is loaded
+    pub resume_at: Option<f64>,
+
+    // Every download requested in this session and the focused item in downloads window
+    pub downloads: (Vec<DownloadItem>, TableState),
+
     // Defined the source in which the music/playlist/artist area are filled.
     // These are the values like Search(query) and is primerly needed while fecthing next/prev page of
     // the list
     pub filled_source: (MusicbarSource, PlaylistbarSource, ArtistbarSource),
 
+    // Set when user asks for local library so that communicator scan the disk again before
+    // filling musicbar. `local:` search and next pages only read what was found last time
+    pub rescan_library: bool,
+
     // First string is the actual string being typed on searchbar (to actually render)
     // If (musicbar or playlistbar or artistbar) is filled with search result
     // second memebr is Some(result_of_this_query) (to send to fetcher)
     // second member is the string of searchbar when use pressed ENTER last time in searchbar
     pub search: (String, String),
 
+    // Some when searchbar is being used to type playlist name. See Prompt
+    pub prompt: Option<Prompt>,
+
     // Currently active window. In UI, this windows title is hilighted and keypress are evaluated
     // depending on active window
     pub active: Window,
@@ -383,8 +536,8 @@ pub struct State<'p> {
     // next/prev respectively
     pub fetched_page: [Option<usize>; 3],
 
-    // Main handler for mpv player. This isw backed my libmpv library
-    pub player: libmpv::Mpv,
+    // Main handler for music player. This is backed by libmpv unless in tests. See player.rs
+    pub player: Arc<dyn player::Player>,
 
     // See documentation for respective struct
     pub playback_behaviour: PlaybackBehaviour,
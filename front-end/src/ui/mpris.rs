@@ -0,0 +1,542 @@
+use crate::ui;
+use config::initilize::CONFIG;
+use std::collections::HashMap;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+use zbus::{
+    fdo, interface,
+    object_server::SignalContext,
+    zvariant::{ObjectPath, OwnedValue, Str},
+};
+
+/*
+* Media keys of desktop and status bars like polybar/waybar (usually through playerctl) talk to
+* media players using MPRIS over the session D-Bus. Here ytui-music is exposed as
+* `org.mpris.MediaPlayer2.ytui_music` with the object at `/org/mpris/MediaPlayer2`.
+* -> Method calls like PlayPause and Next are done by calling the same state methods as key
+* handlers in event.rs
+* -> Properties are read from state when asked. As nobody asks for them unless notified, state is
+* also checked every refresh and PropertiesChanged is sent for what was changed
+* Set DBUS_SESSION_BUS_ADDRESS to use another bus. eg: private bus from `dbus-run-session`
+*/
+
+const OBJECT_PATH: &str = "/org/mpris/MediaPlayer2";
+const BUS_NAME: &str = "org.mpris.MediaPlayer2.ytui_music";
+// Track id to use when nothing is being played. This is reserved by MPRIS
+const NO_TRACK: &str = "/org/mpris/MediaPlayer2/TrackList/NoTrack";
+
+// MPRIS counts time in microseconds
+fn to_micros(secs: u64) -> i64 {
+    (secs as i64).saturating_mul(1_000_000)
+}
+
+fn playback_status(status: &ui::PlayerStatus) -> &'static str {
+    match (&status.title, status.paused) {
+        (None, _) => "Stopped",
+        (Some(_), _) if status.stopped => "Stopped",
+        (Some(_), true) => "Paused",
+        (Some(_), false) => "Playing",
+    }
+}
+
+fn loop_status(status: &ui::PlayerStatus) -> &'static str {
+    if status.repeat_queue {
+        "Playlist"
+    } else {
+        "Track"
+    }
+}
+
+// Every item in queue is a track identified by it's position in queue
+fn track_path(track: Option<usize>) -> String {
+    match track {
+        Some(index) => format!("/org/ytui_music/track/{}", index),
+        None => NO_TRACK.to_string(),
+    }
+}
+
+fn metadata(status: &ui::PlayerStatus, track: Option<usize>) -> HashMap<String, OwnedValue> {
+    let mut metadata = HashMap::new();
+    metadata.insert(
+        String::from("mpris:trackid"),
+        OwnedValue::from(ObjectPath::from_string_unchecked(track_path(track))),
+    );
+    if let Some(title) = status.title.as_ref().filter(|title| !title.is_empty()) {
+        metadata.insert(
+            String::from("xesam:title"),
+            OwnedValue::from(Str::from(title.clone())),
+        );
+    }
+    if status.duration > 0 {
+        metadata.insert(
+            String::from("mpris:length"),
+            OwnedValue::from(to_micros(status.duration)),
+        );
+    }
+    metadata
+}
+
+struct Root;
+
+#[interface(name = "org.mpris.MediaPlayer2")]
+impl Root {
+    // ytui-music lives in terminal. There is no window to raise and quitting is left to user
+    fn raise(&self) {}
+
+    fn quit(&self) {}
+
+    #[zbus(property)]
+    fn can_quit(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn can_raise(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn has_track_list(&self) -> bool {
+        false
+    }
+
+    #[zbus(property)]
+    fn identity(&self) -> String {
+        String::from("ytui-music")
+    }
+
+    #[zbus(property)]
+    fn supported_uri_schemes(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    #[zbus(property)]
+    fn supported_mime_types(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+struct Player {
+    state: Arc<Mutex<ui::State<'static>>>,
+    notifier: Arc<Condvar>,
+}
+
+impl Player {
+    // Do something with state and let ui know about it
+    fn with_state<T>(&self, action: impl FnOnce(&mut ui::State) -> T) -> T {
+        let mut state = self.state.lock().unwrap();
+        let res = action(&mut state);
+        std::mem::drop(state);
+        self.notifier.notify_all();
+        res
+    }
+
+    // Status of player along with position of music being played in queue
+    fn snapshot(&self) -> (ui::PlayerStatus, Option<usize>) {
+        let state = self.state.lock().unwrap();
+        let status = state.player_status();
+        let track = state.queue.0.iter().position(|item| item.current);
+        (status, track)
+    }
+}
+
+#[interface(name = "org.mpris.MediaPlayer2.Player")]
+impl Player {
+    fn next(&self) {
+        self.with_state(|state| state.player.play_next());
+    }
+
+    fn previous(&self) {
+        self.with_state(|state| state.player.play_prev());
+    }
+
+    fn pause(&self) {
+        self.with_state(|state| state.set_paused(true));
+    }
+
+    fn play(&self) {
+        self.with_state(|state| state.set_paused(false));
+    }
+
+    fn play_pause(&self) {
+        self.with_state(|state| state.toggle_pause());
+    }
+
+    fn stop(&self) {
+        self.with_state(|state| state.stop());
+    }
+
+    async fn seek(
+        &self,
+        offset: i64,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        let seconds = offset as f64 / 1_000_000.0;
+        self.with_state(|state| {
+            if seconds < 0.0 {
+                state.player.seek_backward(-seconds)
+            } else {
+                state.player.seek_forward(seconds)
+            }
+        })
+        .map_err(|_| fdo::Error::Failed(String::from("Cannot seek")))?;
+
+        Self::seeked(&ctxt, self.position()).await?;
+        Ok(())
+    }
+
+    async fn set_position(
+        &self,
+        track_id: ObjectPath<'_>,
+        position: i64,
+        #[zbus(signal_context)] ctxt: SignalContext<'_>,
+    ) -> fdo::Result<()> {
+        // Request for music that is no longer being played is ignored as told by MPRIS
+        let (status, track) = self.snapshot();
+        if track_id.as_str() != track_path(track)
+            || position < 0
+            || position > to_micros(status.duration)
+        {
+            return Ok(());
+        }
+
+        self.with_state(|state| state.player.seek_absolute(position as f64 / 1_000_000.0))
+            .map_err(|_| fdo::Error::Failed(String::from("Cannot seek")))?;
+
+        Self::seeked(&ctxt, position).await?;
+        Ok(())
+    }
+
+    fn open_uri(&self, _uri: String) -> fdo::Result<()> {
+        Err(fdo::Error::NotSupported(String::from(
+            "Use `ytui_music ctl play <url>` instead",
+        )))
+    }
+
+    #[zbus(signal)]
+    async fn seeked(ctxt: &SignalContext<'_>, position: i64) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn playback_status(&self) -> String {
+        playback_status(&self.snapshot().0).to_string()
+    }
+
+    #[zbus(property)]
+    fn loop_status(&self) -> String {
+        loop_status(&self.snapshot().0).to_string()
+    }
+
+    // ytui-music always repeat something. So "None" is not supported
+    #[zbus(property)]
+    fn set_loop_status(&self, loop_status: String) -> zbus::Result<()> {
+        let repeat_queue = match loop_status.as_str() {
+            "Playlist" => true,
+            "Track" => false,
+            _ => {
+                return Err(zbus::Error::from(fdo::Error::NotSupported(format!(
+                    "Loop status {} is not supported",
+                    loop_status
+                ))))
+            }
+        };
+        self.with_state(|state| state.set_repeat(repeat_queue));
+        Ok(())
+    }
+
+    #[zbus(property)]
+    fn rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn set_rate(&self, _rate: f64) {}
+
+    #[zbus(property)]
+    fn minimum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn maximum_rate(&self) -> f64 {
+        1.0
+    }
+
+    #[zbus(property)]
+    fn shuffle(&self) -> bool {
+        self.snapshot().0.shuffle
+    }
+
+    #[zbus(property)]
+    fn set_shuffle(&self, shuffle: bool) {
+        self.with_state(|state| state.set_shuffle(shuffle));
+    }
+
+    #[zbus(property)]
+    fn metadata(&self) -> HashMap<String, OwnedValue> {
+        let (status, track) = self.snapshot();
+        metadata(&status, track)
+    }
+
+    // MPRIS volume is between 0.0 and 1.0 while ours is between 0 and 100
+    #[zbus(property)]
+    fn volume(&self) -> f64 {
+        self.snapshot().0.volume as f64 / 100.0
+    }
+
+    #[zbus(property)]
+    fn set_volume(&self, volume: f64) {
+        let level = (volume.clamp(0.0, 1.0) * 100.0).round() as u8;
+        self.with_state(|state| {
+            if let Some(volume) = state.player.set_volume(level) {
+                state.playback_behaviour.volume = volume;
+            }
+        });
+    }
+
+    // Position changes all the time so clients are expected to ask for it instead
+    #[zbus(property(emits_changed_signal = "false"))]
+    fn position(&self) -> i64 {
+        to_micros(self.snapshot().0.elapsed)
+    }
+
+    #[zbus(property)]
+    fn can_go_next(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_go_previous(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_play(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_pause(&self) -> bool {
+        true
+    }
+
+    #[zbus(property)]
+    fn can_seek(&self) -> bool {
+        true
+    }
+
+    #[zbus(property(emits_changed_signal = "const"))]
+    fn can_control(&self) -> bool {
+        true
+    }
+}
+
+// Serve both interfaces in connection being built
+fn serve<'a>(
+    builder: zbus::connection::Builder<'a>,
+    state: &Arc<Mutex<ui::State<'static>>>,
+    notifier: &Arc<Condvar>,
+) -> zbus::Result<zbus::connection::Builder<'a>> {
+    let player = Player {
+        state: Arc::clone(state),
+        notifier: Arc::clone(notifier),
+    };
+    builder
+        .serve_at(OBJECT_PATH, Root)?
+        .serve_at(OBJECT_PATH, player)
+}
+
+async fn connect(
+    bus_name: &str,
+    state: &Arc<Mutex<ui::State<'static>>>,
+    notifier: &Arc<Condvar>,
+) -> zbus::Result<zbus::Connection> {
+    let builder = zbus::connection::Builder::session()?.name(bus_name)?;
+    serve(builder, state, notifier)?.build().await
+}
+
+// This runs in it's own thread like ipc listener until user quits. Nothing is done if there is no
+// session bus. eg: when running over ssh
+pub async fn server(state: &mut Arc<Mutex<ui::State<'static>>>, notifier: &mut Arc<Condvar>) {
+    // Another ytui-music may already have the name. MPRIS asks to add instance suffix then
+    let connection = match connect(BUS_NAME, state, notifier).await {
+        Ok(connection) => connection,
+        Err(_) => {
+            let instance_name = format!("{}.instance{}", BUS_NAME, std::process::id());
+            match connect(&instance_name, state, notifier).await {
+                Ok(connection) => connection,
+                Err(_) => return,
+            }
+        }
+    };
+    let player_ref = match connection
+        .object_server()
+        .interface::<_, Player>(OBJECT_PATH)
+        .await
+    {
+        Ok(player_ref) => player_ref,
+        Err(_) => return,
+    };
+
+    let mut previous: Option<(ui::PlayerStatus, Option<usize>)> = None;
+    loop {
+        tokio::time::sleep(Duration::from_millis(CONFIG.constants.refresh_rate)).await;
+        if state.lock().unwrap().active == ui::Window::None {
+            break;
+        }
+
+        let player = player_ref.get().await;
+        let ctxt = player_ref.signal_context();
+        let (status, track) = player.snapshot();
+        if let Some((prev_status, prev_track)) = &previous {
+            if prev_status.title != status.title
+                || prev_status.duration != status.duration
+                || *prev_track != track
+            {
+                player.metadata_changed(ctxt).await.ok();
+            }
+            if playback_status(prev_status) != playback_status(&status) {
+                player.playback_status_changed(ctxt).await.ok();
+            }
+            if prev_status.volume != status.volume {
+                player.volume_changed(ctxt).await.ok();
+            }
+            if prev_status.shuffle != status.shuffle {
+                player.shuffle_changed(ctxt).await.ok();
+            }
+            if prev_status.repeat_queue != status.repeat_queue {
+                player.loop_status_changed(ctxt).await.ok();
+            }
+        }
+        previous = Some((status, track));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(title: Option<&str>, paused: bool) -> ui::PlayerStatus {
+        ui::PlayerStatus {
+            title: title.map(str::to_string),
+            paused,
+            stopped: false,
+            elapsed: 10,
+            duration: 185,
+            volume: 80,
+            shuffle: false,
+            repeat_queue: true,
+            queue_length: 3,
+            message: String::new(),
+        }
+    }
+
+    #[test]
+    fn status_as_mpris() {
+        assert_eq!(playback_status(&status(None, false)), "Stopped");
+        assert_eq!(playback_status(&status(Some("Song"), true)), "Paused");
+        assert_eq!(playback_status(&status(Some("Song"), false)), "Playing");
+        assert_eq!(loop_status(&status(None, false)), "Playlist");
+
+        let metadata = metadata(&status(Some("Song"), false), Some(2));
+        assert_eq!(
+            metadata["mpris:trackid"],
+            OwnedValue::from(ObjectPath::from_static_str_unchecked(
+                "/org/ytui_music/track/2"
+            ))
+        );
+        assert_eq!(<&str>::try_from(&metadata["xesam:title"]).unwrap(), "Song");
+        assert_eq!(
+            i64::try_from(&metadata["mpris:length"]).unwrap(),
+            185_000_000
+        );
+        let mut stopped = status(Some("Song"), true);
+        stopped.stopped = true;
+        assert_eq!(playback_status(&stopped), "Stopped");
+
+        // Nothing is being played
+        let mut stopped = status(None, false);
+        stopped.duration = 0;
+        let metadata = super::metadata(&stopped, None);
+        assert_eq!(metadata.len(), 1);
+        assert_eq!(
+            metadata["mpris:trackid"],
+            OwnedValue::from(ObjectPath::from_static_str_unchecked(NO_TRACK))
+        );
+    }
+
+    // Client talks to player served over a private connection like playerctl does over session bus
+    #[tokio::test]
+    async fn serve_over_bus() {
+        use crate::ui::playback::{self, PlayerEvent};
+        use crate::ui::player::{FakePlayer, Player as _};
+        use zbus::connection::Builder;
+
+        let fake = Arc::new(FakePlayer::default());
+        let mut state = ui::State::with_player(Arc::clone(&fake) as _);
+        state.enqueue_musics(
+            &[fetcher::test_music("a"), fetcher::test_music("b")],
+            ui::QueueMode::Append,
+        );
+        // Observer is not running. Tell state what mpv would have told
+        playback::apply(PlayerEvent::Title("Song".to_string()), &mut state);
+        playback::apply(PlayerEvent::Duration(185.0), &mut state);
+        let state = Arc::new(Mutex::new(state));
+
+        let (ours, theirs) = tokio::net::UnixStream::pair().unwrap();
+        let server = Builder::unix_stream(ours)
+            .server(zbus::Guid::generate())
+            .unwrap()
+            .p2p();
+        let server = serve(server, &state, &Arc::new(Condvar::new()))
+            .unwrap()
+            .build();
+        let client = Builder::unix_stream(theirs).p2p().build();
+        let (_server, client) = tokio::try_join!(server, client).unwrap();
+
+        let proxy: zbus::Proxy = zbus::proxy::Builder::new(&client)
+            .destination(BUS_NAME)
+            .unwrap()
+            .path(OBJECT_PATH)
+            .unwrap()
+            .interface("org.mpris.MediaPlayer2.Player")
+            .unwrap()
+            .cache_properties(zbus::proxy::CacheProperties::No)
+            .build()
+            .await
+            .unwrap();
+        let playback_status = || async {
+            proxy
+                .get_property::<String>("PlaybackStatus")
+                .await
+                .unwrap()
+        };
+
+        assert_eq!(playback_status().await, "Playing");
+        let metadata: HashMap<String, OwnedValue> = proxy.get_property("Metadata").await.unwrap();
+        assert_eq!(<&str>::try_from(&metadata["xesam:title"]).unwrap(), "Song");
+        assert_eq!(
+            i64::try_from(&metadata["mpris:length"]).unwrap(),
+            185_000_000
+        );
+
+        proxy.call_method("PlayPause", &()).await.unwrap();
+        assert_eq!(playback_status().await, "Paused");
+        assert!(fake.state.lock().unwrap().paused);
+
+        proxy.call_method("Seek", &(30_000_000_i64)).await.unwrap();
+        assert_eq!(fake.state.lock().unwrap().elapsed, 30.0);
+
+        proxy.call_method("Next", &()).await.unwrap();
+        assert_eq!(fake.queue_position(), Some(1));
+
+        proxy.call_method("Stop", &()).await.unwrap();
+        assert_eq!(playback_status().await, "Stopped");
+        assert_eq!(fake.state.lock().unwrap().elapsed, 0.0);
+        proxy.call_method("Play", &()).await.unwrap();
+        assert_eq!(playback_status().await, "Playing");
+        assert!(!fake.state.lock().unwrap().paused);
+
+        // ytui-music always repeats something
+        assert!(proxy.set_property("LoopStatus", "None").await.is_err());
+    }
+}
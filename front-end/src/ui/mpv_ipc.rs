@@ -0,0 +1,548 @@
+use crate::ui::playback::PlayerEvent;
+use crate::ui::player::{ChannelEvents, Player, PlayerEvents, PlayerResult, QueueEntry};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::net::Shutdown;
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Mutex, Once};
+use std::time::{Duration, Instant};
+
+/*
+* Player that runs mpv binary and controls it over it's json ipc. This needs only mpv to be
+* installed instead of libmpv at build time. See https://mpv.io/manual/stable/#json-ipc
+* Every request is a json line with `request_id` and mpv replies with a line having the same
+* `request_id`. Events come in the same socket, so a reader thread splits them: replies go to
+* whoever is waiting in `request` and events go to `ChannelEvents` read by observer.
+* mpv is run with --idle so it never quits by itself. It is killed when `IpcPlayer` is dropped,
+* when we panic and (on linux) when we die in any other way. eg: std::process::exit
+*/
+
+// Properties that mpv will tell us about. Index is used as observe id
+const OBSERVED: [&str; 6] = [
+    "pause",
+    "media-title",
+    "duration",
+    "time-pos",
+    "idle-active",
+    // Only used to know that queue has changed. Queue itself is read by observer
+    "playlist",
+];
+
+// mpv that does not reply or start listening in this time is taken as stuck
+const IPC_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Reply = Result<Value, String>;
+
+// Pid of mpv to kill if we panic. 0 when there is none
+static RUNNING_MPV: AtomicU32 = AtomicU32::new(0);
+
+struct Connection {
+    stream: UnixStream,
+    replies: Receiver<(u64, Reply)>,
+    last_id: u64,
+}
+
+#[derive(Deserialize)]
+struct PlaylistEntry {
+    filename: String,
+    title: Option<String>,
+}
+
+pub struct IpcPlayer {
+    connection: Mutex<Connection>,
+    events: Mutex<Option<Receiver<PlayerEvent>>>,
+    // mpv process and it's socket. None when mpv was not run by us. eg: in tests
+    mpv: Option<(Child, PathBuf)>,
+}
+
+fn request_line(id: u64, command: &Value) -> String {
+    format!("{}\n", json!({ "command": command, "request_id": id }))
+}
+
+// Error is "success" when request went well
+fn parse_reply(message: &Value) -> Option<(u64, Reply)> {
+    let id = message["request_id"].as_u64()?;
+    let reply = match message["error"].as_str() {
+        Some("success") => Ok(message["data"].clone()),
+        Some(error) => Err(error.to_string()),
+        None => Err(String::from("Invalid reply from mpv")),
+    };
+    Some((id, reply))
+}
+
+fn event_from_json(message: &Value) -> Option<PlayerEvent> {
+    match message["event"].as_str()? {
+        "property-change" => {
+            let data = &message["data"];
+            match message["name"].as_str()? {
+                "pause" => data.as_bool().map(PlayerEvent::Paused),
+                "media-title" => data
+                    .as_str()
+                    .map(|title| PlayerEvent::Title(title.to_string())),
+                "duration" => data.as_f64().map(PlayerEvent::Duration),
+                "time-pos" => data.as_f64().map(PlayerEvent::Elapsed),
+                "idle-active" => data.as_bool().map(PlayerEvent::Idle),
+                "playlist" => Some(PlayerEvent::QueueChanged),
+                _ => None,
+            }
+        }
+        "start-file" => Some(PlayerEvent::Started),
+        "file-loaded" => Some(PlayerEvent::Loaded),
+        "end-file" => Some(PlayerEvent::Ended),
+        "shutdown" => Some(PlayerEvent::Shutdown),
+        _ => None,
+    }
+}
+
+// Runs in it's own thread until socket is closed
+fn read_messages(stream: UnixStream, replies: Sender<(u64, Reply)>, events: Sender<PlayerEvent>) {
+    for line in BufReader::new(stream).lines() {
+        let message: Value = match line {
+            Ok(line) => match serde_json::from_str(&line) {
+                Ok(message) => message,
+                Err(_) => continue,
+            },
+            Err(_) => break,
+        };
+        if let Some(reply) = parse_reply(&message) {
+            replies.send(reply).ok();
+        } else if let Some(event) = event_from_json(&message) {
+            events.send(event).ok();
+        }
+    }
+    // mpv is gone. Nothing will be played anymore
+    events.send(PlayerEvent::Shutdown).ok();
+}
+
+// mpv takes a moment before it starts listening in socket
+fn connect(child: &mut Child, socket_path: &Path) -> Result<UnixStream, String> {
+    let deadline = Instant::now() + IPC_TIMEOUT;
+    loop {
+        if let Ok(stream) = UnixStream::connect(socket_path) {
+            return Ok(stream);
+        }
+        if let Ok(Some(status)) = child.try_wait() {
+            return Err(format!("mpv exited with {}", status));
+        }
+        if Instant::now() > deadline {
+            return Err(String::from("mpv did not open ipc socket"));
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+// Kernel kills mpv as soon as thread that started it dies. Player is always created by main thread
+#[cfg(target_os = "linux")]
+fn die_with_parent(command: &mut Command) {
+    use std::os::unix::process::CommandExt;
+    let parent = std::process::id() as libc::pid_t;
+    // Safety: only async-signal-safe functions are called between fork and exec
+    unsafe {
+        command.pre_exec(move || {
+            if libc::prctl(libc::PR_SET_PDEATHSIG, libc::SIGKILL) == -1 {
+                return Err(std::io::Error::last_os_error());
+            }
+            // We may have died before prctl was called
+            if libc::getppid() != parent {
+                libc::_exit(0);
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn die_with_parent(_command: &mut Command) {}
+
+// Drop is not run for value of thread that panics, nor for state shared with other threads
+fn kill_on_panic(pid: u32) {
+    RUNNING_MPV.store(pid, Ordering::SeqCst);
+    static HOOK: Once = Once::new();
+    HOOK.call_once(|| {
+        let default_hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(move |info| {
+            let pid = RUNNING_MPV.swap(0, Ordering::SeqCst);
+            if pid != 0 {
+                // Safety: kill does not touch memory of this process
+                unsafe {
+                    libc::kill(pid as libc::pid_t, libc::SIGKILL);
+                }
+            }
+            default_hook(info);
+        }));
+    });
+}
+
+impl IpcPlayer {
+    pub fn new(executable: &str) -> Result<Self, String> {
+        let config_dir = config::ConfigContainer::get_config_dir().unwrap();
+        let socket_path = config::ConfigContainer::get_mpv_socket_path().unwrap();
+        // Left by some old process with same pid
+        std::fs::remove_file(&socket_path).ok();
+
+        let mut command = Command::new(executable);
+        // mpv.conf of config directory is included instead of the one mpv would load itself
+        command.arg("--no-config");
+        // User may ask to run without their mpv options. eg: to find if mpv.conf is causing trouble
+        if !config::initilize::OVERRIDES.lock().unwrap().no_mpv_config {
+            let mpv_config_path = config_dir.join(config::MPV_OPTION_FILE_NAME);
+            command.arg(format!("--include={}", mpv_config_path.display()));
+        }
+        // Given after include so that these override config file
+        command
+            .arg(format!("--config-dir={}", config_dir.display()))
+            .arg(format!("--input-ipc-server={}", socket_path.display()))
+            .args([
+                "--idle=yes",
+                "--no-terminal",
+                "--video=no",
+                "--cache-secs=10",
+            ])
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        die_with_parent(&mut command);
+
+        let mut child = command
+            .spawn()
+            .map_err(|err| format!("Cannot run {}. Error: {}", executable, err))?;
+        kill_on_panic(child.id());
+        match connect(&mut child, &socket_path) {
+            Ok(stream) => Self::with_stream(stream, Some((child, socket_path))),
+            Err(err) => {
+                RUNNING_MPV.store(0, Ordering::SeqCst);
+                child.kill().ok();
+                child.wait().ok();
+                Err(err)
+            }
+        }
+    }
+
+    fn with_stream(stream: UnixStream, mpv: Option<(Child, PathBuf)>) -> Result<Self, String> {
+        let reader = stream.try_clone().map_err(|err| err.to_string())?;
+        let (reply_sender, replies) = mpsc::channel();
+        let (event_sender, events) = mpsc::channel();
+        std::thread::spawn(move || read_messages(reader, reply_sender, event_sender));
+
+        Ok(IpcPlayer {
+            connection: Mutex::new(Connection {
+                stream,
+                replies,
+                last_id: 0,
+            }),
+            events: Mutex::new(Some(events)),
+            mpv,
+        })
+    }
+
+    // Only one request is sent at a time so the reply is the one we are waiting for
+    // unless it is a late reply of request that was timed out before
+    fn request(&self, command: Value) -> Reply {
+        let mut connection = self.connection.lock().unwrap();
+        connection.last_id += 1;
+        let id = connection.last_id;
+        connection
+            .stream
+            .write_all(request_line(id, &command).as_bytes())
+            .map_err(|err| err.to_string())?;
+
+        let deadline = Instant::now() + IPC_TIMEOUT;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            match connection.replies.recv_timeout(remaining) {
+                Ok((reply_id, reply)) if reply_id == id => return reply,
+                Ok(_) => continue,
+                Err(RecvTimeoutError::Timeout) => return Err(String::from("mpv did not reply")),
+                Err(RecvTimeoutError::Disconnected) => return Err(String::from("mpv has exited")),
+            }
+        }
+    }
+
+    fn run(&self, command: Value) -> PlayerResult {
+        self.request(command).map(|_| ())
+    }
+
+    fn get_property<T: serde::de::DeserializeOwned>(&self, name: &str) -> Option<T> {
+        let value = self.request(json!(["get_property", name])).ok()?;
+        serde_json::from_value(value).ok()
+    }
+
+    fn set_property(&self, name: &str, value: Value) -> PlayerResult {
+        self.run(json!(["set_property", name, value]))
+    }
+}
+
+impl Drop for IpcPlayer {
+    fn drop(&mut self) {
+        // Reader thread stops once socket is closed
+        if let Ok(connection) = self.connection.get_mut() {
+            connection.stream.shutdown(Shutdown::Both).ok();
+        }
+        if let Some((child, socket_path)) = &mut self.mpv {
+            RUNNING_MPV
+                .compare_exchange(child.id(), 0, Ordering::SeqCst, Ordering::SeqCst)
+                .ok();
+            child.kill().ok();
+            child.wait().ok();
+            std::fs::remove_file(socket_path).ok();
+        }
+    }
+}
+
+impl Player for IpcPlayer {
+    fn load(&self, url: &str) -> PlayerResult {
+        self.run(json!(["loadfile", url]))
+    }
+
+    fn append(&self, url: &str, play: bool) -> PlayerResult {
+        let mode = if play { "append-play" } else { "append" };
+        self.run(json!(["loadfile", url, mode]))
+    }
+
+    fn pause(&self) -> PlayerResult {
+        self.set_property("pause", json!(true))
+    }
+
+    fn unpause(&self) -> PlayerResult {
+        self.set_property("pause", json!(false))
+    }
+
+    fn seek_forward(&self, secs: f64) -> PlayerResult {
+        self.run(json!(["seek", secs, "relative"]))
+    }
+
+    fn seek_backward(&self, secs: f64) -> PlayerResult {
+        self.run(json!(["seek", -secs, "relative"]))
+    }
+
+    fn seek_absolute(&self, secs: f64) -> PlayerResult {
+        self.run(json!(["seek", secs, "absolute"]))
+    }
+
+    fn elapsed(&self) -> Option<f64> {
+        self.get_property("time-pos")
+    }
+
+    fn title(&self) -> Option<String> {
+        self.get_property("media-title")
+    }
+
+    fn play_next(&self) {
+        self.run(json!(["playlist-next", "weak"])).ok();
+    }
+
+    fn play_prev(&self) {
+        self.run(json!(["playlist-prev", "weak"])).ok();
+    }
+
+    fn get_volume(&self) -> Option<f64> {
+        self.get_property("volume")
+    }
+
+    fn set_volume(&self, level: u8) -> Option<u8> {
+        let level = level.min(100);
+        self.set_property("volume", json!(level))
+            .ok()
+            .map(|_| level)
+    }
+
+    fn repeat_playlist(&self) {
+        self.set_property("loop-playlist", json!("inf")).ok();
+    }
+
+    fn repeat_one(&self) {
+        self.set_property("loop-file", json!("inf")).ok();
+    }
+
+    fn repeat_nothing(&self) {
+        self.set_property("loop-playlist", json!("no")).ok();
+        self.set_property("loop-file", json!("no")).ok();
+    }
+
+    fn shuffle(&self) {
+        self.run(json!(["playlist-shuffle"])).ok();
+    }
+
+    fn unshuffle(&self) {
+        self.run(json!(["playlist-unshuffle"])).ok();
+    }
+
+    // Whole playlist is asked at once instead of one property per music as libmpv does
+    fn queue(&self) -> Vec<QueueEntry> {
+        self.get_property::<Vec<PlaylistEntry>>("playlist")
+            .unwrap_or_default()
+            .into_iter()
+            .map(|entry| QueueEntry {
+                url: entry.filename,
+                title: entry.title,
+            })
+            .collect()
+    }
+
+    fn queue_length(&self) -> usize {
+        self.get_property::<i64>("playlist-count")
+            .unwrap_or_default()
+            .max(0) as usize
+    }
+
+    fn queue_position(&self) -> Option<usize> {
+        let position = self.get_property::<i64>("playlist-pos")?;
+        usize::try_from(position).ok()
+    }
+
+    fn play_index(&self, index: usize) -> PlayerResult {
+        self.set_property("playlist-pos", json!(index))
+    }
+
+    fn remove_index(&self, index: usize) -> PlayerResult {
+        self.run(json!(["playlist-remove", index]))
+    }
+
+    fn move_index(&self, from: usize, to: usize) -> PlayerResult {
+        self.run(json!(["playlist-move", from, to]))
+    }
+
+    // Panics if called more than once
+    fn events(&self) -> Box<dyn PlayerEvents + '_> {
+        let receiver = self.events.lock().unwrap().take();
+        let receiver = receiver.expect("Events are already taken");
+        for (index, name) in OBSERVED.iter().enumerate() {
+            self.run(json!(["observe_property", index, name])).ok();
+        }
+        Box::new(ChannelEvents(receiver))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipc_messages() {
+        assert_eq!(
+            request_line(3, &json!(["seek", 10.0, "relative"])),
+            "{\"command\":[\"seek\",10.0,\"relative\"],\"request_id\":3}\n"
+        );
+
+        let reply = json!({ "data": 52.0, "error": "success", "request_id": 7 });
+        assert_eq!(parse_reply(&reply), Some((7, Ok(json!(52.0)))));
+        let reply = json!({ "error": "property unavailable", "request_id": 8 });
+        assert_eq!(
+            parse_reply(&reply),
+            Some((8, Err("property unavailable".to_string())))
+        );
+
+        let change = |name, data| {
+            event_from_json(
+                &json!({ "event": "property-change", "id": 0, "name": name, "data": data }),
+            )
+        };
+        assert_eq!(
+            change("pause", json!(true)),
+            Some(PlayerEvent::Paused(true))
+        );
+        assert_eq!(
+            change("media-title", json!("Song")),
+            Some(PlayerEvent::Title("Song".to_string()))
+        );
+        assert_eq!(
+            change("idle-active", json!(false)),
+            Some(PlayerEvent::Idle(false))
+        );
+        assert_eq!(
+            change("playlist", json!([])),
+            Some(PlayerEvent::QueueChanged)
+        );
+        // Property that is not available yet has no data
+        assert_eq!(change("duration", Value::Null), None);
+
+        // Events are not mistaken for replies and the other way around
+        let event = json!({ "event": "file-loaded" });
+        assert_eq!(parse_reply(&event), None);
+        assert_eq!(event_from_json(&event), Some(PlayerEvent::Loaded));
+        assert_eq!(event_from_json(&reply), None);
+    }
+
+    #[test]
+    fn talk_to_mpv() {
+        let (ours, theirs) = UnixStream::pair().unwrap();
+        let player = IpcPlayer::with_stream(ours, None).unwrap();
+
+        // Pretends to be mpv. Every command is answered and remembered
+        let mpv = std::thread::spawn(move || {
+            let mut writer = theirs.try_clone().unwrap();
+            let mut commands = Vec::new();
+            for line in BufReader::new(theirs).lines() {
+                let request: Value = serde_json::from_str(&line.unwrap()).unwrap();
+                let command = request["command"].clone();
+                let (error, data) = match (command[0].as_str(), command[1].as_str()) {
+                    (Some("get_property"), Some("volume")) => ("success", json!(40.0)),
+                    (Some("get_property"), Some("playlist")) => (
+                        "success",
+                        json!([
+                            { "filename": "a", "current": true },
+                            { "filename": "b", "title": "B" }
+                        ]),
+                    ),
+                    (Some("playlist-remove"), _) => ("invalid parameter", Value::Null),
+                    _ => ("success", Value::Null),
+                };
+                // Events may come before reply
+                writeln!(
+                    writer,
+                    "{}",
+                    json!({ "event": "property-change", "id": 0, "name": "pause", "data": true })
+                )
+                .unwrap();
+                let reply =
+                    json!({ "request_id": request["request_id"], "error": error, "data": data });
+                writeln!(writer, "{}", reply).unwrap();
+                commands.push(command);
+            }
+            commands
+        });
+
+        let mut events = player.events();
+        assert_eq!(player.change_volume(10), Some(50));
+        player.seek_backward(5.0).unwrap();
+        player.repeat_one();
+        player.play_next();
+        assert_eq!(
+            player.queue(),
+            vec![
+                QueueEntry {
+                    url: "a".to_string(),
+                    title: None
+                },
+                QueueEntry {
+                    url: "b".to_string(),
+                    title: Some("B".to_string())
+                }
+            ]
+        );
+        assert_eq!(player.remove_index(7), Err("invalid parameter".to_string()));
+        assert_eq!(events.wait_event(1.0), Some(PlayerEvent::Paused(true)));
+
+        std::mem::drop(events);
+        std::mem::drop(player);
+        let commands = mpv.join().unwrap();
+        assert_eq!(commands[0], json!(["observe_property", 0, "pause"]));
+        assert_eq!(
+            commands[OBSERVED.len()..],
+            [
+                json!(["get_property", "volume"]),
+                json!(["set_property", "volume", 50]),
+                json!(["seek", -5.0, "relative"]),
+                json!(["set_property", "loop-file", "inf"]),
+                json!(["playlist-next", "weak"]),
+                json!(["get_property", "playlist"]),
+                json!(["playlist-remove", 7]),
+            ]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use crate::ui;
+use crate::ui::player::{Player, PlayerEvents};
+use config::initilize::CONFIG;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+/*
+* Instead of asking player for title, position and duration on every paint, player tells when
+* any of those change. Observer waits for these events in it's own thread and keeps
+* `State.bottom` and queue up to date, so anything reading the state (ui, ipc, mpris) just reads
+* it without talking to the player.
+* Events of mpv are turned into `PlayerEvent` in player.rs. Reacting to track change (history,
+* notifications, ..) can be done when `PlayerEvent::Loaded` is applied.
+*/
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlayerEvent {
+    Paused(bool),
+    Title(String),
+    Duration(f64),
+    Elapsed(f64),
+    // Nothing left to play when true
+    Idle(bool),
+    QueueChanged,
+    // Music has started loading
+    Started,
+    // Music is loaded and about to be played
+    Loaded,
+    Ended,
+    Shutdown,
+}
+
+// mpv may report negative or nan time while file is being loaded
+fn to_duration(secs: f64) -> Duration {
+    if secs.is_finite() && secs > 0.0 {
+        Duration::from_secs(secs as u64)
+    } else {
+        Duration::from_secs(0)
+    }
+}
+
+// Returns true if there is something new to show in ui
+pub fn apply(event: PlayerEvent, state: &mut ui::State) -> bool {
+    match event {
+        PlayerEvent::Paused(paused) => {
+            if let Some((_, is_playing)) = &mut state.bottom.playing {
+                *is_playing = !paused;
+            }
+        }
+        PlayerEvent::Title(title) => {
+            if let Some((name, _)) = &mut state.bottom.playing {
+                *name = title;
+            }
+        }
+        PlayerEvent::Duration(secs) => state.bottom.music_duration = to_duration(secs),
+        // Position changes many times a second but ui only shows seconds
+        PlayerEvent::Elapsed(secs) => {
+            let elapsed = to_duration(secs);
+            if elapsed == state.bottom.music_elapse {
+                return false;
+            }
+            state.bottom.music_elapse = elapsed;
+        }
+        PlayerEvent::Idle(true) => {
+            if let Some((name, _)) = &mut state.bottom.playing {
+                *name = String::from(">> Play some music <<");
+            }
+            state.bottom.music_elapse = Duration::from_secs(0);
+            state.bottom.music_duration = Duration::from_secs(0);
+        }
+        // Next music is not stopped even if the last one was
+        PlayerEvent::Started => state.bottom.stopped = false,
+        PlayerEvent::Idle(false) | PlayerEvent::Shutdown => return false,
+        // Queue is read by observer once for all pending changes. See `observe_once`
+        PlayerEvent::QueueChanged => return false,
+        PlayerEvent::Loaded => {
+            // Music is now loaded so it can be seeked to where it was left last time
+            if let Some(position) = state.resume_at.take() {
+                state.player.seek_absolute(position).ok();
+            }
+        }
+        PlayerEvent::Ended => {
+            state.bottom.music_elapse = Duration::from_secs(0);
+            state.bottom.music_duration = Duration::from_secs(0);
+        }
+    }
+    true
+}
+
+// Events taken at once. Player that never stops sending (eg: position) still gets it's turn
+const MAX_PENDING: usize = 256;
+
+// Wait for something to happen and take everything else that has happened along with it
+fn pending_events(events: &mut dyn PlayerEvents, timeout: f64) -> Vec<PlayerEvent> {
+    let mut pending: Vec<PlayerEvent> = events.wait_event(timeout).into_iter().collect();
+    while !pending.is_empty() && pending.len() < MAX_PENDING {
+        match events.wait_event(0.0) {
+            Some(event) => pending.push(event),
+            None => break,
+        }
+    }
+    pending
+}
+
+// Apply what player did since last time. None is returned when observing should stop, otherwise
+// if ui should be repainted
+fn observe_once(
+    player: &dyn Player,
+    events: &mut dyn PlayerEvents,
+    state: &Mutex<ui::State<'static>>,
+    timeout: f64,
+) -> Option<bool> {
+    // Queue changes come in bursts. eg: one for every music of playlist being appended.
+    // Queue is read once for the whole burst and before locking the state, as reading it asks
+    // player about every music in queue. Queue changed meanwhile is read again on it's own event
+    let pending = pending_events(events, timeout);
+    let queue = pending
+        .contains(&PlayerEvent::QueueChanged)
+        .then(|| (player.queue(), player.queue_position()));
+
+    let mut state = state.lock().unwrap();
+    if state.active == ui::Window::None || pending.contains(&PlayerEvent::Shutdown) {
+        return None;
+    }
+    let mut repaint = false;
+    for event in pending {
+        repaint |= apply(event, &mut state);
+    }
+    if let Some((queue, position)) = queue {
+        state.show_queue(queue, position);
+        repaint = true;
+    }
+    Some(repaint)
+}
+
+// This runs in it's own thread until user quits. Unlike other threads this one blocks while
+// waiting for player so it does not need async runtime
+pub fn observer(state: &mut Arc<Mutex<ui::State<'static>>>, notifier: &mut Arc<Condvar>) {
+    // Player is kept by this thread so that events can be read without locking state
+    let player = Arc::clone(&state.lock().unwrap().player);
+    let mut events = player.events();
+
+    let timeout = CONFIG.constants.refresh_rate as f64 / 1000.0;
+    while let Some(repaint) = observe_once(player.as_ref(), events.as_mut(), state, timeout) {
+        if repaint {
+            notifier.notify_all();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::player::FakePlayer;
+
+    #[test]
+    fn apply_events() {
+        let mut state = ui::State::with_player(Arc::new(FakePlayer::default()));
+        // Nothing is shown until something is played
+        apply(PlayerEvent::Title("Song".to_string()), &mut state);
+        assert_eq!(state.bottom.playing, None);
+
+        state.bottom.playing = Some((String::new(), true));
+        apply(PlayerEvent::Title("Song".to_string()), &mut state);
+        apply(PlayerEvent::Paused(true), &mut state);
+        apply(PlayerEvent::Duration(185.2), &mut state);
+        assert_eq!(state.bottom.playing, Some(("Song".to_string(), false)));
+        assert_eq!(state.bottom.music_duration, Duration::from_secs(185));
+
+        assert!(apply(PlayerEvent::Elapsed(10.2), &mut state));
+        assert!(!apply(PlayerEvent::Elapsed(10.8), &mut state));
+        assert_eq!(state.bottom.music_elapse, Duration::from_secs(10));
+
+        apply(PlayerEvent::Idle(true), &mut state);
+        assert_eq!(
+            state.bottom.playing,
+            Some((">> Play some music <<".to_string(), false))
+        );
+        assert_eq!(state.bottom.music_elapse, Duration::from_secs(0));
+
+        assert_eq!(to_duration(-0.5), Duration::from_secs(0));
+        assert_eq!(to_duration(f64::NAN), Duration::from_secs(0));
+    }
+
+    #[test]
+    fn queue_read_once_per_burst() {
+        let player = Arc::new(FakePlayer::default());
+        let state = Mutex::new(ui::State::with_player(Arc::clone(&player) as _));
+        let mut events = player.events();
+        for index in 0..50 {
+            player.append(&format!("music {}", index), true).unwrap();
+        }
+
+        assert_eq!(
+            observe_once(player.as_ref(), events.as_mut(), &state, 0.0),
+            Some(true)
+        );
+        assert_eq!(player.state.lock().unwrap().queue_reads, 1);
+        let state_unlocked = state.lock().unwrap();
+        assert_eq!(state_unlocked.queue.0.len(), 50);
+        assert!(state_unlocked.queue.0[0].current);
+        std::mem::drop(state_unlocked);
+
+        // Nothing happened
+        assert_eq!(
+            observe_once(player.as_ref(), events.as_mut(), &state, 0.0),
+            Some(false)
+        );
+        assert_eq!(player.state.lock().unwrap().queue_reads, 1);
+    }
+}
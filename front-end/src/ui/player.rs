@@ -0,0 +1,637 @@
+use crate::ui::playback::PlayerEvent;
+#[cfg(feature = "libmpv")]
+use libmpv::events::{Event, EventContext, PropertyData};
+#[cfg(feature = "libmpv")]
+use libmpv::Format;
+
+/*
+* Everything ytui-music asks from the music player. State only talks to the player through
+* `Player` so that the player itself can be swapped. eg: `FakePlayer` in tests
+* Queue of the player is the queue shown in ui. See `State::refresh_queue`
+* What player is doing (title, position, pause, ..) is not asked. Player tells it as
+* `PlayerEvent` and observer keeps the state updated. See playback.rs
+* mpv is either linked as libmpv (`MpvPlayer`) or run as separate process and controlled over it's
+* json ipc (`IpcPlayer` in mpv_ipc.rs). Config decides which one is used. See `from_config`
+*/
+
+pub type PlayerResult = Result<(), String>;
+
+// Music in the queue of player
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueEntry {
+    // What was given to load or append
+    pub url: String,
+    // Title if player itself knows it. eg: music from youtube playlist
+    pub title: Option<String>,
+}
+
+pub trait PlayerEvents: Send {
+    // Wait for at most timeout seconds for something to happen. Negative timeout waits forever
+    fn wait_event(&mut self, timeout: f64) -> Option<PlayerEvent>;
+}
+
+// Events sent by player from another thread
+pub struct ChannelEvents(pub std::sync::mpsc::Receiver<PlayerEvent>);
+
+impl PlayerEvents for ChannelEvents {
+    fn wait_event(&mut self, timeout: f64) -> Option<PlayerEvent> {
+        if timeout < 0.0 {
+            self.0.recv().ok()
+        } else {
+            self.0
+                .recv_timeout(std::time::Duration::from_secs_f64(timeout))
+                .ok()
+        }
+    }
+}
+
+pub trait Player: Send + Sync {
+    // Play url right away replacing whole queue
+    fn load(&self, url: &str) -> PlayerResult;
+    // Add url at the end of queue. If play is true, it also starts playing if nothing else is
+    fn append(&self, url: &str, play: bool) -> PlayerResult;
+
+    fn pause(&self) -> PlayerResult;
+    fn unpause(&self) -> PlayerResult;
+    fn seek_forward(&self, secs: f64) -> PlayerResult;
+    fn seek_backward(&self, secs: f64) -> PlayerResult;
+    fn seek_absolute(&self, secs: f64) -> PlayerResult;
+    // Position of music being played in seconds
+    fn elapsed(&self) -> Option<f64>;
+    // Title of music being played. State is kept updated by events instead of asking this
+    fn title(&self) -> Option<String>;
+    fn play_next(&self);
+    fn play_prev(&self);
+
+    // Volume is between 0 and 100. Volume that was actually set is returned
+    fn get_volume(&self) -> Option<f64>;
+    fn set_volume(&self, level: u8) -> Option<u8>;
+    fn change_volume(&self, step: i8) -> Option<u8> {
+        let current = self.get_volume()?;
+        self.set_volume((current + step as f64).clamp(0.0, 100.0) as u8)
+    }
+
+    fn repeat_playlist(&self);
+    fn repeat_one(&self);
+    fn repeat_nothing(&self);
+    fn shuffle(&self);
+    fn unshuffle(&self);
+
+    fn queue(&self) -> Vec<QueueEntry>;
+    fn queue_length(&self) -> usize {
+        self.queue().len()
+    }
+    // Index of music being played in queue
+    fn queue_position(&self) -> Option<usize>;
+    fn play_index(&self, index: usize) -> PlayerResult;
+    fn remove_index(&self, index: usize) -> PlayerResult;
+    // Put music at from in place of music at to, pushing that one down. Same as mpv playlist-move
+    fn move_index(&self, from: usize, to: usize) -> PlayerResult;
+
+    // Only one source of events is expected to exist at a time
+    fn events(&self) -> Box<dyn PlayerEvents + '_>;
+}
+
+// Player asked in config. mpv binary is the only choice when built without libmpv
+pub fn from_config() -> Result<Box<dyn Player>, String> {
+    #[cfg(feature = "libmpv")]
+    if config::initilize::CONFIG.mpv.backend == config::PlayerBackend::Libmpv {
+        return Ok(Box::new(MpvPlayer::new()?));
+    }
+    external_player()
+}
+
+#[cfg(unix)]
+fn external_player() -> Result<Box<dyn Player>, String> {
+    let player = crate::ui::mpv_ipc::IpcPlayer::new(&config::initilize::CONFIG.mpv.executable)?;
+    Ok(Box::new(player))
+}
+
+// mpv on windows listens on named pipe instead of unix socket
+#[cfg(not(unix))]
+fn external_player() -> Result<Box<dyn Player>, String> {
+    Err(String::from("Only libmpv can be used in this platform"))
+}
+
+// Properties that mpv will tell us about. Index is used as reply id
+#[cfg(feature = "libmpv")]
+const OBSERVED: [(&str, Format); 6] = [
+    ("pause", Format::Flag),
+    ("media-title", Format::String),
+    ("duration", Format::Double),
+    ("time-pos", Format::Double),
+    ("idle-active", Format::Flag),
+    // Only used to know that queue has changed. Queue itself is read by observer
+    ("playlist", Format::Node),
+];
+
+#[cfg(feature = "libmpv")]
+fn event_from_mpv(event: Event) -> Option<PlayerEvent> {
+    match event {
+        Event::PropertyChange { name, change, .. } => match (name, change) {
+            ("pause", PropertyData::Flag(paused)) => Some(PlayerEvent::Paused(paused)),
+            ("media-title", PropertyData::Str(title)) => {
+                Some(PlayerEvent::Title(title.to_string()))
+            }
+            ("duration", PropertyData::Double(secs)) => Some(PlayerEvent::Duration(secs)),
+            ("time-pos", PropertyData::Double(secs)) => Some(PlayerEvent::Elapsed(secs)),
+            ("idle-active", PropertyData::Flag(idle)) => Some(PlayerEvent::Idle(idle)),
+            ("playlist", _) => Some(PlayerEvent::QueueChanged),
+            _ => None,
+        },
+        Event::StartFile => Some(PlayerEvent::Started),
+        Event::FileLoaded => Some(PlayerEvent::Loaded),
+        Event::EndFile(_) => Some(PlayerEvent::Ended),
+        Event::Shutdown => Some(PlayerEvent::Shutdown),
+        _ => None,
+    }
+}
+
+#[cfg(feature = "libmpv")]
+struct MpvEvents<'a>(EventContext<'a>);
+
+#[cfg(feature = "libmpv")]
+impl PlayerEvents for MpvEvents<'_> {
+    fn wait_event(&mut self, timeout: f64) -> Option<PlayerEvent> {
+        // Errors are for single event only. eg: file that could not be played
+        self.0
+            .wait_event(timeout)
+            .and_then(Result::ok)
+            .and_then(event_from_mpv)
+    }
+}
+
+// Player backed by libmpv
+#[cfg(feature = "libmpv")]
+pub struct MpvPlayer {
+    mpv: libmpv::Mpv,
+}
+
+#[cfg(feature = "libmpv")]
+impl MpvPlayer {
+    pub fn new() -> Result<Self, String> {
+        let mpv = libmpv::Mpv::new().map_err(|err| err.to_string())?;
+        let config_dir = config::ConfigContainer::get_config_dir().unwrap();
+
+        mpv.set_property("config-dir", config_dir.to_str().unwrap())
+            .unwrap();
+        // User may ask to run without their mpv options. eg: to find if mpv.conf is causing trouble
+        if !config::initilize::OVERRIDES.lock().unwrap().no_mpv_config {
+            let mpv_config_path = config_dir.join(config::MPV_OPTION_FILE_NAME);
+            mpv.set_property("include", mpv_config_path.to_str().unwrap())
+                .unwrap();
+        }
+
+        // Video is always hidden. Override config file
+        mpv.set_property("video", "no").unwrap();
+        mpv.set_property("cache-secs", 10).ok();
+
+        Ok(MpvPlayer { mpv })
+    }
+
+    fn run(&self, name: &str, args: &[&str]) -> PlayerResult {
+        self.mpv.command(name, args).map_err(|err| err.to_string())
+    }
+}
+
+#[cfg(feature = "libmpv")]
+impl Player for MpvPlayer {
+    fn load(&self, url: &str) -> PlayerResult {
+        self.run("loadfile", &[url])
+    }
+
+    fn append(&self, url: &str, play: bool) -> PlayerResult {
+        let mode = if play { "append-play" } else { "append" };
+        self.run("loadfile", &[url, mode])
+    }
+
+    fn pause(&self) -> PlayerResult {
+        self.mpv.pause().map_err(|err| err.to_string())
+    }
+
+    fn unpause(&self) -> PlayerResult {
+        self.mpv.unpause().map_err(|err| err.to_string())
+    }
+
+    fn seek_forward(&self, secs: f64) -> PlayerResult {
+        self.mpv.seek_forward(secs).map_err(|err| err.to_string())
+    }
+
+    fn seek_backward(&self, secs: f64) -> PlayerResult {
+        self.mpv.seek_backward(secs).map_err(|err| err.to_string())
+    }
+
+    fn seek_absolute(&self, secs: f64) -> PlayerResult {
+        self.mpv.seek_absolute(secs).map_err(|err| err.to_string())
+    }
+
+    fn elapsed(&self) -> Option<f64> {
+        self.mpv.get_property("time-pos").ok()
+    }
+
+    fn title(&self) -> Option<String> {
+        self.mpv.get_property("media-title").ok()
+    }
+
+    fn play_next(&self) {
+        self.mpv.playlist_next_weak().ok();
+    }
+
+    fn play_prev(&self) {
+        self.mpv.playlist_previous_weak().ok();
+    }
+
+    fn get_volume(&self) -> Option<f64> {
+        self.mpv.get_property("volume").ok()
+    }
+
+    fn set_volume(&self, level: u8) -> Option<u8> {
+        let level = level.min(100);
+        self.mpv
+            .set_property("volume", level as f64)
+            .ok()
+            .map(|_| level)
+    }
+
+    fn repeat_playlist(&self) {
+        self.mpv.set_property("loop-playlist", "inf").ok();
+    }
+
+    fn repeat_one(&self) {
+        self.mpv.set_property("loop-file", "inf").ok();
+    }
+
+    fn repeat_nothing(&self) {
+        self.mpv.set_property("loop-playlist", "no").ok();
+        self.mpv.set_property("loop-file", "no").ok();
+    }
+
+    fn shuffle(&self) {
+        self.run("playlist-shuffle", &[]).ok();
+    }
+
+    fn unshuffle(&self) {
+        self.run("playlist-unshuffle", &[]).ok();
+    }
+
+    fn queue(&self) -> Vec<QueueEntry> {
+        (0..self.queue_length())
+            .map(|index| QueueEntry {
+                url: self
+                    .mpv
+                    .get_property(&format!("playlist/{}/filename", index))
+                    .unwrap_or_default(),
+                title: self
+                    .mpv
+                    .get_property(&format!("playlist/{}/title", index))
+                    .ok(),
+            })
+            .collect()
+    }
+
+    fn queue_length(&self) -> usize {
+        self.mpv
+            .get_property::<i64>("playlist-count")
+            .unwrap_or_default()
+            .max(0) as usize
+    }
+
+    fn queue_position(&self) -> Option<usize> {
+        let position = self.mpv.get_property::<i64>("playlist-pos").ok()?;
+        usize::try_from(position).ok()
+    }
+
+    fn play_index(&self, index: usize) -> PlayerResult {
+        self.mpv
+            .set_property("playlist-pos", index as i64)
+            .map_err(|err| err.to_string())
+    }
+
+    fn remove_index(&self, index: usize) -> PlayerResult {
+        self.run("playlist-remove", &[index.to_string().as_str()])
+    }
+
+    fn move_index(&self, from: usize, to: usize) -> PlayerResult {
+        self.run(
+            "playlist-move",
+            &[from.to_string().as_str(), to.to_string().as_str()],
+        )
+    }
+
+    // Panics if called more than once
+    fn events(&self) -> Box<dyn PlayerEvents + '_> {
+        let events = self.mpv.create_event_context();
+        for (index, (name, format)) in OBSERVED.iter().enumerate() {
+            events.observe_property(name, *format, index as u64).ok();
+        }
+        Box::new(MpvEvents(events))
+    }
+}
+
+// Player that only remembers what it was asked to do. Nothing is played
+#[cfg(test)]
+#[derive(Debug, Default)]
+pub struct FakeState {
+    pub queue: Vec<String>,
+    pub position: Option<usize>,
+    pub paused: bool,
+    pub elapsed: f64,
+    pub volume: u8,
+    pub loop_file: bool,
+    pub loop_playlist: bool,
+    pub shuffled: bool,
+    // Times whole queue was asked for
+    pub queue_reads: usize,
+}
+
+#[cfg(test)]
+pub struct FakePlayer {
+    pub state: std::sync::Mutex<FakeState>,
+    sender: std::sync::Mutex<std::sync::mpsc::Sender<PlayerEvent>>,
+    receiver: std::sync::Mutex<Option<std::sync::mpsc::Receiver<PlayerEvent>>>,
+}
+
+#[cfg(test)]
+impl Default for FakePlayer {
+    fn default() -> Self {
+        let (sender, receiver) = std::sync::mpsc::channel();
+        FakePlayer {
+            state: std::sync::Mutex::new(FakeState {
+                volume: 100,
+                ..Default::default()
+            }),
+            sender: std::sync::Mutex::new(sender),
+            receiver: std::sync::Mutex::new(Some(receiver)),
+        }
+    }
+}
+
+#[cfg(test)]
+impl FakePlayer {
+    fn emit(&self, event: PlayerEvent) {
+        self.sender.lock().unwrap().send(event).ok();
+    }
+
+    fn start(&self, state: &mut FakeState, index: usize) {
+        state.position = Some(index);
+        state.elapsed = 0.0;
+        self.emit(PlayerEvent::Started);
+        self.emit(PlayerEvent::Loaded);
+    }
+}
+
+#[cfg(test)]
+impl Player for FakePlayer {
+    fn load(&self, url: &str) -> PlayerResult {
+        let mut state = self.state.lock().unwrap();
+        state.queue = vec![url.to_string()];
+        self.start(&mut state, 0);
+        self.emit(PlayerEvent::QueueChanged);
+        Ok(())
+    }
+
+    fn append(&self, url: &str, play: bool) -> PlayerResult {
+        let mut state = self.state.lock().unwrap();
+        state.queue.push(url.to_string());
+        if play && state.position.is_none() {
+            let index = state.queue.len() - 1;
+            self.start(&mut state, index);
+        }
+        self.emit(PlayerEvent::QueueChanged);
+        Ok(())
+    }
+
+    fn pause(&self) -> PlayerResult {
+        self.state.lock().unwrap().paused = true;
+        self.emit(PlayerEvent::Paused(true));
+        Ok(())
+    }
+
+    fn unpause(&self) -> PlayerResult {
+        self.state.lock().unwrap().paused = false;
+        self.emit(PlayerEvent::Paused(false));
+        Ok(())
+    }
+
+    fn seek_forward(&self, secs: f64) -> PlayerResult {
+        self.state.lock().unwrap().elapsed += secs;
+        Ok(())
+    }
+
+    fn seek_backward(&self, secs: f64) -> PlayerResult {
+        let mut state = self.state.lock().unwrap();
+        state.elapsed = (state.elapsed - secs).max(0.0);
+        Ok(())
+    }
+
+    fn seek_absolute(&self, secs: f64) -> PlayerResult {
+        self.state.lock().unwrap().elapsed = secs;
+        Ok(())
+    }
+
+    fn elapsed(&self) -> Option<f64> {
+        let state = self.state.lock().unwrap();
+        state.position.map(|_| state.elapsed)
+    }
+
+    fn title(&self) -> Option<String> {
+        let state = self.state.lock().unwrap();
+        state.position.map(|position| state.queue[position].clone())
+    }
+
+    fn play_next(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(position) = state.position {
+            if position + 1 < state.queue.len() {
+                self.start(&mut state, position + 1);
+            } else if state.loop_playlist {
+                self.start(&mut state, 0);
+            }
+        }
+    }
+
+    fn play_prev(&self) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(position) = state.position.filter(|position| *position > 0) {
+            self.start(&mut state, position - 1);
+        }
+    }
+
+    fn get_volume(&self) -> Option<f64> {
+        Some(self.state.lock().unwrap().volume as f64)
+    }
+
+    fn set_volume(&self, level: u8) -> Option<u8> {
+        let level = level.min(100);
+        self.state.lock().unwrap().volume = level;
+        Some(level)
+    }
+
+    fn repeat_playlist(&self) {
+        self.state.lock().unwrap().loop_playlist = true;
+    }
+
+    fn repeat_one(&self) {
+        self.state.lock().unwrap().loop_file = true;
+    }
+
+    fn repeat_nothing(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.loop_file = false;
+        state.loop_playlist = false;
+    }
+
+    // Order is kept as it is so that tests know where everything is
+    fn shuffle(&self) {
+        self.state.lock().unwrap().shuffled = true;
+    }
+
+    fn unshuffle(&self) {
+        self.state.lock().unwrap().shuffled = false;
+    }
+
+    fn queue(&self) -> Vec<QueueEntry> {
+        let mut state = self.state.lock().unwrap();
+        state.queue_reads += 1;
+        state
+            .queue
+            .iter()
+            .map(|url| QueueEntry {
+                url: url.clone(),
+                title: None,
+            })
+            .collect()
+    }
+
+    fn queue_position(&self) -> Option<usize> {
+        self.state.lock().unwrap().position
+    }
+
+    fn play_index(&self, index: usize) -> PlayerResult {
+        let mut state = self.state.lock().unwrap();
+        if index >= state.queue.len() {
+            return Err(String::from("No such music in queue"));
+        }
+        self.start(&mut state, index);
+        self.emit(PlayerEvent::QueueChanged);
+        Ok(())
+    }
+
+    fn remove_index(&self, index: usize) -> PlayerResult {
+        let mut state = self.state.lock().unwrap();
+        if index >= state.queue.len() {
+            return Err(String::from("No such music in queue"));
+        }
+        state.queue.remove(index);
+        state.position = match state.position {
+            Some(position) if position == index => None,
+            Some(position) if position > index => Some(position - 1),
+            position => position,
+        };
+        self.emit(PlayerEvent::QueueChanged);
+        Ok(())
+    }
+
+    fn move_index(&self, from: usize, to: usize) -> PlayerResult {
+        let mut state = self.state.lock().unwrap();
+        if from >= state.queue.len() || to > state.queue.len() {
+            return Err(String::from("No such music in queue"));
+        }
+        if from == to || from + 1 == to {
+            return Ok(());
+        }
+        let url = state.queue.remove(from);
+        let to = if to > from { to - 1 } else { to };
+        state.queue.insert(to, url);
+        // Music being played is followed to it's new place
+        state.position = state.position.map(|position| {
+            if position == from {
+                to
+            } else if from < position && position <= to {
+                position - 1
+            } else if to <= position && position < from {
+                position + 1
+            } else {
+                position
+            }
+        });
+        self.emit(PlayerEvent::QueueChanged);
+        Ok(())
+    }
+
+    fn events(&self) -> Box<dyn PlayerEvents + '_> {
+        let receiver = self.receiver.lock().unwrap().take();
+        Box::new(ChannelEvents(receiver.expect("Events are already taken")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(feature = "libmpv")]
+    fn mpv_events() {
+        let change = |name, change| {
+            event_from_mpv(Event::PropertyChange {
+                name,
+                change,
+                reply_userdata: 0,
+            })
+        };
+
+        assert_eq!(
+            change("pause", PropertyData::Flag(true)),
+            Some(PlayerEvent::Paused(true))
+        );
+        assert_eq!(
+            change("media-title", PropertyData::Str("Song")),
+            Some(PlayerEvent::Title("Song".to_string()))
+        );
+        assert_eq!(
+            change("time-pos", PropertyData::Double(12.5)),
+            Some(PlayerEvent::Elapsed(12.5))
+        );
+        assert_eq!(
+            change("playlist", PropertyData::Int64(0)),
+            Some(PlayerEvent::QueueChanged)
+        );
+        // Unexpected format is not mistaken for value
+        assert_eq!(change("duration", PropertyData::Str("10")), None);
+        assert_eq!(change("volume", PropertyData::Double(50.0)), None);
+
+        assert_eq!(event_from_mpv(Event::FileLoaded), Some(PlayerEvent::Loaded));
+        assert_eq!(event_from_mpv(Event::Seek), None);
+    }
+
+    #[test]
+    fn fake_queue() {
+        let player = FakePlayer::default();
+        let mut events = player.events();
+        for url in ["a", "b", "c", "d"] {
+            player.append(url, true).unwrap();
+        }
+        assert_eq!(player.queue_position(), Some(0));
+        assert_eq!(events.wait_event(0.0), Some(PlayerEvent::Started));
+        assert_eq!(events.wait_event(0.0), Some(PlayerEvent::Loaded));
+        assert_eq!(events.wait_event(0.0), Some(PlayerEvent::QueueChanged));
+
+        // Same as mpv. Moving down needs the index after the target
+        player.move_index(0, 2).unwrap();
+        assert_eq!(player.state.lock().unwrap().queue, ["b", "a", "c", "d"]);
+        assert_eq!(player.queue_position(), Some(1));
+        player.move_index(3, 0).unwrap();
+        assert_eq!(player.state.lock().unwrap().queue, ["d", "b", "a", "c"]);
+        assert_eq!(player.queue_position(), Some(2));
+
+        player.remove_index(0).unwrap();
+        assert_eq!(player.queue_position(), Some(1));
+        player.play_next();
+        player.play_next();
+        assert_eq!(player.queue_position(), Some(2));
+        player.repeat_playlist();
+        player.play_next();
+        assert_eq!(player.queue_position(), Some(0));
+        assert!(player.play_index(5).is_err());
+    }
+}
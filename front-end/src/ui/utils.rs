@@ -4,19 +4,50 @@ use std::borrow::Cow;
 use tui;
 use ui::shared_import::*;
 
-pub const SIDEBAR_LIST_COUNT: usize = 6;
+pub const SIDEBAR_LIST_COUNT: usize = 8;
 pub const SIDEBAR_LIST_ITEMS: [&str; SIDEBAR_LIST_COUNT] = [
     "Trending",
     "Youtube Community",
     "Liked songs",
     "My playlist",
     "Following",
+    "Recently played",
+    "Local library",
     "Search",
 ];
 use config::initilize::{
-    CONFIG, STORAGE, TB_FAVOURATES_ARTIST, TB_FAVOURATES_MUSIC, TB_FAVOURATES_PLAYLIST,
+    CONFIG, STORAGE, TB_DOWNLOADED_MUSIC, TB_DOWNLOADED_PLAYLIST, TB_DOWNLOAD_HISTORY,
 };
 
+pub fn get_downloads_container<'a>(state: &'a ui::State) -> Table<'a> {
+    let items: Vec<Row> = state
+        .downloads
+        .0
+        .iter()
+        .map(|download| {
+            let status = match &download.status {
+                ui::DownloadStatus::Queued => "Queued".to_string(),
+                ui::DownloadStatus::Running(progress) => format!("{:.1}%", progress),
+                ui::DownloadStatus::Finished => "Done".to_string(),
+                ui::DownloadStatus::Failed(reason) => format!("Failed: {}", reason),
+                ui::DownloadStatus::Cancelled => "Cancelled".to_string(),
+            };
+            Row::new(vec![
+                Cell::from(download.title.as_str()),
+                Cell::from(status),
+            ])
+        })
+        .collect();
+
+    Table::new(items)
+        .header(Row::new(vec!["Title", "Status"]).style(Style::list_title()))
+        .widths([Constraint::Percentage(60), Constraint::Percentage(40)].as_ref())
+        .column_spacing(2)
+        .style(Style::list_idle())
+        .highlight_style(Style::list_highlight())
+        .block(Block::active("Downloads ".to_owned()))
+}
+
 pub fn show_pupop_text<'a, B>(frame: &mut tui::terminal::Frame<B>, text: [&'a str; 2], area: &Rect)
 where
     B: Backend,
@@ -70,14 +101,20 @@ impl<'parent> ui::TopLayout {
     pub fn get_searchbox(state: &'parent ui::State) -> Paragraph<'parent> {
         let mut cursor_style = Style::default().fg(rgb!(CONFIG.theme.color_secondary));
 
+        let title = match state.prompt {
+            Some(ui::Prompt::AddTo(_)) => "Add to playlist ",
+            Some(ui::Prompt::Create) => "New playlist ",
+            Some(ui::Prompt::Rename(_)) => "Rename playlist ",
+            None => "Search ",
+        };
         let block = match state.active {
             ui::Window::Searchbar => {
                 cursor_style = cursor_style.add_modifier(Modifier::BOLD | Modifier::RAPID_BLINK);
-                Block::active("Search ".to_owned())
+                Block::active(title.to_owned())
             }
             _ => {
                 cursor_style = cursor_style.add_modifier(Modifier::HIDDEN);
-                Block::new("Search ".to_owned())
+                Block::new(title.to_owned())
             }
         };
         let text = text::Spans::from(vec![
@@ -113,13 +150,46 @@ impl<'parent> ui::MiddleLayout {
             .direction(Direction::Vertical)
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
             .split(parent);
+        let top_layout = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(70), Constraint::Percentage(30)])
+            .split(layout[0]);
 
         ui::MiddleLayout {
-            layout: layout[0],
+            layout: [top_layout[0], top_layout[1]],
             bottom: ui::MiddleBottom::new(layout[1]),
         }
     }
 
+    pub fn get_queue_container(state: &'parent mut ui::State) -> Table<'parent> {
+        let block = match state.active {
+            ui::Window::Queue => Block::active("Queue ".to_owned()),
+            _ => {
+                state.queue.1.select(None);
+                Block::new("Queue ".to_owned())
+            }
+        };
+
+        let items: Vec<Row> = state
+            .queue
+            .0
+            .iter()
+            .map(|item| {
+                // Mark the music that is being played
+                let marker = if item.current { ">" } else { "" };
+                Row::new(vec![marker, item.title.as_str()])
+            })
+            .collect();
+        let table = Table::new(items)
+            .widths([Constraint::Length(1), Constraint::Percentage(100)].as_ref())
+            .column_spacing(1)
+            .style(Style::list_idle())
+            .highlight_style(Style::list_highlight())
+            .block(block);
+
+        table
+    }
+
     pub fn get_music_container(state: &'parent mut ui::State) -> Table<'parent> {
         let block = match state.active {
             ui::Window::Musicbar => Block::active("Music ".to_owned()),
@@ -130,10 +200,18 @@ impl<'parent> ui::MiddleLayout {
         };
 
         let data_list = &state.musicbar.0;
+        let downloaded = &state.downloaded;
         let items: Vec<Row> = data_list
             .iter()
             .map(|music| {
+                // Mark the music that is already downloaded
+                let marker = if downloaded.contains_key(&music.id) {
+                    "*"
+                } else {
+                    ""
+                };
                 Row::new(vec![
+                    marker,
                     music.name.as_str(),
                     music.artist.as_str(),
                     music.duration.as_str(),
@@ -141,9 +219,10 @@ impl<'parent> ui::MiddleLayout {
             })
             .collect();
         let table = Table::new(items)
-            .header(Row::new(vec!["Title", "Artist", "Length"]).style(Style::list_title()))
+            .header(Row::new(vec!["", "Title", "Artist", "Length"]).style(Style::list_title()))
             .widths(
                 [
+                    Constraint::Length(1),
                     Constraint::Percentage(55),
                     Constraint::Percentage(30),
                     Constraint::Percentage(15),
@@ -476,31 +555,44 @@ impl ui::Position {
             height,
             width,
         };
+        // Downloads list needs more room than simple text popup
+        let downloads_pos = Rect {
+            x: screen_rect.width / 10,
+            y: screen_rect.height / 5,
+            height: screen_rect.height * 3 / 5,
+            width: screen_rect.width * 4 / 5,
+        };
 
         ui::Position {
             search: top_section.layout[0],
             status: top_section.layout[1],
             shortcut: sidebar.layout[0],
-            music: middle_section.layout,
+            music: middle_section.layout[0],
+            queue: middle_section.layout[1],
             playlist: middle_bottom.layout[0],
             artist: middle_bottom.layout[1],
             music_info: bottom_section.layout,
             bottom_icons: sidebar.layout[1],
             popup: popup_pos,
+            downloads: downloads_pos,
         }
     }
 }
 
 impl Default for ui::State<'_> {
     fn default() -> Self {
-        let mpv = libmpv::Mpv::new().unwrap();
-        mpv.configure_defult();
-        mpv.cache_for(10);
+        let player = ui::player::from_config().expect("Cannot initilize mpv");
+        Self::with_player(Arc::from(player))
+    }
+}
+
+impl ui::State<'_> {
+    pub fn with_player(player: Arc<dyn ui::player::Player>) -> Self {
         // By default repeat the playlist. Set playlist to repeat
-        mpv.repeat_playlist();
+        player.repeat_playlist();
 
         // At first have maximum volume
-        mpv.change_volume(100);
+        player.set_volume(100);
 
         let mut sidebar_list_state = ListState::default();
         sidebar_list_state.select(Some(0));
@@ -510,20 +602,32 @@ impl Default for ui::State<'_> {
             musicbar: (Vec::new(), TableState::default()),
             playlistbar: (Vec::new(), TableState::default()),
             artistbar: (Vec::new(), TableState::default()),
+            queue: (Vec::new(), TableState::default()),
+            queue_titles: std::collections::HashMap::new(),
+            to_queue: None,
+            to_download: Vec::new(),
+            downloaded: std::collections::HashMap::new(),
+            resume_at: None,
+            downloads: (Vec::new(), TableState::default()),
             search: (String::new(), String::new()),
+            prompt: None,
             active: ui::Window::Sidebar,
-            fetched_page: [None; 3],
+            // Start with first page of recently played source so that
+            // what was played last is shown on startup
+            fetched_page: [Some(0); 3],
             filled_source: (
                 ui::MusicbarSource::RecentlyPlayed,
                 ui::PlaylistbarSource::RecentlyPlayed,
                 ui::ArtistbarSource::RecentlyPlayed,
             ),
+            rescan_library: false,
             bottom: ui::BottomState {
                 playing: None,
+                stopped: false,
                 music_duration: Duration::new(0, 0),
                 music_elapse: Duration::new(0, 0),
             },
-            player: mpv,
+            player,
             playback_behaviour: ui::PlaybackBehaviour {
                 shuffle: false,
                 repeat: true,
@@ -533,110 +637,16 @@ impl Default for ui::State<'_> {
     }
 }
 
-pub trait ExtendMpv {
-    fn configure_defult(&self);
-    fn repeat_playlist(&self);
-    fn repeat_one(&self);
-    fn repeat_nothing(&self);
-    fn shuffle(&self);
-    fn unshuffle(&self);
-    fn cache_for(&self, time: i64);
-    fn play_next(&self);
-    fn play_prev(&self);
-    fn change_volume(&self, step: i8) -> Option<u8>;
-    fn get_volume(&self) -> Option<f64>;
-}
-
-impl ExtendMpv for libmpv::Mpv {
-    fn configure_defult(&self) {
-        let config_dir = config::ConfigContainer::get_config_dir().unwrap();
-
-        self.set_property("config-dir", config_dir.to_str().unwrap())
-            .unwrap();
-        let mpv_config_path = config_dir.join(config::MPV_OPTION_FILE_NAME);
-        self.set_property("include", mpv_config_path.to_str().unwrap())
-            .unwrap();
-
-        // Video is always hidden. Override config file
-        self.set_property("video", "no").unwrap();
-    }
-
-    #[inline(always)]
-    fn shuffle(&self) {
-        self.command("playlist-shuffle", &[]).ok();
-    }
-
-    #[inline(always)]
-    fn unshuffle(&self) {
-        self.command("playlist-unshuffle", &[]).ok();
-    }
-
-    #[inline(always)]
-    fn cache_for(&self, time: i64) {
-        self.set_property("cache-secs", time).ok();
-    }
-
-    #[inline(always)]
-    fn get_volume(&self) -> Option<f64> {
-        self.get_property("volume").ok()
-    }
-
-    #[inline(always)]
-    fn change_volume(&self, step: i8) -> Option<u8> {
-        let current_vol = self.get_volume();
-        match current_vol {
-            Some(current) => {
-                let mut new_vol: f64 = current + step as f64;
-                if new_vol < 0.00 {
-                    new_vol = 0.0;
-                } else if new_vol > 100.00 {
-                    new_vol = 100.0;
-                }
-
-                let sucess = self.set_property("volume", new_vol).is_ok();
-                if sucess {
-                    Some(new_vol as u8)
-                } else {
-                    None
-                }
-            }
-            None => None,
-        }
-    }
-
-    #[inline(always)]
-    fn repeat_playlist(&self) {
-        self.set_property("loop-playlist", "inf").ok();
-    }
-
-    fn repeat_nothing(&self) {
-        self.set_property("loop-playlist", "no").ok();
-        self.set_property("loop-file", "no").ok();
-    }
-
-    #[inline(always)]
-    fn repeat_one(&self) {
-        self.set_property("loop-file", "inf").ok();
-    }
-
-    #[inline(always)]
-    fn play_next(&self) {
-        self.playlist_next_weak().ok();
-    }
-
-    #[inline(always)]
-    fn play_prev(&self) {
-        self.playlist_previous_weak().ok();
-    }
-}
-
 impl ui::State<'_> {
     pub fn play_music(&mut self, music_id: &str) {
+        // Remember this music as recently played. Played music is always expected to be from
+        // musicbar
+        if let Some(music) = self.musicbar.0.iter().find(|music| music.id == *music_id) {
+            self.add_music_to_history(music);
+        }
+
         self.player.unpause().ok();
-        match self.player.command(
-            "loadfile",
-            [format!("https://www.youtube.com/watch?v={}", music_id).as_str()].as_ref(),
-        ) {
+        match self.player.load(&fetcher::library::music_url(music_id)) {
             Ok(_) => {
                 // clear any previous thing from bottombar
                 self.bottom.music_duration = Duration::from_secs(0);
@@ -644,38 +654,47 @@ impl ui::State<'_> {
 
                 self.status = "Playing...";
                 // set currently playing (unpaused) to ture. no need to set real title as it will
-                // be done by refresh_mpv_status() later on
+                // be done by observer later on. See playback.rs
                 self.bottom.playing = Some((String::new(), true))
             }
             Err(_) => self.status = "Playback error..",
         }
         // Now as the selection is being played. Add remaining item from musicbar to the play
         // queue.
-        for music in self.musicbar.0.iter() {
+        let musicbar = std::mem::take(&mut self.musicbar.0);
+        for music in musicbar.iter() {
+            self.remember_title(music);
             // If this is the currently payed song donot add it to prevent having
             // currently played song two time in queue
             if music.id == *music_id {
                 continue;
             }
             self.player
-                .command(
-                    "loadfile",
-                    [
-                        format!("https://www.youtube.com/watch?v={}", music.id).as_str(),
-                        "append",
-                    ]
-                    .as_ref(),
-                )
+                .append(&fetcher::library::music_url(&music.id), false)
                 .ok();
         }
+        self.musicbar.0 = musicbar;
     }
 
     // This function is called when user press enter in non-empty list of playlistbar
     pub fn activate_playlist(&mut self, playlist_id: &str) {
-        match self.player.command(
-            "loadfile",
-            [format!("https://www.youtube.com/playlist?list={}", playlist_id).as_str()].as_ref(),
-        ) {
+        if let Some(playlist) = self
+            .playlistbar
+            .0
+            .iter()
+            .find(|playlist| playlist.id == *playlist_id)
+        {
+            self.add_playlist_to_history(playlist);
+        }
+
+        if let Some(user_playlist_id) = fetcher::ids::user_playlist_id(playlist_id) {
+            return self.play_user_playlist(user_playlist_id);
+        }
+
+        match self.player.load(&format!(
+            "https://www.youtube.com/playlist?list={}",
+            playlist_id
+        )) {
             Ok(_) => {
                 // send unpause signal
                 self.player.unpause().ok();
@@ -686,54 +705,13 @@ impl ui::State<'_> {
 
                 self.status = "Playing..";
                 // set currently playing (unpaused) to ture. no need to set real title as it will
-                // be done by refresh_mpv_status() later on
+                // be done by observer later on. See playback.rs
                 self.bottom.playing = Some((String::new(), true));
             }
             Err(_) => self.status = "Playback error..",
         }
     }
 
-    // This function can also be used to check playing status
-    // Returning true means some music is playing which may be paused or unpaused
-    pub fn refresh_mpv_status(&mut self) {
-        // It may be better to use wait event method from mpv
-        // but for that we need tp spawn seperate thread/task
-        // and also we are updating the ui anway so it may also be affordable to just query mpv in
-        // ui updating loop
-        if let Some((_, true)) = self.bottom.playing {
-            match self.player.get_property::<i64>("audio-pts") {
-                Ok(time) => {
-                    self.bottom.music_elapse = Duration::from_secs(time as u64);
-                }
-                Err(_e) => {
-                    // This error is generally expected to be -10 (property exist but not available
-                    // at the moment)
-                    // which means that the mpv has not yet loaded the file
-                    // this depends on the condition of network and amount of task needed to done
-                    // by mpv (ususally depends on the length of stream)
-                    // eprintln!("Err: {}", e);
-                }
-            }
-
-            // These will update the status from mpv in real time. This will always show to correct
-            // title of the music that is being playing even from playlist so we there is no need
-            // to listen to mpv event for playlist index change just to change the title and
-            // duration of currently playing music.
-            let title = self
-                .player
-                .get_property::<String>("media-title")
-                .unwrap_or(">> Play some music <<".to_string());
-            let estimated_duration_reply = self
-                .player
-                .get_property::<i64>("duration")
-                .unwrap_or_default();
-
-            self.bottom.playing = Some((title, true)); // at this scope of match playing status is always true
-            self.bottom.music_duration =
-                Duration::from_secs(estimated_duration_reply.try_into().unwrap_or_default());
-        }
-    }
-
     pub fn toggle_pause(&mut self) {
         if let Some((_, ref mut is_playing)) = self.bottom.playing {
             if *is_playing {
@@ -742,26 +720,77 @@ impl ui::State<'_> {
             } else {
                 self.status = "Playing..";
                 self.player.unpause().unwrap();
+                self.bottom.stopped = false;
             }
             *is_playing = !*is_playing;
         }
     }
+
+    // Queue is kept as it is so that it can be played again
+    pub fn stop(&mut self) {
+        if self.bottom.playing.is_none() {
+            return;
+        }
+        self.set_paused(true);
+        self.player.seek_absolute(0.0).ok();
+        self.bottom.music_elapse = Duration::from_secs(0);
+        self.bottom.stopped = true;
+        self.status = "Stopped..";
+    }
+
+    pub fn set_shuffle(&mut self, shuffle: bool) {
+        if shuffle {
+            self.player.shuffle();
+        } else {
+            self.player.unshuffle();
+        }
+        self.playback_behaviour.shuffle = shuffle;
+    }
+
+    // Repeat whole queue if repeat_queue is true. Otherwise repeat the music being played
+    pub fn set_repeat(&mut self, repeat_queue: bool) {
+        self.player.repeat_nothing();
+        if repeat_queue {
+            self.player.repeat_playlist();
+        } else {
+            self.player.repeat_one();
+        }
+        self.playback_behaviour.repeat = repeat_queue;
+    }
+
+    // Pause or resume. Nothing is done if it is already so or nothing is being played
+    pub fn set_paused(&mut self, pause: bool) {
+        if let Some((_, is_playing)) = self.bottom.playing {
+            if is_playing == pause {
+                self.toggle_pause();
+            }
+        }
+    }
+
+    pub fn player_status(&self) -> ui::PlayerStatus {
+        let (title, paused) = match &self.bottom.playing {
+            Some((title, is_playing)) => (Some(title.clone()), !is_playing),
+            None => (None, false),
+        };
+
+        ui::PlayerStatus {
+            title,
+            paused,
+            stopped: self.bottom.stopped,
+            elapsed: self.bottom.music_elapse.as_secs(),
+            duration: self.bottom.music_duration.as_secs(),
+            volume: self.playback_behaviour.volume,
+            shuffle: self.playback_behaviour.shuffle,
+            repeat_queue: self.playback_behaviour.repeat,
+            queue_length: self.queue.0.len(),
+            message: self.status.to_string(),
+        }
+    }
 }
 
 impl ui::State<'_> {
     pub fn remove_music_from_favourates(&mut self, music: &fetcher::MusicUnit) {
-        let query = format!(
-            "
-            DELETE FROM
-            {tb_name} as fav_music
-            WHERE
-            fav_music.id = :id
-        ",
-            tb_name = TB_FAVOURATES_MUSIC
-        );
-        let args = [(":id", &music.id)];
-
-        let res = STORAGE.lock().unwrap().execute(&query, &args);
+        let res = fetcher::favourates::remove_music(&music.id);
         if res.is_ok() {
             self.status = "Removed..";
         } else {
@@ -770,17 +799,7 @@ impl ui::State<'_> {
     }
 
     pub fn remove_playlist_from_favourates(&mut self, playlist: &fetcher::PlaylistUnit) {
-        let query = format!(
-            "
-            DELETE FROM
-            {tb_name} as fav_playlist
-            WHERE fav_playlist.id = :id
-        ",
-            tb_name = TB_FAVOURATES_PLAYLIST
-        );
-        let args = [(":id", &playlist.id)];
-
-        let res = STORAGE.lock().unwrap().execute(&query, &args);
+        let res = fetcher::favourates::remove_playlist(&playlist.id);
         if res.is_ok() {
             self.status = "Removed..";
         } else {
@@ -789,18 +808,7 @@ impl ui::State<'_> {
     }
 
     pub fn remove_artist_from_favourates(&mut self, artist: &fetcher::ArtistUnit) {
-        let query = format!(
-            "
-            DELETE FROM
-            {tb_name} as fav_artist
-            WHERE fav_artist.id = :id
-        ",
-            tb_name = TB_FAVOURATES_ARTIST
-        );
-
-        let args = [(":id", &artist.id)];
-
-        let res = STORAGE.lock().unwrap().execute(&query, &args);
+        let res = fetcher::favourates::remove_artist(&artist.id);
         if res.is_ok() {
             self.status = "Removed..."
         } else {
@@ -809,80 +817,793 @@ impl ui::State<'_> {
     }
 
     pub fn add_artist_to_favourates(&mut self, artist: &fetcher::ArtistUnit) {
+        let res = fetcher::favourates::add_artist(artist);
+        if res.is_ok() {
+            self.status = "Added..";
+        } else {
+            self.status = "Err adding..";
+        }
+    }
+
+    pub fn add_music_to_favourates(&mut self, music: &fetcher::MusicUnit) {
+        let res = fetcher::favourates::add_music(music);
+        if res.is_ok() {
+            self.status = "Added...";
+        } else {
+            self.status = "Err adding..";
+        }
+    }
+
+    pub fn add_playlist_to_favourates(&mut self, playlist: &fetcher::PlaylistUnit) {
+        let res = fetcher::favourates::add_playlist(playlist);
+        if res.is_ok() {
+            self.status = "Added...";
+        } else {
+            self.status = "Err adding..";
+        }
+    }
+}
+
+// Unlike favourates, history is recorded as a side effect of playing something. So failure to
+// record history is not reported in status as it would override the status of playback itself
+impl ui::State<'_> {
+    pub fn add_music_to_history(&self, music: &fetcher::MusicUnit) {
+        fetcher::history::add_music(music).ok();
+    }
+
+    pub fn add_playlist_to_history(&self, playlist: &fetcher::PlaylistUnit) {
+        fetcher::history::add_playlist(playlist).ok();
+    }
+
+    // Artist do not get played directly. So an artist is recorded in history when it's content
+    // is explored from artistbar
+    pub fn add_artist_to_history(&self, artist: &fetcher::ArtistUnit) {
+        fetcher::history::add_artist(artist).ok();
+    }
+}
+
+// Playlists created by user itself. See user playlist table in config for how they are stored
+impl ui::State<'_> {
+    pub fn create_user_playlist(&mut self, name: &str) {
+        let res = fetcher::user_playlist::get_or_create(name);
+        match res {
+            Ok(id) => {
+                // Show the new playlist right away if `My playlist` is being shown.
+                // User playlists are listed before any other playlists
+                let playlist_id = format!("{}{}", fetcher::ids::USER_PLAYLIST_PREFIX, id);
+                let is_shown = self
+                    .playlistbar
+                    .0
+                    .iter()
+                    .any(|playlist| playlist.id == playlist_id);
+                if self.filled_source.1 == ui::PlaylistbarSource::Favourates && !is_shown {
+                    let position = self
+                        .playlistbar
+                        .0
+                        .iter()
+                        .take_while(|playlist| {
+                            fetcher::ids::user_playlist_id(&playlist.id).is_some()
+                        })
+                        .count();
+                    self.playlistbar.0.insert(
+                        position,
+                        fetcher::PlaylistUnit {
+                            name: name.to_string(),
+                            id: playlist_id,
+                            author: "You".to_string(),
+                            video_count: "0".to_string(),
+                        },
+                    );
+                }
+                self.status = "Created..";
+            }
+            Err(_) => self.status = "Err creating..",
+        }
+    }
+
+    // Music is added at the end of playlist. Adding music that is already in playlist do nothing
+    pub fn add_music_to_user_playlist(&mut self, name: &str, music: &fetcher::MusicUnit) {
+        let id = match fetcher::user_playlist::get_or_create(name) {
+            Ok(id) => id,
+            Err(_) => {
+                self.status = "Err adding..";
+                return;
+            }
+        };
+        let res = fetcher::user_playlist::add_music(id, music);
+
+        match res {
+            Ok(0) => self.status = "Already added..",
+            Ok(_) => {
+                let playlist_id = format!("{}{}", fetcher::ids::USER_PLAYLIST_PREFIX, id);
+                if let Some(playlist) = self
+                    .playlistbar
+                    .0
+                    .iter_mut()
+                    .find(|playlist| playlist.id == playlist_id)
+                {
+                    let count = playlist.video_count.parse::<usize>().unwrap_or_default();
+                    playlist.video_count = (count + 1).to_string();
+                }
+                self.status = "Added..";
+            }
+            Err(_) => self.status = "Err adding..",
+        }
+    }
+
+    pub fn rename_user_playlist(&mut self, playlist_id: &str, name: &str) {
+        let id = match fetcher::ids::user_playlist_id(playlist_id) {
+            Some(id) => id,
+            None => return,
+        };
+        let res = fetcher::user_playlist::rename(id, name);
+        if res.is_ok() {
+            for playlist in self.playlistbar.0.iter_mut() {
+                if playlist.id == playlist_id {
+                    playlist.name = name.to_string();
+                }
+            }
+            self.status = "Renamed..";
+        } else {
+            // Most probably another playlist with same name already exists
+            self.status = "Err renaming..";
+        }
+    }
+
+    pub fn delete_user_playlist(&mut self, playlist_id: &str) {
+        let id = match fetcher::ids::user_playlist_id(playlist_id) {
+            Some(id) => id,
+            None => return,
+        };
+        let res = fetcher::user_playlist::delete(id);
+        if res.is_ok() {
+            self.playlistbar
+                .0
+                .retain(|playlist| playlist.id != playlist_id);
+            self.playlistbar.1.select(None);
+            self.status = "Deleted..";
+        } else {
+            self.status = "Err deleting..";
+        }
+    }
+
+    pub fn remove_music_from_user_playlist(&mut self, playlist_id: &str, music_id: &str) {
+        let id = match fetcher::ids::user_playlist_id(playlist_id) {
+            Some(id) => id,
+            None => return,
+        };
+        let res = fetcher::user_playlist::remove_music(id, music_id);
+        if res.is_ok() {
+            self.musicbar.0.retain(|music| music.id != music_id);
+            self.musicbar.1.select(None);
+            self.status = "Removed..";
+        } else {
+            self.status = "Err removing..";
+        }
+    }
+
+    // Swap the focused music in musicbar with the music just above/below it in user playlist.
+    // Music which it is swapped with may be in another page. In that case focused music is moved
+    // to that page and the other music takes it's place in current page
+    pub fn move_music_in_user_playlist(&mut self, up: bool) {
+        let id = match &self.filled_source.0 {
+            ui::MusicbarSource::Playlist(playlist_id) => {
+                match fetcher::ids::user_playlist_id(playlist_id) {
+                    Some(id) => id,
+                    None => return,
+                }
+            }
+            _ => return,
+        };
+        let index = match self.musicbar.1.selected() {
+            Some(index) if index < self.musicbar.0.len() => index,
+            _ => return,
+        };
+        let music_id = self.musicbar.0[index].id.clone();
+
+        let res = fetcher::user_playlist::swap_with_neighbour(id, &music_id, up);
+        let neighbour = match res {
+            Ok(Some(neighbour)) => neighbour,
+            Ok(None) => {
+                self.status = "Can't move..";
+                return;
+            }
+            Err(_) => {
+                self.status = "Err moving..";
+                return;
+            }
+        };
+
+        let neighbour_index = if up {
+            index.checked_sub(1)
+        } else {
+            Some(index + 1).filter(|next| *next < self.musicbar.0.len())
+        };
+        match neighbour_index {
+            Some(neighbour_index) if self.musicbar.0[neighbour_index].id == neighbour.id => {
+                self.musicbar.0.swap(index, neighbour_index);
+                self.musicbar.1.select(Some(neighbour_index));
+            }
+            _ => self.musicbar.0[index] = neighbour,
+        }
+        self.status = "Moved..";
+    }
+
+    pub fn user_playlist_music(&self, user_playlist_id: i64) -> Vec<fetcher::MusicUnit> {
+        fetcher::user_playlist::musics(user_playlist_id).unwrap_or_default()
+    }
+
+    // Unlike youtube playlist, user playlist cannot be given to mpv as a single url.
+    // So every music of playlist is loaded one by one
+    fn play_user_playlist(&mut self, user_playlist_id: i64) {
+        let musics = self.user_playlist_music(user_playlist_id);
+        let first = match musics.first() {
+            Some(music) => music,
+            None => {
+                self.status = "Empty playlist..";
+                return;
+            }
+        };
+
+        match self.player.load(&fetcher::library::music_url(&first.id)) {
+            Ok(_) => {
+                self.player.unpause().ok();
+                self.bottom.music_duration = Duration::from_secs(0);
+                self.bottom.music_elapse = Duration::from_secs(0);
+                self.status = "Playing..";
+                self.bottom.playing = Some((String::new(), true));
+            }
+            Err(_) => {
+                self.status = "Playback error..";
+                return;
+            }
+        }
+
+        for music in musics.iter() {
+            self.remember_title(music);
+        }
+        for music in musics.iter().skip(1) {
+            self.player
+                .append(&fetcher::library::music_url(&music.id), false)
+                .ok();
+        }
+    }
+}
+
+// Queue is the playlist of mpv itself. Every change is done by asking mpv and queue shown in ui
+// is then read back from mpv. This way queue is always in sync even after mpv reorder
+// things by itself. eg: when shuffled
+impl ui::State<'_> {
+    pub fn remember_title(&mut self, music: &fetcher::MusicUnit) {
+        // Music added only by it's id have no title. Let mpv find it
+        if music.name.is_empty() {
+            return;
+        }
+        self.queue_titles.insert(
+            fetcher::library::music_url(&music.id),
+            format!("{} - {}", music.name, music.artist),
+        );
+    }
+
+    pub fn refresh_queue(&mut self) {
+        let queue = self.player.queue();
+        let playing_index = self.player.queue_position();
+        self.show_queue(queue, playing_index);
+    }
+
+    // Show queue read from player. See `ui::playback::observe_once`
+    pub fn show_queue(&mut self, queue: Vec<ui::player::QueueEntry>, playing_index: Option<usize>) {
+        let queue = queue
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                // Player knows the title of music when whole playlist is loaded from youtube.
+                // Otherwise look for title we remembered while appending
+                let title = entry
+                    .title
+                    .or_else(|| self.queue_titles.get(&entry.url).cloned())
+                    .unwrap_or_else(|| entry.url.clone());
+                ui::QueueItem {
+                    title,
+                    filename: entry.url,
+                    current: Some(index) == playing_index,
+                }
+            })
+            .collect();
+        self.queue.0 = queue;
+
+        // Titles are only needed while music is in queue
+        let queued: std::collections::HashSet<&str> = self
+            .queue
+            .0
+            .iter()
+            .map(|item| item.filename.as_str())
+            .collect();
+        self.queue_titles
+            .retain(|url, _| queued.contains(url.as_str()));
+
+        // Queue might have been shrinked by mpv itself
+        if let Some(selected) = self.queue.1.selected() {
+            if selected >= self.queue.0.len() {
+                self.queue.1.select(self.queue.0.len().checked_sub(1));
+            }
+        }
+    }
+
+    // Add musics to queue without replacing what is being played. If nothing is being played,
+    // first of the musics starts playing
+    pub fn enqueue_musics(&mut self, musics: &[fetcher::MusicUnit], mode: ui::QueueMode) {
+        let playing_index = self.player.queue_position();
+
+        for (offset, music) in musics.iter().enumerate() {
+            self.remember_title(music);
+            let res = self
+                .player
+                .append(&fetcher::library::music_url(&music.id), true);
+            if res.is_err() {
+                self.status = "Queue error..";
+                return;
+            }
+
+            // Player can only append. So move appended music next to the music being played,
+            // keeping the order of musics themselves
+            if let (ui::QueueMode::Next, Some(playing_index)) = (mode, playing_index) {
+                let appended_index = self.player.queue_length().saturating_sub(1);
+                let target_index = playing_index + 1 + offset;
+                if appended_index != target_index {
+                    self.player.move_index(appended_index, target_index).ok();
+                }
+            }
+        }
+
+        if self.bottom.playing.is_none() {
+            self.player.unpause().ok();
+            self.bottom.playing = Some((String::new(), true));
+        }
+        self.status = match mode {
+            ui::QueueMode::Append => "Queued..",
+            ui::QueueMode::Next => "Playing next..",
+        };
+        self.refresh_queue();
+    }
+
+    pub fn play_from_queue(&mut self) {
+        let selected = self.queue.1.selected();
+        if let Some(index) = selected.filter(|index| *index < self.queue.0.len()) {
+            self.play_queue_index(index);
+        }
+    }
+
+    // Play given music right away. Rest of the queue is kept as it is
+    pub fn play_now(&mut self, music: &fetcher::MusicUnit) {
+        let playing_index = self.player.queue_position();
+        self.enqueue_musics(std::slice::from_ref(music), ui::QueueMode::Next);
+        // If nothing was being played, music is already started by enqueue
+        if let Some(playing_index) = playing_index {
+            self.play_queue_index(playing_index + 1);
+        }
+    }
+
+    pub fn play_queue_index(&mut self, index: usize) {
+        match self.player.play_index(index) {
+            Ok(_) => {
+                self.player.unpause().ok();
+                self.bottom.music_duration = Duration::from_secs(0);
+                self.bottom.music_elapse = Duration::from_secs(0);
+                self.status = "Playing..";
+                self.bottom.playing = Some((String::new(), true));
+            }
+            Err(_) => self.status = "Playback error..",
+        }
+        self.refresh_queue();
+    }
+
+    pub fn remove_from_queue(&mut self) {
+        let index = match self.queue.1.selected() {
+            Some(index) if index < self.queue.0.len() => index,
+            _ => return,
+        };
+
+        match self.player.remove_index(index) {
+            Ok(_) => self.status = "Removed..",
+            Err(_) => self.status = "Queue error..",
+        }
+        self.refresh_queue();
+    }
+
+    // Swap the focused item of queue with the item above/below it
+    pub fn move_in_queue(&mut self, up: bool) {
+        let index = match self.queue.1.selected() {
+            Some(index) if index < self.queue.0.len() => index,
+            _ => return,
+        };
+
+        // playlist-move puts the item in place of target item, pushing the target item down.
+        // So moving down one step means putting it in place of item which is two step below
+        let (target, new_index) = if up {
+            match index.checked_sub(1) {
+                Some(target) => (target, target),
+                None => {
+                    self.status = "Can't move..";
+                    return;
+                }
+            }
+        } else if index + 1 < self.queue.0.len() {
+            (index + 2, index + 1)
+        } else {
+            self.status = "Can't move..";
+            return;
+        };
+
+        match self.player.move_index(index, target) {
+            Ok(_) => {
+                self.queue.1.select(Some(new_index));
+                self.status = "Moved..";
+            }
+            Err(_) => self.status = "Queue error..",
+        }
+        self.refresh_queue();
+    }
+
+    // Move the focused item of queue right after the music which is being played
+    pub fn play_next_from_queue(&mut self) {
+        let index = match self.queue.1.selected() {
+            Some(index) if index < self.queue.0.len() => index,
+            _ => return,
+        };
+        let playing_index = match self.queue.0.iter().position(|item| item.current) {
+            Some(playing_index) if playing_index != index => playing_index,
+            _ => return,
+        };
+
+        match self.player.move_index(index, playing_index + 1) {
+            Ok(_) => {
+                // When item from above is moved, currently playing music is pushed up
+                let new_index = if index > playing_index {
+                    playing_index + 1
+                } else {
+                    playing_index
+                };
+                self.queue.1.select(Some(new_index));
+                self.status = "Playing next..";
+            }
+            Err(_) => self.status = "Queue error..",
+        }
+        self.refresh_queue();
+    }
+}
+
+impl ui::State<'_> {
+    pub fn save_session(&mut self) {
+        let session_path = match config::ConfigContainer::get_session_path() {
+            Some(path) => path,
+            None => return,
+        };
+
+        self.refresh_queue();
+        let session = ui::Session {
+            queue: self.queue.0.clone(),
+            elapsed: self.player.elapsed().unwrap_or_default(),
+            volume: self.playback_behaviour.volume,
+            shuffle: self.playback_behaviour.shuffle,
+            repeat: self.playback_behaviour.repeat,
+            filled_source: self.filled_source.clone(),
+        };
+
+        // Terminal is not restored yet so errors are written to log file instead of stderr
+        let res = match serde_json::to_string_pretty(&session) {
+            Ok(content) => std::fs::write(&session_path, content).map_err(|err| {
+                format!(
+                    "Cannot write session to {path}. Error: {err}",
+                    path = session_path.to_string_lossy(),
+                    err = err
+                )
+            }),
+            Err(err) => Err(format!("Cannot encode session. Error: {}", err)),
+        };
+        if let Err(err) = res {
+            crate::communicator::log_error(&err);
+            self.status = "Err saving session..";
+        }
+    }
+
+    // Invalid or missing session file is simply ignored and app starts as fresh
+    pub fn restore_session(&mut self) {
+        let session: ui::Session = match config::ConfigContainer::get_session_path()
+            .and_then(|path| std::fs::File::open(path).ok())
+            .and_then(|file| serde_json::from_reader(std::io::BufReader::new(file)).ok())
+        {
+            Some(session) => session,
+            None => return,
+        };
+
+        if let Some(volume) = self.player.set_volume(session.volume) {
+            self.playback_behaviour.volume = volume;
+        }
+        if !session.repeat {
+            self.player.repeat_nothing();
+            self.player.repeat_one();
+            self.playback_behaviour.repeat = false;
+        }
+        // Queue was saved in shuffled order itself so there is no need to shuffle again
+        self.playback_behaviour.shuffle = session.shuffle;
+        self.filled_source = session.filled_source;
+
+        let playing_index = session.queue.iter().position(|item| item.current);
+        for item in session.queue {
+            self.player.append(&item.filename, false).ok();
+            self.queue_titles.insert(item.filename, item.title);
+        }
+
+        if let Some(index) = playing_index {
+            if self.player.play_index(index).is_ok() {
+                self.player.unpause().ok();
+                self.bottom.playing = Some((String::new(), true));
+                self.resume_at = Some(session.elapsed);
+                self.status = "Resuming..";
+            }
+        }
+        self.refresh_queue();
+    }
+}
+
+// Downloads are only queued here. They are then run by downloader. See downloader.rs
+impl ui::State<'_> {
+    pub fn queue_download(
+        &mut self,
+        url: String,
+        music: Option<fetcher::MusicUnit>,
+        playlist: Option<fetcher::PlaylistUnit>,
+    ) {
+        let is_pending = self.downloads.0.iter().any(|download| {
+            download.url == url
+                && matches!(
+                    download.status,
+                    ui::DownloadStatus::Queued | ui::DownloadStatus::Running(_)
+                )
+        });
+        if is_pending {
+            self.status = "Already queued..";
+            return;
+        }
+
+        let title = match (&music, &playlist) {
+            (Some(music), _) => music.name.clone(),
+            (None, Some(playlist)) => playlist.name.clone(),
+            (None, None) => url.clone(),
+        };
+        self.downloads.0.push(ui::DownloadItem {
+            url,
+            title,
+            status: ui::DownloadStatus::Queued,
+            music,
+            playlist,
+        });
+        self.status = "Download queued..";
+    }
+
+    // true if some download is running or waiting to be run
+    pub fn has_pending_download(&self) -> bool {
+        self.downloads.0.iter().any(|download| {
+            matches!(
+                download.status,
+                ui::DownloadStatus::Queued | ui::DownloadStatus::Running(_)
+            )
+        })
+    }
+
+    // Cancel the focused download if it is not finished yet. Otherwise remove it from list
+    pub fn cancel_download(&mut self) {
+        let index = match self.downloads.1.selected() {
+            Some(index) if index < self.downloads.0.len() => index,
+            _ => return,
+        };
+
+        let download = &mut self.downloads.0[index];
+        match download.status {
+            ui::DownloadStatus::Queued | ui::DownloadStatus::Running(_) => {
+                // Downloader notice this and kill the running download
+                download.status = ui::DownloadStatus::Cancelled;
+                self.status = "Cancelled..";
+            }
+            _ => {
+                self.downloads.0.remove(index);
+                self.downloads.1.select(None);
+                self.status = "Removed..";
+            }
+        }
+    }
+
+    pub fn retry_download(&mut self) {
+        let index = match self.downloads.1.selected() {
+            Some(index) if index < self.downloads.0.len() => index,
+            _ => return,
+        };
+
+        let download = &mut self.downloads.0[index];
+        match download.status {
+            ui::DownloadStatus::Failed(_) | ui::DownloadStatus::Cancelled => {
+                download.status = ui::DownloadStatus::Queued;
+                self.status = "Download queued..";
+            }
+            _ => self.status = "Nothing to retry..",
+        }
+    }
+
+    pub fn add_download_to_history(&self, download: &ui::DownloadItem, path: &str) {
         let query = format!(
             "
-            INSERT OR REPLACE INTO
-            {tb_name}
-            (id, name, count)
+            INSERT INTO {tb_name}
+            (url, title, path, finished_at)
             VALUES
-            (:id, :name, :count)
+            (:url, :title, :path, CAST(strftime('%s', 'now') AS INTEGER))
+            ON CONFLICT(url) DO UPDATE SET
+            title=excluded.title, path=excluded.path, finished_at=excluded.finished_at
         ",
-            tb_name = TB_FAVOURATES_ARTIST
+            tb_name = TB_DOWNLOAD_HISTORY
         );
+        let args = rusqlite::named_params! {
+            ":url": download.url,
+            ":title": download.title,
+            ":path": path,
+        };
 
-        let args = [
-            (":id", &artist.id),
-            (":name", &artist.name),
-            (":count", &artist.video_count),
-        ];
+        STORAGE.lock().unwrap().execute(&query, args).ok();
+    }
 
-        let res = STORAGE.lock().unwrap().execute(&query, &args);
-        if res.is_ok() {
-            self.status = "Added..";
-        } else {
-            self.status = "Err adding..";
+    // Fill `State::downloaded` from the downloaded_music table. Called once on startup
+    pub fn load_downloads_index(&mut self) {
+        let query = format!(
+            "
+            SELECT id, path
+            FROM {tb_name}
+        ",
+            tb_name = TB_DOWNLOADED_MUSIC
+        );
+
+        let conn = STORAGE.lock().unwrap();
+        let mut stmt = match conn.prepare(&query) {
+            Ok(stmt) => stmt,
+            Err(_) => return,
+        };
+        let res = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)));
+        if let Ok(downloaded) = res {
+            self.downloaded = downloaded.filter_map(|item| item.ok()).collect();
         }
     }
 
-    pub fn add_music_to_favourates(&mut self, music: &fetcher::MusicUnit) {
+    pub fn add_to_downloads_index(
+        &mut self,
+        conn: &rusqlite::Connection,
+        music_id: &str,
+        path: &str,
+    ) {
         let query = format!(
             "
-                INSERT OR REPLACE INTO 
-                {tb_name} 
-                (id, title, author, duration) 
-                VALUES
-                (:id, :title, :author, :duration)
-            ",
-            tb_name = TB_FAVOURATES_MUSIC
+            INSERT INTO {tb_name}
+            (id, path, downloaded_at)
+            VALUES
+            (:id, :path, CAST(strftime('%s', 'now') AS INTEGER))
+            ON CONFLICT(id) DO UPDATE SET
+            path=excluded.path, downloaded_at=excluded.downloaded_at
+        ",
+            tb_name = TB_DOWNLOADED_MUSIC
         );
+        let args = rusqlite::named_params! {
+            ":id": music_id,
+            ":path": path,
+        };
 
-        let args = [
-            (":id", &music.id),
-            (":title", &music.name),
-            (":author", &music.artist),
-            (":duration", &music.duration),
-        ];
+        conn.execute(&query, args).ok();
+        self.downloaded
+            .insert(music_id.to_string(), path.to_string());
+    }
 
-        let res = STORAGE.lock().unwrap().execute(&query, &args);
-        if res.is_ok() {
-            self.status = "Added...";
-        } else {
-            self.status = "Err adding..";
+    // true if music was downloaded before and the file is still there
+    pub fn is_downloaded(&self, music_id: &str) -> bool {
+        match self.downloaded.get(music_id) {
+            Some(path) => std::path::Path::new(path).exists(),
+            None => false,
         }
     }
 
-    pub fn add_playlist_to_favourates(&mut self, playlist: &fetcher::PlaylistUnit) {
+    // If user have deleted or moved the downloaded file of any of these music, it is forgotten
+    // so that it can be downloaded again
+    pub fn forget_missing_downloads(
+        &mut self,
+        conn: &rusqlite::Connection,
+        musics: &[fetcher::MusicUnit],
+    ) {
         let query = format!(
             "
-            INSERT OR REPLACE INTO {tb_name}
-            (id, name, author, count)
-            VALUES (:id, :name, :author, :count);
+            DELETE FROM {tb_name}
+            WHERE id = :id
         ",
-            tb_name = TB_FAVOURATES_PLAYLIST
+            tb_name = TB_DOWNLOADED_MUSIC
         );
+        for music in musics {
+            if self.downloaded.contains_key(&music.id) && !self.is_downloaded(&music.id) {
+                conn.execute(&query, &[(":id", &music.id)]).ok();
+                self.downloaded.remove(&music.id);
+            }
+        }
+    }
 
-        let args = [
-            (":id", &playlist.id),
-            (":name", &playlist.name),
-            (":author", &playlist.author),
-            (":count", &playlist.video_count),
-        ];
+    // Queue download of those music of playlist that are not downloaded yet. Playlist is then
+    // remembered so that it can be synced again later
+    pub fn sync_playlist_downloads(
+        &mut self,
+        conn: &rusqlite::Connection,
+        playlist: &fetcher::PlaylistUnit,
+        musics: Vec<fetcher::MusicUnit>,
+    ) {
+        let query = format!(
+            "
+            INSERT INTO {tb_name}
+            (id, name, author, count, synced_at)
+            VALUES
+            (:id, :name, :author, :count, CAST(strftime('%s', 'now') AS INTEGER))
+            ON CONFLICT(id) DO UPDATE SET
+            name=excluded.name, author=excluded.author, count=excluded.count,
+            synced_at=excluded.synced_at
+        ",
+            tb_name = TB_DOWNLOADED_PLAYLIST
+        );
+        let args = rusqlite::named_params! {
+            ":id": playlist.id,
+            ":name": playlist.name,
+            ":author": playlist.author,
+            ":count": playlist.video_count,
+        };
+        conn.execute(&query, args).ok();
 
-        let res = STORAGE.lock().unwrap().execute(&query, &args);
-        if res.is_ok() {
-            self.status = "Added...";
-        } else {
-            self.status = "Err adding..";
+        self.forget_missing_downloads(conn, &musics);
+        let new_musics: Vec<fetcher::MusicUnit> = musics
+            .into_iter()
+            .filter(|music| {
+                !fetcher::library::is_local(&music.id) && !self.is_downloaded(&music.id)
+            })
+            .collect();
+        if new_musics.is_empty() {
+            self.status = "Nothing new..";
+            return;
+        }
+        for music in new_musics {
+            self.queue_download(
+                format!("https://www.youtube.com/watch?v={}", music.id),
+                Some(music),
+                Some(playlist.clone()),
+            );
+        }
+    }
+
+    // Every playlist that was downloaded before. Used to sync all of them at once
+    pub fn downloaded_playlists(&self) -> Vec<fetcher::PlaylistUnit> {
+        let query = format!(
+            "
+            SELECT id, name, author, count
+            FROM {tb_name}
+            ORDER BY synced_at DESC
+        ",
+            tb_name = TB_DOWNLOADED_PLAYLIST
+        );
+
+        let conn = STORAGE.lock().unwrap();
+        let mut stmt = match conn.prepare(&query) {
+            Ok(stmt) => stmt,
+            Err(_) => return Vec::new(),
+        };
+        let res = stmt.query_map([], |row| {
+            Ok(fetcher::PlaylistUnit {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                author: row.get(2)?,
+                video_count: row.get(3)?,
+            })
+        });
+        match res {
+            Ok(playlists) => playlists.filter_map(|playlist| playlist.ok()).collect(),
+            Err(_) => Vec::new(),
         }
     }
 }
@@ -892,11 +1613,13 @@ impl ui::Window {
     pub fn next(&self) -> ui::Window {
         match self {
             ui::Window::Sidebar => ui::Window::Musicbar,
-            ui::Window::Musicbar => ui::Window::Playlistbar,
+            ui::Window::Musicbar => ui::Window::Queue,
+            ui::Window::Queue => ui::Window::Playlistbar,
             ui::Window::Playlistbar => ui::Window::Artistbar,
             ui::Window::Searchbar
             | ui::Window::Artistbar
             | ui::Window::BottomControl
+            | ui::Window::Downloads
             | ui::Window::Popup(..) => ui::Window::Sidebar,
             ui::Window::None => unreachable!(),
         }
@@ -905,11 +1628,13 @@ impl ui::Window {
     pub fn prev(&self) -> ui::Window {
         match self {
             ui::Window::Artistbar => ui::Window::Playlistbar,
-            ui::Window::Playlistbar => ui::Window::Musicbar,
+            ui::Window::Playlistbar => ui::Window::Queue,
+            ui::Window::Queue => ui::Window::Musicbar,
             ui::Window::Musicbar => ui::Window::Sidebar,
             ui::Window::Searchbar
             | ui::Window::Sidebar
             | ui::Window::BottomControl
+            | ui::Window::Downloads
             | ui::Window::Popup(..) => ui::Window::Artistbar,
             ui::Window::None => unreachable!(),
         }
@@ -925,8 +1650,169 @@ impl std::convert::TryFrom<usize> for ui::SidebarOption {
             2 => Ok(ui::SidebarOption::Liked),
             3 => Ok(ui::SidebarOption::Saved),
             4 => Ok(ui::SidebarOption::Following),
-            5 => Ok(ui::SidebarOption::Search),
+            5 => Ok(ui::SidebarOption::RecentlyPlayed),
+            6 => Ok(ui::SidebarOption::LocalLibrary),
+            7 => Ok(ui::SidebarOption::Search),
             _ => Err("No sidebar option found corresponding to this usize"),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ui::player::{FakePlayer, Player};
+    use config::ConfigContainer;
+    use fetcher::test_music;
+
+    fn fake_state() -> (ui::State<'static>, Arc<FakePlayer>) {
+        let player = Arc::new(FakePlayer::default());
+        (ui::State::with_player(Arc::clone(&player) as _), player)
+    }
+
+    fn queued(state: &ui::State) -> Vec<String> {
+        state
+            .queue
+            .0
+            .iter()
+            .map(|item| item.title.replace("Title ", "").replace(" - Artist", ""))
+            .collect()
+    }
+
+    #[test]
+    fn queue_flow() {
+        let (mut state, player) = fake_state();
+
+        state.enqueue_musics(&[test_music("a"), test_music("b")], ui::QueueMode::Append);
+        assert_eq!(queued(&state), ["a", "b"]);
+        assert!(state.queue.0[0].current);
+        assert_eq!(state.bottom.playing, Some((String::new(), true)));
+        assert_eq!(
+            player.state.lock().unwrap().queue[0],
+            "https://www.youtube.com/watch?v=a"
+        );
+
+        state.enqueue_musics(&[test_music("c"), test_music("d")], ui::QueueMode::Next);
+        assert_eq!(queued(&state), ["a", "c", "d", "b"]);
+        assert_eq!(state.status, "Playing next..");
+
+        state.play_now(&test_music("e"));
+        assert_eq!(queued(&state), ["a", "e", "c", "d", "b"]);
+        assert_eq!(player.queue_position(), Some(1));
+
+        state.queue.1.select(Some(4));
+        state.play_next_from_queue();
+        assert_eq!(queued(&state), ["a", "e", "b", "c", "d"]);
+        assert_eq!(state.queue.1.selected(), Some(2));
+
+        state.move_in_queue(true);
+        assert_eq!(queued(&state), ["a", "b", "e", "c", "d"]);
+        assert_eq!(state.queue.1.selected(), Some(1));
+        assert!(state.queue.0[2].current);
+
+        state.remove_from_queue();
+        assert_eq!(queued(&state), ["a", "e", "c", "d"]);
+        // Title of removed music is forgotten
+        assert_eq!(state.queue_titles.len(), 4);
+        assert!(!state
+            .queue_titles
+            .contains_key("https://www.youtube.com/watch?v=b"));
+        state.queue.1.select(Some(3));
+        state.move_in_queue(false);
+        assert_eq!(state.status, "Can't move..");
+    }
+
+    #[test]
+    fn playback_behaviour() {
+        let (mut state, player) = fake_state();
+        assert!(player.state.lock().unwrap().loop_playlist);
+        assert_eq!(player.state.lock().unwrap().volume, 100);
+
+        // Nothing to pause
+        state.toggle_pause();
+        assert!(!player.state.lock().unwrap().paused);
+
+        state.enqueue_musics(&[test_music("a")], ui::QueueMode::Append);
+        state.toggle_pause();
+        assert!(player.state.lock().unwrap().paused);
+        assert!(state.player_status().paused);
+        state.set_paused(true);
+        assert!(player.state.lock().unwrap().paused);
+        state.set_paused(false);
+        assert!(!player.state.lock().unwrap().paused);
+
+        state.set_repeat(false);
+        assert!(player.state.lock().unwrap().loop_file);
+        assert!(!player.state.lock().unwrap().loop_playlist);
+        state.set_shuffle(true);
+        assert!(player.state.lock().unwrap().shuffled);
+        let status = state.player_status();
+        assert!(status.shuffle && !status.repeat_queue);
+    }
+
+    fn downloads_index(conn: &rusqlite::Connection) -> Vec<String> {
+        let query = format!("SELECT id FROM {} ORDER BY id", TB_DOWNLOADED_MUSIC);
+        let mut stmt = conn.prepare(&query).unwrap();
+        let rows = stmt.query_map([], |row| row.get(0)).unwrap();
+        rows.map(Result::unwrap).collect()
+    }
+
+    fn queued_downloads(state: &ui::State) -> Vec<String> {
+        state
+            .downloads
+            .0
+            .iter()
+            .map(|download| download.url.replace("https://www.youtube.com/watch?v=", ""))
+            .collect()
+    }
+
+    #[test]
+    fn sync_only_new_downloads() {
+        let dir = std::env::temp_dir().join("ytui_utils_downloads");
+        std::fs::remove_dir_all(&dir).ok();
+        std::fs::create_dir_all(&dir).unwrap();
+        let kept = dir.join("kept.mp3").to_string_lossy().to_string();
+        let deleted = dir.join("deleted.mp3").to_string_lossy().to_string();
+        std::fs::write(&kept, []).unwrap();
+
+        let conn = ConfigContainer::test_storage();
+        let (mut state, _) = fake_state();
+        state.add_to_downloads_index(&conn, "kept", &kept);
+        state.add_to_downloads_index(&conn, "deleted", &deleted);
+        assert!(state.is_downloaded("kept"));
+        assert!(!state.is_downloaded("deleted"));
+        assert!(!state.is_downloaded("new"));
+        // Asking does not forget anything
+        assert_eq!(downloads_index(&conn), ["deleted", "kept"]);
+
+        let playlist = fetcher::PlaylistUnit {
+            name: String::from("Playlist"),
+            id: String::from("PLx"),
+            author: String::from("Author"),
+            video_count: String::from("3"),
+        };
+        let musics = vec![test_music("kept"), test_music("deleted"), test_music("new")];
+        state.sync_playlist_downloads(&conn, &playlist, musics.clone());
+        assert_eq!(queued_downloads(&state), ["deleted", "new"]);
+        assert_eq!(downloads_index(&conn), ["kept"]);
+        assert!(!state.downloaded.contains_key("deleted"));
+        let synced: String = conn
+            .query_row(
+                &format!("SELECT name FROM {}", TB_DOWNLOADED_PLAYLIST),
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(synced, "Playlist");
+
+        // Once finished, syncing again finds nothing new
+        state.downloads.0.clear();
+        state.add_to_downloads_index(&conn, "deleted", &kept);
+        state.add_to_downloads_index(&conn, "new", &kept);
+        state.sync_playlist_downloads(&conn, &playlist, musics);
+        assert!(state.downloads.0.is_empty());
+        assert_eq!(state.status, "Nothing new..");
+
+        std::fs::remove_dir_all(&dir).ok();
+    }
+}